@@ -0,0 +1,213 @@
+//! Running commands from a batch file and checking `assert-*` results.
+
+use serde_json::{json, Value};
+use std::fs;
+
+use crate::connection::Response;
+use crate::fanout::collect_errors;
+
+/// Split a batch line into arguments, honoring single and double quotes.
+pub fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Parse batch file contents into `(line number, args)` pairs, skipping blank
+/// lines and `#` comments. A leading `agent-browser` on a line is ignored.
+pub fn parse_batch(content: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut commands = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut args = split_line(trimmed).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if args.first().map(String::as_str) == Some("agent-browser") {
+            args.remove(0);
+        }
+        if !args.is_empty() {
+            commands.push((i + 1, args));
+        }
+    }
+    Ok(commands)
+}
+
+pub fn read_batch(path: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    parse_batch(&content)
+}
+
+/// Selectors from `--selector-file` contents: one per line, skipping blank
+/// lines and comments. A comment is `#` followed by whitespace, so id
+/// selectors like `#login` are kept.
+pub fn parse_selectors(content: &str) -> Vec<String> {
+    let is_comment = |line: &str| {
+        line.strip_prefix('#')
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    };
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !is_comment(line))
+        .map(String::from)
+        .collect()
+}
+
+pub fn read_selector_file(path: &str) -> Result<Vec<String>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let selectors = parse_selectors(&content);
+    if selectors.is_empty() {
+        return Err(format!("{} contains no selectors", path));
+    }
+    Ok(selectors)
+}
+
+/// The `--json` report for a `--selector-file` run: one result per selector,
+/// plus an `errors` list for the selectors that failed.
+pub fn selector_report(selectors: &[String], responses: &[Response]) -> Value {
+    let results: Vec<Value> = selectors
+        .iter()
+        .zip(responses)
+        .map(|(selector, r)| json!({ "selector": selector, "success": r.success, "data": r.data, "error": r.error }))
+        .collect();
+    let success = responses.iter().all(|r| r.success);
+    let mut report = json!({ "success": success, "data": { "results": results } });
+    let errors = collect_errors("selector", selectors.iter().map(|s| json!(s)), responses);
+    if !errors.is_empty() {
+        report["errors"] = json!(errors);
+    }
+    report
+}
+
+/// Decide whether a command passed. Commands with an `assert` spec compare the
+/// reply's `field` against `expected`; others pass when the daemon succeeded.
+pub fn check_result(cmd: &Value, resp: &Response) -> Result<(), String> {
+    if !resp.success {
+        return Err(resp.error.clone().unwrap_or_else(|| "Unknown error".to_string()));
+    }
+    let Some(spec) = cmd.get("assert") else {
+        return Ok(());
+    };
+    let field = spec["field"].as_str().unwrap_or("");
+    let expected = &spec["expected"];
+    let actual = resp.data.as_ref().and_then(|d| d.get(field)).unwrap_or(&Value::Null);
+    let passed = match (spec["match"].as_str(), actual.as_str(), expected.as_str()) {
+        (Some("contains"), Some(actual), Some(expected)) => actual.contains(expected),
+        _ => actual == expected,
+    };
+    if passed {
+        Ok(())
+    } else {
+        let verb = if spec["match"] == "contains" { "contain" } else { "be" };
+        Err(format!("expected {} to {} {}, got {}", field, verb, expected, actual))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(data: Value) -> Response {
+        Response {
+            success: true,
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_line_quotes() {
+        assert_eq!(split_line(r#"assert-text h1 "Hello world""#).unwrap(), vec!["assert-text", "h1", "Hello world"]);
+        assert_eq!(split_line("fill '#q' ''").unwrap(), vec!["fill", "#q", ""]);
+        assert!(split_line("fill '#q").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch_skips_comments() {
+        let content = "# login checks\nopen example.com\n\n  agent-browser assert-url example.com\n";
+        let commands = parse_batch(content).unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0], (2, vec!["open".to_string(), "example.com".to_string()]));
+        assert_eq!(commands[1].0, 4);
+        assert_eq!(commands[1].1[0], "assert-url");
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        let content = "# nav links\nnav a\n\n  #login  \n[data-test=\"save\"]\n";
+        assert_eq!(parse_selectors(content), vec!["nav a", "#login", r#"[data-test="save"]"#]);
+        assert!(parse_selectors("# only comments\n#\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_selector_report() {
+        let selectors = vec!["#a".to_string(), "#missing".to_string()];
+        let responses = vec![
+            ok(json!({ "count": 2 })),
+            Response {
+                success: false,
+                error: Some("Element not found: #missing".to_string()),
+                ..Default::default()
+            },
+        ];
+        let report = selector_report(&selectors, &responses);
+        assert_eq!(report["success"], false);
+        assert_eq!(report["data"]["results"][0], json!({ "selector": "#a", "success": true, "data": { "count": 2 }, "error": null }));
+        assert_eq!(report["data"]["results"][1]["selector"], "#missing");
+        assert_eq!(report["errors"][0]["selector"], "#missing");
+        assert_eq!(report["errors"][0]["errorCode"], "element_not_found");
+
+        let report = selector_report(&selectors[..1], &responses[..1]);
+        assert_eq!(report["success"], true);
+        assert!(report.get("errors").is_none());
+    }
+
+    #[test]
+    fn test_check_result() {
+        let cmd = json!({ "action": "url", "assert": { "field": "url", "expected": "/home", "match": "contains" } });
+        assert!(check_result(&cmd, &ok(json!({ "url": "https://x.test/home" }))).is_ok());
+        let err = check_result(&cmd, &ok(json!({ "url": "https://x.test/login" }))).unwrap_err();
+        assert_eq!(err, r#"expected url to contain "/home", got "https://x.test/login""#);
+
+        let cmd = json!({ "action": "count", "assert": { "field": "count", "expected": 3, "match": "equals" } });
+        assert!(check_result(&cmd, &ok(json!({ "count": 3 }))).is_ok());
+        assert!(check_result(&cmd, &ok(json!({ "count": 2 }))).is_err());
+
+        let failed = Response {
+            success: false,
+            error: Some("boom".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(check_result(&json!({ "action": "click" }), &failed).unwrap_err(), "boom");
+        assert!(check_result(&json!({ "action": "click" }), &ok(json!({}))).is_ok());
+    }
+}
@@ -1,15 +1,62 @@
 //! Color output utilities respecting NO_COLOR environment variable.
 //!
 //! When the NO_COLOR environment variable is present (regardless of value),
-//! all color formatting is disabled per https://no-color.org/
+//! all color formatting is disabled per https://no-color.org/. Color is also
+//! off when stdout or stderr isn't a terminal, unless `--color=always` asks
+//! for it.
 
 use std::env;
+use std::io::{self, IsTerminal};
 use std::sync::OnceLock;
 
-/// Returns true if color output is enabled (NO_COLOR is NOT set)
+/// `--color=always|auto|never`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Always,
+    Auto,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "always" => Ok(ColorMode::Always),
+            "auto" => Ok(ColorMode::Auto),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("Invalid --color '{}': expected always, auto or never", s)),
+        }
+    }
+}
+
+static MODE: OnceLock<ColorMode> = OnceLock::new();
+
+/// Choose the color mode. Call before anything is printed; the decision is
+/// made once, on first use.
+pub fn set_mode(mode: ColorMode) {
+    let _ = MODE.set(mode);
+}
+
+/// Whether to color output. In auto mode, NO_COLOR, a dumb terminal, or
+/// output that isn't a terminal turns it off.
+fn decide(mode: ColorMode, no_color: bool, dumb_term: bool, is_tty: bool) -> bool {
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => !no_color && !dumb_term && is_tty,
+    }
+}
+
+/// Returns true if color output is enabled
 pub fn is_enabled() -> bool {
     static COLORS_ENABLED: OnceLock<bool> = OnceLock::new();
-    *COLORS_ENABLED.get_or_init(|| env::var("NO_COLOR").is_err())
+    *COLORS_ENABLED.get_or_init(|| {
+        decide(
+            MODE.get().copied().unwrap_or(ColorMode::Auto),
+            env::var_os("NO_COLOR").is_some(),
+            env::var("TERM").is_ok_and(|t| t == "dumb"),
+            io::stdout().is_terminal() && io::stderr().is_terminal(),
+        )
+    })
 }
 
 /// Format text in red (errors)
@@ -139,6 +186,26 @@ mod tests {
         assert!(formatted.contains("\x1b[32m"));
     }
 
+    #[test]
+    fn test_color_mode_parse() {
+        assert_eq!(ColorMode::parse("always"), Ok(ColorMode::Always));
+        assert_eq!(ColorMode::parse("auto"), Ok(ColorMode::Auto));
+        assert_eq!(ColorMode::parse("never"), Ok(ColorMode::Never));
+        assert!(ColorMode::parse("yes").unwrap_err().contains("always, auto or never"));
+    }
+
+    #[test]
+    fn test_decide_color() {
+        // Auto: only on a real, capable terminal without NO_COLOR
+        assert!(decide(ColorMode::Auto, false, false, true));
+        assert!(!decide(ColorMode::Auto, true, false, true));
+        assert!(!decide(ColorMode::Auto, false, true, true));
+        assert!(!decide(ColorMode::Auto, false, false, false));
+        // Explicit modes override everything
+        assert!(decide(ColorMode::Always, true, true, false));
+        assert!(!decide(ColorMode::Never, false, false, true));
+    }
+
     #[test]
     fn test_console_level_prefix_contains_level() {
         // Regardless of color state, the level text should be present
@@ -1,6 +1,15 @@
+// Positional arguments read as rest.get(0), rest.get(1), ...
+#![allow(clippy::get_first)]
+
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
 
-use crate::flags::Flags;
+use crate::batch::read_selector_file;
+use crate::connection::read_timeout_from_secs;
+use crate::flags::{parse_key_value, Flags};
+use crate::network::NetworkFilter;
+use crate::parse;
 
 /// Error type for command parsing with contextual information
 #[derive(Debug)]
@@ -20,6 +29,15 @@ pub enum ParseError {
 }
 
 impl ParseError {
+    /// Machine-readable error type for JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::UnknownCommand { .. } => "unknown_command",
+            ParseError::UnknownSubcommand { .. } => "unknown_subcommand",
+            ParseError::MissingArguments { .. } => "missing_arguments",
+        }
+    }
+
     pub fn format(&self) -> String {
         match self {
             ParseError::UnknownCommand { command } => {
@@ -45,6 +63,36 @@ impl ParseError {
     }
 }
 
+/// Default `wait-function` timeout, kept under the 30s socket read timeout so
+/// the daemon reports the timeout rather than the connection.
+pub const DEFAULT_WAIT_FUNCTION_TIMEOUT_MS: u64 = 25_000;
+
+/// How much longer than a command's own `timeout` the CLI waits for the
+/// reply, so the daemon's timeout error arrives before the socket gives up.
+pub const TRANSPORT_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+/// The transport (socket read) timeout for `command`, the name as typed,
+/// sending `cmd`: its `[timeouts.transport]` entry in the config, else that
+/// table's `default`, else `fallback`. It is raised above the semantic
+/// `timeout` (ms) the command carries for the daemon, if any, by
+/// `TRANSPORT_TIMEOUT_GRACE`. `None`, configured as 0, waits indefinitely.
+pub fn transport_timeout(
+    command: &str,
+    cmd: &Value,
+    transport: &HashMap<String, f64>,
+    fallback: Option<Duration>,
+) -> Option<Duration> {
+    let configured = match transport.get(command).or_else(|| transport.get("default")) {
+        Some(&secs) => read_timeout_from_secs(secs),
+        None => fallback,
+    };
+    let semantic = cmd.get("timeout").and_then(Value::as_u64).map(Duration::from_millis);
+    match (configured, semantic) {
+        (Some(t), Some(s)) => Some(t.max(s + TRANSPORT_TIMEOUT_GRACE)),
+        (t, _) => t,
+    }
+}
+
 pub fn gen_id() -> String {
     format!(
         "r{}",
@@ -57,6 +105,78 @@ pub fn gen_id() -> String {
 }
 
 pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError> {
+    match flags.selector_file.as_deref() {
+        Some(path) => parse_bulk(args, flags, path),
+        None => parse_single(args, flags),
+    }
+}
+
+/// Validate a command line for `--parse-only` without contacting the daemon.
+/// Valid commands report the JSON that would be sent; invalid ones report the
+/// `ParseError` with the same `type` used for normal JSON errors.
+pub fn parse_report(args: &[String], flags: &Flags) -> Value {
+    match parse_command(args, flags) {
+        Ok(cmd) => json!({ "success": true, "data": { "valid": true, "command": cmd } }),
+        Err(e) => json!({
+            "success": false,
+            "data": { "valid": false },
+            "error": e.format().replace('\n', " "),
+            "type": e.kind(),
+        }),
+    }
+}
+
+/// Parse a command once per selector in `--selector-file`, as a `bulk` action
+/// that `main` runs over one connection.
+fn parse_bulk(args: &[String], flags: &Flags, path: &str) -> Result<Value, ParseError> {
+    const USAGE: &str = "<command> [args...] --selector-file <file>";
+    let selectors = read_selector_file(path).map_err(|e| ParseError::MissingArguments {
+        context: format!("--selector-file: {}", e),
+        usage: USAGE,
+    })?;
+    if args.is_empty() {
+        return Err(ParseError::MissingArguments {
+            context: "--selector-file".to_string(),
+            usage: USAGE,
+        });
+    }
+    let commands = bulk_commands(args, flags, &selectors)?;
+    Ok(json!({ "id": gen_id(), "action": "bulk", "selectors": selectors, "commands": commands }))
+}
+
+/// One command per selector. The selector goes where the command expects it:
+/// after the subcommand for `get`/`is` (`get text <sel>`), otherwise right
+/// after the command name.
+fn bulk_commands(args: &[String], flags: &Flags, selectors: &[String]) -> Result<Vec<Value>, ParseError> {
+    let at = match args[0].as_str() {
+        "get" | "is" => 2,
+        _ => 1,
+    }
+    .min(args.len());
+    selectors
+        .iter()
+        .map(|selector| {
+            let mut args = args.to_vec();
+            args.insert(at, selector.clone());
+            parse_single(&args, flags)
+        })
+        .collect()
+}
+
+/// Parse one command, scoping it to the `--frame` iframe if it takes a
+/// selector. The daemon matches the frame by iframe selector first, then by
+/// a substring of the frame's URL.
+fn parse_single(args: &[String], flags: &Flags) -> Result<Value, ParseError> {
+    let mut cmd = parse_action(args, flags)?;
+    if let Some(ref frame) = flags.frame {
+        if cmd.get("selector").is_some() {
+            cmd["frame"] = json!(frame);
+        }
+    }
+    Ok(cmd)
+}
+
+fn parse_action(args: &[String], flags: &Flags) -> Result<Value, ParseError> {
     if args.is_empty() {
         return Err(ParseError::MissingArguments {
             context: "".to_string(),
@@ -71,10 +191,12 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
     match cmd {
         // === Navigation ===
         "open" | "goto" | "navigate" => {
-            let url = rest.get(0).ok_or_else(|| ParseError::MissingArguments {
+            let url = rest.iter().find(|a| !a.starts_with("--")).ok_or_else(|| ParseError::MissingArguments {
                 context: cmd.to_string(),
-                usage: "open <url>",
+                usage: "open <url> [--measure] [--summary]",
             })?;
+            let measure = rest.contains(&"--measure");
+            let summary = rest.contains(&"--summary");
             let url_lower = url.to_lowercase();
             let url = if url_lower.starts_with("http://") 
                 || url_lower.starts_with("https://")
@@ -86,17 +208,44 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
                 format!("https://{}", url)
             };
             let mut nav_cmd = json!({ "id": id, "action": "navigate", "url": url });
+            if measure {
+                nav_cmd["measure"] = json!(true);
+            }
+            if summary {
+                nav_cmd["summary"] = json!(true);
+            }
             // If --headers flag is set, include headers (scoped to this origin)
             if let Some(ref headers_json) = flags.headers {
                 if let Ok(headers) = serde_json::from_str::<serde_json::Value>(headers_json) {
                     nav_cmd["headers"] = headers;
                 }
             }
+            // Web storage to seed on the target origin (applied via init script)
+            for (field, flag, pairs) in [
+                ("localStorage", "--set-local-storage", &flags.local_storage),
+                ("sessionStorage", "--set-session-storage", &flags.session_storage),
+            ] {
+                if pairs.is_empty() {
+                    continue;
+                }
+                let mut entries = serde_json::Map::new();
+                for pair in pairs {
+                    let (key, value) = parse_key_value(pair).ok_or_else(|| ParseError::MissingArguments {
+                        context: format!("{}: expected key=value, got '{}'", flag, pair),
+                        usage: "open <url> --set-local-storage <key=value> --set-session-storage <key=value>",
+                    })?;
+                    entries.insert(key.to_string(), json!(value));
+                }
+                nav_cmd[field] = Value::Object(entries);
+            }
             Ok(nav_cmd)
         }
-        "back" => Ok(json!({ "id": id, "action": "back" })),
-        "forward" => Ok(json!({ "id": id, "action": "forward" })),
-        "reload" => Ok(json!({ "id": id, "action": "reload" })),
+        "back" => Ok(json!({ "id": id, "action": "goBack" })),
+        "forward" => Ok(json!({ "id": id, "action": "goForward" })),
+        "reload" => {
+            let hard = rest.contains(&"--hard");
+            Ok(json!({ "id": id, "action": "reload", "hard": hard }))
+        }
 
         // === Core Actions ===
         "click" => {
@@ -281,6 +430,40 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
             }
         }
 
+        // === Wait for a predicate function ===
+        "wait-function" => {
+            const USAGE: &str = "wait-function <js> [--timeout ms]";
+            let mut parts = Vec::new();
+            let mut timeout = DEFAULT_WAIT_FUNCTION_TIMEOUT_MS;
+            let mut i = 0;
+            while i < rest.len() {
+                if rest[i] == "--timeout" {
+                    let ms = rest.get(i + 1).ok_or_else(|| ParseError::MissingArguments {
+                        context: "wait-function --timeout".to_string(),
+                        usage: USAGE,
+                    })?;
+                    timeout = ms.parse::<u64>().ok().filter(|&t| t > 0).ok_or_else(|| {
+                        ParseError::MissingArguments {
+                            context: format!("wait-function: invalid timeout '{}'", ms),
+                            usage: USAGE,
+                        }
+                    })?;
+                    i += 2;
+                    continue;
+                }
+                parts.push(rest[i]);
+                i += 1;
+            }
+            let function = parts.join(" ");
+            if function.trim().is_empty() {
+                return Err(ParseError::MissingArguments {
+                    context: "wait-function".to_string(),
+                    usage: USAGE,
+                });
+            }
+            Ok(json!({ "id": id, "action": "waitforfunction", "expression": function, "timeout": timeout }))
+        }
+
         // === Screenshot/PDF ===
         "screenshot" => {
             let mut cmd = json!({ "id": id, "action": "screenshot", "fullPage": flags.full });
@@ -335,7 +518,52 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         "eval" => Ok(json!({ "id": id, "action": "evaluate", "script": rest.join(" ") })),
 
         // === Close ===
-        "close" | "quit" | "exit" => Ok(json!({ "id": id, "action": "close" })),
+        "close" | "quit" | "exit" => match rest.iter().position(|&a| a == "--timeout") {
+            None => Ok(json!({ "id": id, "action": "close" })),
+            Some(i) => {
+                let ms = rest.get(i + 1).copied().unwrap_or("");
+                let timeout = ms.parse::<u64>().ok().filter(|&t| t > 0).ok_or_else(|| ParseError::MissingArguments {
+                    context: format!("close: invalid timeout '{}'", ms),
+                    usage: "close [--timeout ms]",
+                })?;
+                Ok(json!({ "id": id, "action": "close", "timeout": timeout }))
+            }
+        },
+
+        // === Graceful shutdown (the CLI waits for the daemon to exit) ===
+        "shutdown" => match rest.iter().position(|&a| a == "--timeout") {
+            None => Ok(json!({ "id": id, "action": "shutdown" })),
+            Some(i) => {
+                let ms = rest.get(i + 1).copied().unwrap_or("");
+                let timeout = ms.parse::<u64>().ok().filter(|&t| t > 0).ok_or_else(|| ParseError::MissingArguments {
+                    context: format!("shutdown: invalid timeout '{}'", ms),
+                    usage: "shutdown [--timeout ms]",
+                })?;
+                Ok(json!({ "id": id, "action": "shutdown", "timeout": timeout }))
+            }
+        },
+
+        // === Health check (never spawns a daemon) ===
+        "ping" => Ok(json!({ "id": id, "action": "ping" })),
+
+        // === Launch (pre-warm a session without navigating) ===
+        "launch" | "warmup" => Ok(json!({ "id": id, "action": "launch", "headless": !flags.headed })),
+
+        // === Accessibility tree ===
+        "a11y" | "accessibility" => {
+            let mut a11y_cmd = json!({ "id": id, "action": "a11y_snapshot" });
+            if let Some(idx) = rest.iter().position(|&s| s == "--selector" || s == "-s") {
+                let sel = rest.get(idx + 1).ok_or_else(|| ParseError::MissingArguments {
+                    context: cmd.to_string(),
+                    usage: "a11y [--selector <sel>]",
+                })?;
+                a11y_cmd["selector"] = json!(sel);
+            }
+            Ok(a11y_cmd)
+        }
+
+        // === CDP endpoint (for pointing other CDP tools at this browser) ===
+        "cdp-url" => Ok(json!({ "id": id, "action": "cdp_url" })),
 
         // === Connect (CDP) ===
         "connect" => {
@@ -350,8 +578,23 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
             Ok(json!({ "id": id, "action": "launch", "cdpPort": port }))
         }
 
+        // === Batch (run commands from a file) ===
+        "batch" => {
+            let file = rest.get(0).ok_or_else(|| ParseError::MissingArguments {
+                context: "batch".to_string(),
+                usage: "batch <file>",
+            })?;
+            Ok(json!({ "id": id, "action": "batch", "file": file }))
+        }
+
+        // === Assertions ===
+        "assert-visible" | "assert-text" | "assert-url" | "assert-title" | "assert-count" => {
+            parse_assert(cmd, &rest, &id)
+        }
+
         // === Get ===
         "get" => parse_get(&rest, &id),
+        "count" => parse_count(&rest, &id, "count"),
 
         // === Is (state checks) ===
         "is" => parse_is(&rest, &id),
@@ -365,6 +608,19 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Set (browser settings) ===
         "set" => parse_set(&rest, &id),
 
+        // === Runtime viewport ===
+        "set-viewport" => {
+            let dims = rest.get(0).ok_or_else(|| ParseError::MissingArguments {
+                context: "set-viewport".to_string(),
+                usage: "set-viewport <WIDTHxHEIGHT>",
+            })?;
+            let (w, h) = parse::dimensions(dims).map_err(|e| ParseError::MissingArguments {
+                context: format!("set-viewport: {}", e),
+                usage: "set-viewport <WIDTHxHEIGHT>",
+            })?;
+            Ok(json!({ "id": id, "action": "setViewport", "width": w, "height": h }))
+        }
+
         // === Network ===
         "network" => parse_network(&rest, &id),
 
@@ -393,7 +649,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
 
         // === Tabs ===
         "tab" => {
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("new") => {
                     let mut cmd = json!({ "id": id, "action": "tab_new" });
                     if let Some(url) = rest.get(1) {
@@ -419,7 +675,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Window ===
         "window" => {
             const VALID: &[&str] = &["new"];
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("new") => Ok(json!({ "id": id, "action": "window_new" })),
                 Some(sub) => Err(ParseError::UnknownSubcommand {
                     subcommand: sub.to_string(),
@@ -434,7 +690,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
 
         // === Frame ===
         "frame" => {
-            if rest.get(0).map(|s| *s) == Some("main") {
+            if rest.get(0).copied() == Some("main") {
                 Ok(json!({ "id": id, "action": "mainframe" }))
             } else {
                 let sel = rest.get(0).ok_or_else(|| ParseError::MissingArguments {
@@ -448,7 +704,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Dialog ===
         "dialog" => {
             const VALID: &[&str] = &["accept", "dismiss"];
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("accept") => {
                     let mut cmd = json!({ "id": id, "action": "dialog", "response": "accept" });
                     if let Some(prompt_text) = rest.get(1) {
@@ -471,7 +727,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Debug ===
         "trace" => {
             const VALID: &[&str] = &["start", "stop"];
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("start") => Ok(json!({ "id": id, "action": "trace_start" })),
                 Some("stop") => {
                     let path = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
@@ -494,7 +750,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === Recording (Playwright native video recording) ===
         "record" => {
             const VALID: &[&str] = &["start", "stop", "restart"];
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("start") => {
                     let path = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                         context: "record start".to_string(),
@@ -545,11 +801,11 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
             }
         }
         "console" => {
-            let clear = rest.iter().any(|&s| s == "--clear");
+            let clear = rest.contains(&"--clear");
             Ok(json!({ "id": id, "action": "console", "clear": clear }))
         }
         "errors" => {
-            let clear = rest.iter().any(|&s| s == "--clear");
+            let clear = rest.contains(&"--clear");
             Ok(json!({ "id": id, "action": "errors", "clear": clear }))
         }
         "highlight" => {
@@ -563,7 +819,7 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
         // === State ===
         "state" => {
             const VALID: &[&str] = &["save", "load"];
-            match rest.get(0).map(|s| *s) {
+            match rest.get(0).copied() {
                 Some("save") => {
                     let path = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                         context: "state save".to_string(),
@@ -595,10 +851,72 @@ pub fn parse_command(args: &[String], flags: &Flags) -> Result<Value, ParseError
     }
 }
 
+/// Map an `assert-*` command to the query it checks plus an `assert` spec
+/// (`field` of the reply, `expected` value, `match` mode) evaluated client-side.
+fn parse_assert(cmd: &str, rest: &[&str], id: &str) -> Result<Value, ParseError> {
+    let missing = |usage: &'static str| ParseError::MissingArguments {
+        context: cmd.to_string(),
+        usage,
+    };
+    match cmd {
+        "assert-visible" => {
+            let sel = rest.get(0).ok_or_else(|| missing("assert-visible <selector>"))?;
+            Ok(json!({ "id": id, "action": "isvisible", "selector": sel,
+                "assert": { "field": "visible", "expected": true, "match": "equals" } }))
+        }
+        "assert-text" => {
+            let sel = rest.get(0).ok_or_else(|| missing("assert-text <selector> <text>"))?;
+            if rest.len() < 2 {
+                return Err(missing("assert-text <selector> <text>"));
+            }
+            Ok(json!({ "id": id, "action": "gettext", "selector": sel,
+                "assert": { "field": "text", "expected": rest[1..].join(" "), "match": "contains" } }))
+        }
+        "assert-url" => {
+            if rest.is_empty() {
+                return Err(missing("assert-url <substring>"));
+            }
+            Ok(json!({ "id": id, "action": "url",
+                "assert": { "field": "url", "expected": rest.join(" "), "match": "contains" } }))
+        }
+        "assert-title" => {
+            if rest.is_empty() {
+                return Err(missing("assert-title <title>"));
+            }
+            Ok(json!({ "id": id, "action": "title",
+                "assert": { "field": "title", "expected": rest.join(" "), "match": "equals" } }))
+        }
+        _ => {
+            let usage = "assert-count <selector> <n>";
+            let sel = rest.get(0).ok_or_else(|| missing(usage))?;
+            let n = rest.get(1).ok_or_else(|| missing(usage))?;
+            let n: u64 = n.parse().map_err(|_| ParseError::MissingArguments {
+                context: format!("assert-count: invalid count '{}'", n),
+                usage,
+            })?;
+            Ok(json!({ "id": id, "action": "count", "selector": sel,
+                "assert": { "field": "count", "expected": n, "match": "equals" } }))
+        }
+    }
+}
+
+/// `count <selector> [--highlight]`, shared by `count` and `get count`.
+fn parse_count(rest: &[&str], id: &str, context: &str) -> Result<Value, ParseError> {
+    let sel = rest.iter().find(|a| **a != "--highlight").ok_or_else(|| ParseError::MissingArguments {
+        context: context.to_string(),
+        usage: "count <selector> [--highlight]",
+    })?;
+    let mut cmd = json!({ "id": id, "action": "count", "selector": sel });
+    if rest.contains(&"--highlight") {
+        cmd["highlight"] = json!(true);
+    }
+    Ok(cmd)
+}
+
 fn parse_get(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["text", "html", "value", "attr", "url", "title", "count", "box", "styles"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
         Some("text") => {
             let sel = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "get text".to_string(),
@@ -633,13 +951,7 @@ fn parse_get(rest: &[&str], id: &str) -> Result<Value, ParseError> {
         }
         Some("url") => Ok(json!({ "id": id, "action": "url" })),
         Some("title") => Ok(json!({ "id": id, "action": "title" })),
-        Some("count") => {
-            let sel = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
-                context: "get count".to_string(),
-                usage: "get count <selector>",
-            })?;
-            Ok(json!({ "id": id, "action": "count", "selector": sel }))
-        }
+        Some("count") => parse_count(&rest[1..], id, "get count"),
         Some("box") => {
             let sel = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "get box".to_string(),
@@ -668,7 +980,7 @@ fn parse_get(rest: &[&str], id: &str) -> Result<Value, ParseError> {
 fn parse_is(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["visible", "enabled", "checked"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
         Some("visible") => {
             let sel = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "is visible".to_string(),
@@ -710,8 +1022,8 @@ fn parse_find(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     })?;
     
     let name_idx = rest.iter().position(|&s| s == "--name");
-    let name = name_idx.and_then(|i| rest.get(i + 1).map(|s| *s));
-    let exact = rest.iter().any(|&s| s == "--exact");
+    let name = name_idx.and_then(|i| rest.get(i + 1).copied());
+    let exact = rest.contains(&"--exact");
 
     match *locator {
         "role" | "text" | "label" | "placeholder" | "alt" | "title" | "testid" | "first" | "last" => {
@@ -807,7 +1119,7 @@ fn parse_find(rest: &[&str], id: &str) -> Result<Value, ParseError> {
 fn parse_mouse(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["move", "down", "up", "wheel"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
         Some("move") => {
             let x_str = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "mouse move".to_string(),
@@ -852,7 +1164,7 @@ fn parse_mouse(rest: &[&str], id: &str) -> Result<Value, ParseError> {
 fn parse_set(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["viewport", "device", "geo", "geolocation", "offline", "headers", "credentials", "auth", "media"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
         Some("viewport") => {
             let w_str = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "set viewport".to_string(),
@@ -880,22 +1192,31 @@ fn parse_set(rest: &[&str], id: &str) -> Result<Value, ParseError> {
             Ok(json!({ "id": id, "action": "device", "device": dev }))
         }
         Some("geo") | Some("geolocation") => {
-            let lat_str = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
-                context: "set geo".to_string(),
-                usage: "set geo <latitude> <longitude>",
-            })?;
-            let lng_str = rest.get(2).ok_or_else(|| ParseError::MissingArguments {
-                context: "set geo".to_string(),
-                usage: "set geo <latitude> <longitude>",
-            })?;
-            let lat = lat_str.parse::<f64>().map_err(|_| ParseError::MissingArguments {
-                context: "set geo".to_string(),
-                usage: "set geo <latitude> <longitude>",
-            })?;
-            let lng = lng_str.parse::<f64>().map_err(|_| ParseError::MissingArguments {
-                context: "set geo".to_string(),
-                usage: "set geo <latitude> <longitude>",
-            })?;
+            const USAGE: &str = "set geo <latitude> <longitude> | set geo <latitude,longitude>";
+            let (lat, lng) = match (rest.get(1), rest.get(2)) {
+                // A single `lat,lng` argument
+                (Some(pair), None) => parse::coordinates(pair).map_err(|e| ParseError::MissingArguments {
+                    context: format!("set geo: {}", e),
+                    usage: USAGE,
+                })?,
+                (Some(lat_str), Some(lng_str)) => {
+                    let lat = lat_str.parse::<f64>().map_err(|_| ParseError::MissingArguments {
+                        context: "set geo".to_string(),
+                        usage: USAGE,
+                    })?;
+                    let lng = lng_str.parse::<f64>().map_err(|_| ParseError::MissingArguments {
+                        context: "set geo".to_string(),
+                        usage: USAGE,
+                    })?;
+                    (lat, lng)
+                }
+                _ => {
+                    return Err(ParseError::MissingArguments {
+                        context: "set geo".to_string(),
+                        usage: USAGE,
+                    })
+                }
+            };
             Ok(json!({ "id": id, "action": "geolocation", "latitude": lat, "longitude": lng }))
         }
         Some("offline") => {
@@ -927,14 +1248,14 @@ fn parse_set(rest: &[&str], id: &str) -> Result<Value, ParseError> {
             Ok(json!({ "id": id, "action": "credentials", "username": user, "password": pass }))
         }
         Some("media") => {
-            let color = if rest.iter().any(|&s| s == "dark") {
+            let color = if rest.contains(&"dark") {
                 "dark"
-            } else if rest.iter().any(|&s| s == "light") {
+            } else if rest.contains(&"light") {
                 "light"
             } else {
                 "no-preference"
             };
-            let reduced = if rest.iter().any(|&s| s == "reduced-motion") {
+            let reduced = if rest.contains(&"reduced-motion") {
                 "reduce"
             } else {
                 "no-preference"
@@ -955,15 +1276,32 @@ fn parse_set(rest: &[&str], id: &str) -> Result<Value, ParseError> {
 fn parse_network(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["route", "unroute", "requests"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
+        // No subcommand: live tail of network events, filtered client-side
+        None | Some("--method") | Some("--status") => {
+            let method = rest.iter().position(|&s| s == "--method").and_then(|i| rest.get(i + 1).copied());
+            let status = rest.iter().position(|&s| s == "--status").and_then(|i| rest.get(i + 1).copied());
+            NetworkFilter::new(method, status).map_err(|bad| ParseError::MissingArguments {
+                context: format!("network: invalid status '{}'", bad),
+                usage: "network [--method <GET,POST>] [--status <4xx,5xx,200>]",
+            })?;
+            let mut cmd = json!({ "id": id, "action": "network_tail" });
+            if let Some(m) = method {
+                cmd["method"] = json!(m);
+            }
+            if let Some(s) = status {
+                cmd["status"] = json!(s);
+            }
+            Ok(cmd)
+        }
         Some("route") => {
             let url = rest.get(1).ok_or_else(|| ParseError::MissingArguments {
                 context: "network route".to_string(),
                 usage: "network route <url> [--abort|--body <json>]",
             })?;
-            let abort = rest.iter().any(|&s| s == "--abort");
+            let abort = rest.contains(&"--abort");
             let body_idx = rest.iter().position(|&s| s == "--body");
-            let body = body_idx.and_then(|i| rest.get(i + 1).map(|s| *s));
+            let body = body_idx.and_then(|i| rest.get(i + 1).copied());
             Ok(json!({ "id": id, "action": "route", "url": url, "abort": abort, "body": body }))
         }
         Some("unroute") => {
@@ -974,9 +1312,9 @@ fn parse_network(rest: &[&str], id: &str) -> Result<Value, ParseError> {
             Ok(cmd)
         },
         Some("requests") => {
-            let clear = rest.iter().any(|&s| s == "--clear");
+            let clear = rest.contains(&"--clear");
             let filter_idx = rest.iter().position(|&s| s == "--filter");
-            let filter = filter_idx.and_then(|i| rest.get(i + 1).map(|s| *s));
+            let filter = filter_idx.and_then(|i| rest.get(i + 1).copied());
             let mut cmd = json!({ "id": id, "action": "requests", "clear": clear });
             if let Some(f) = filter {
                 cmd["filter"] = json!(f);
@@ -987,17 +1325,13 @@ fn parse_network(rest: &[&str], id: &str) -> Result<Value, ParseError> {
             subcommand: sub.to_string(),
             valid_options: VALID,
         }),
-        None => Err(ParseError::MissingArguments {
-            context: "network".to_string(),
-            usage: "network <route|unroute|requests> [args...]",
-        }),
     }
 }
 
 fn parse_storage(rest: &[&str], id: &str) -> Result<Value, ParseError> {
     const VALID: &[&str] = &["local", "session"];
     
-    match rest.get(0).map(|s| *s) {
+    match rest.get(0).copied() {
         Some("local") | Some("session") => {
             let storage_type = rest.get(0).unwrap();
             let op = rest.get(1).unwrap_or(&"get");
@@ -1043,18 +1377,106 @@ mod tests {
     fn default_flags() -> Flags {
         Flags {
             session: "test".to_string(),
-            json: false,
-            full: false,
-            headed: false,
-            debug: false,
-            headers: None,
-            executable_path: None,
-            extensions: Vec::new(),
-            cdp: None,
-            proxy: None,
+            ..Default::default()
         }
     }
 
+    fn selectors(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_report_valid() {
+        let report = parse_report(&args("click @e1"), &default_flags());
+        assert_eq!(report["success"], true);
+        assert_eq!(report["data"]["valid"], true);
+        assert_eq!(report["data"]["command"]["action"], "click");
+        assert_eq!(report["data"]["command"]["selector"], "@e1");
+    }
+
+    #[test]
+    fn test_parse_report_invalid() {
+        let report = parse_report(&args("frobnicate"), &default_flags());
+        assert_eq!(report["success"], false);
+        assert_eq!(report["data"]["valid"], false);
+        assert_eq!(report["type"], "unknown_command");
+        assert_eq!(report["error"], "Unknown command: frobnicate");
+
+        let report = parse_report(&args("get bogus"), &default_flags());
+        assert_eq!(report["type"], "unknown_subcommand");
+
+        let report = parse_report(&args("set-viewport 1280"), &default_flags());
+        assert_eq!(report["type"], "missing_arguments");
+        assert!(report["error"].as_str().unwrap().contains("expected WIDTHxHEIGHT"));
+    }
+
+    #[test]
+    fn test_close_timeout() {
+        let cmd = parse_command(&args("close"), &default_flags()).unwrap();
+        assert!(cmd.get("timeout").is_none());
+        let cmd = parse_command(&args("close --timeout 3000"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "close");
+        assert_eq!(cmd["timeout"], 3000);
+        assert!(parse_command(&args("close --timeout"), &default_flags()).is_err());
+        assert!(parse_command(&args("close --timeout 0"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let cmd = parse_command(&args("shutdown"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "shutdown");
+        assert!(cmd.get("timeout").is_none());
+        let cmd = parse_command(&args("shutdown --timeout 20000"), &default_flags()).unwrap();
+        assert_eq!(cmd["timeout"], 20000);
+        assert!(parse_command(&args("shutdown --timeout soon"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_ping() {
+        let cmd = parse_command(&args("ping"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "ping");
+        assert_eq!(cmd.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bulk_commands_insert_selector() {
+        let cmds = bulk_commands(&args("click"), &default_flags(), &selectors(&["#a", ".b"])).unwrap();
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0]["action"], "click");
+        assert_eq!(cmds[0]["selector"], "#a");
+        assert_eq!(cmds[1]["selector"], ".b");
+
+        let cmds = bulk_commands(&args("count --highlight"), &default_flags(), &selectors(&["li"])).unwrap();
+        assert_eq!(cmds[0]["action"], "count");
+        assert_eq!(cmds[0]["selector"], "li");
+        assert_eq!(cmds[0]["highlight"], true);
+
+        let cmds = bulk_commands(&args("get text"), &default_flags(), &selectors(&["h1"])).unwrap();
+        assert_eq!(cmds[0]["action"], "gettext");
+        assert_eq!(cmds[0]["selector"], "h1");
+    }
+
+    #[test]
+    fn test_parse_command_with_selector_file() {
+        let path = std::env::temp_dir().join(format!("agent-browser-selectors-{}.txt", std::process::id()));
+        std::fs::write(&path, "# buttons\n#save\n\n.cancel\n").unwrap();
+        let flags = Flags {
+            selector_file: Some(path.to_string_lossy().into_owned()),
+            ..default_flags()
+        };
+        let cmd = parse_command(&args("click"), &flags).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cmd["action"], "bulk");
+        assert_eq!(cmd["selectors"], json!(["#save", ".cancel"]));
+        assert_eq!(cmd["commands"][1]["selector"], ".cancel");
+
+        let missing = Flags {
+            selector_file: Some("/nonexistent/selectors.txt".to_string()),
+            ..default_flags()
+        };
+        assert!(parse_command(&args("click"), &missing).is_err());
+    }
+
     fn args(s: &str) -> Vec<String> {
         s.split_whitespace().map(String::from).collect()
     }
@@ -1214,6 +1636,225 @@ mod tests {
         assert!(cmd.get("headers").is_none());
     }
 
+    #[test]
+    fn test_navigate_with_storage_injection() {
+        let mut flags = default_flags();
+        flags.local_storage = vec!["token=abc".to_string(), "theme=dark".to_string()];
+        flags.session_storage = vec!["step=2".to_string()];
+        let cmd = parse_command(&args("open app.example.com"), &flags).unwrap();
+        assert_eq!(cmd["localStorage"], json!({ "token": "abc", "theme": "dark" }));
+        assert_eq!(cmd["sessionStorage"], json!({ "step": "2" }));
+    }
+
+    #[test]
+    fn test_navigate_without_storage_injection() {
+        let cmd = parse_command(&args("open example.com"), &default_flags()).unwrap();
+        assert!(cmd.get("localStorage").is_none());
+        assert!(cmd.get("sessionStorage").is_none());
+    }
+
+    #[test]
+    fn test_navigate_invalid_storage_pair() {
+        let mut flags = default_flags();
+        flags.local_storage = vec!["novalue".to_string()];
+        let result = parse_command(&args("open example.com"), &flags);
+        assert!(matches!(result.unwrap_err(), ParseError::MissingArguments { .. }));
+    }
+
+    #[test]
+    fn test_navigate_measure() {
+        let cmd = parse_command(&args("navigate example.com --measure"), &default_flags()).unwrap();
+        assert_eq!(cmd["url"], "https://example.com");
+        assert_eq!(cmd["measure"], true);
+
+        let cmd = parse_command(&args("open --measure example.com"), &default_flags()).unwrap();
+        assert_eq!(cmd["url"], "https://example.com");
+        assert_eq!(cmd["measure"], true);
+
+        let cmd = parse_command(&args("open example.com"), &default_flags()).unwrap();
+        assert!(cmd.get("measure").is_none());
+    }
+
+    #[test]
+    fn test_navigate_summary() {
+        let cmd = parse_command(&args("navigate example.com --summary"), &default_flags()).unwrap();
+        assert_eq!(cmd["url"], "https://example.com");
+        assert_eq!(cmd["summary"], true);
+
+        let cmd = parse_command(&args("open --summary --measure example.com"), &default_flags()).unwrap();
+        assert_eq!(cmd["url"], "https://example.com");
+        assert_eq!(cmd["summary"], true);
+        assert_eq!(cmd["measure"], true);
+
+        let cmd = parse_command(&args("open example.com"), &default_flags()).unwrap();
+        assert!(cmd.get("summary").is_none());
+    }
+
+    #[test]
+    fn test_count_highlight() {
+        let cmd = parse_command(&args("count .item --highlight"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "count");
+        assert_eq!(cmd["selector"], ".item");
+        assert_eq!(cmd["highlight"], true);
+
+        let cmd = parse_command(&args("get count --highlight li"), &default_flags()).unwrap();
+        assert_eq!(cmd["selector"], "li");
+        assert_eq!(cmd["highlight"], true);
+
+        let cmd = parse_command(&args("count .item"), &default_flags()).unwrap();
+        assert!(cmd.get("highlight").is_none());
+    }
+
+    #[test]
+    fn test_count_highlight_requires_selector() {
+        let result = parse_command(&args("count --highlight"), &default_flags());
+        assert!(matches!(result, Err(ParseError::MissingArguments { .. })));
+    }
+
+    #[test]
+    fn test_batch() {
+        let cmd = parse_command(&args("batch checks.txt"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "batch");
+        assert_eq!(cmd["file"], "checks.txt");
+        assert!(parse_command(&args("batch"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_assert_commands() {
+        let cmd = parse_command(&args("assert-visible #login"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "isvisible");
+        assert_eq!(cmd["assert"], json!({ "field": "visible", "expected": true, "match": "equals" }));
+
+        let cmd = parse_command(&args("assert-text h1 Hello world"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "gettext");
+        assert_eq!(cmd["selector"], "h1");
+        assert_eq!(cmd["assert"]["expected"], "Hello world");
+        assert_eq!(cmd["assert"]["match"], "contains");
+
+        let cmd = parse_command(&args("assert-url /dashboard"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "url");
+        assert_eq!(cmd["assert"]["field"], "url");
+
+        let cmd = parse_command(&args("assert-count li 3"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "count");
+        assert_eq!(cmd["assert"]["expected"], 3);
+    }
+
+    #[test]
+    fn test_assert_commands_invalid() {
+        assert!(parse_command(&args("assert-text h1"), &default_flags()).is_err());
+        assert!(parse_command(&args("assert-title"), &default_flags()).is_err());
+        assert!(parse_command(&args("assert-count li many"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_a11y() {
+        let cmd = parse_command(&args("a11y"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "a11y_snapshot");
+        assert!(cmd.get("selector").is_none());
+    }
+
+    #[test]
+    fn test_a11y_with_selector() {
+        let cmd = parse_command(&args("a11y --selector #main"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "a11y_snapshot");
+        assert_eq!(cmd["selector"], "#main");
+
+        let cmd = parse_command(&args("accessibility -s nav"), &default_flags()).unwrap();
+        assert_eq!(cmd["selector"], "nav");
+
+        assert!(parse_command(&args("a11y --selector"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_wait_function() {
+        let input = vec!["wait-function".to_string(), "() => window.appReady === true".to_string()];
+        let cmd = parse_command(&input, &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "waitforfunction");
+        assert_eq!(cmd["expression"], "() => window.appReady === true");
+        assert_eq!(cmd["timeout"], DEFAULT_WAIT_FUNCTION_TIMEOUT_MS);
+    }
+
+    #[test]
+    fn test_frame_scopes_selector_commands() {
+        let flags = Flags {
+            frame: Some("#checkout".to_string()),
+            ..default_flags()
+        };
+        let cmd = parse_command(&args("click #pay"), &flags).unwrap();
+        assert_eq!(cmd["frame"], "#checkout");
+        assert_eq!(cmd["selector"], "#pay");
+        let cmd = parse_command(&args("get text h1"), &flags).unwrap();
+        assert_eq!(cmd["frame"], "#checkout");
+        // Commands without a selector run in the page as before
+        let cmd = parse_command(&args("open example.com"), &flags).unwrap();
+        assert!(cmd.get("frame").is_none());
+        let cmd = parse_command(&args("click #pay"), &default_flags()).unwrap();
+        assert!(cmd.get("frame").is_none());
+    }
+
+    #[test]
+    fn test_transport_timeout_per_command() {
+        let table: HashMap<String, f64> =
+            [("default".to_string(), 20.0), ("click".to_string(), 2.0), ("eval".to_string(), 0.0)].into();
+        let fallback = Some(Duration::from_secs(30));
+        let click = parse_command(&args("click #go"), &default_flags()).unwrap();
+        assert_eq!(transport_timeout("click", &click, &table, fallback), Some(Duration::from_secs(2)));
+        // Unlisted commands use the table's default, then the fallback
+        let url = parse_command(&args("get url"), &default_flags()).unwrap();
+        assert_eq!(transport_timeout("get", &url, &table, fallback), Some(Duration::from_secs(20)));
+        assert_eq!(transport_timeout("get", &url, &HashMap::new(), fallback), fallback);
+        // 0 means no limit
+        let eval = parse_command(&args("eval 1"), &default_flags()).unwrap();
+        assert_eq!(transport_timeout("eval", &eval, &table, fallback), None);
+    }
+
+    #[test]
+    fn test_transport_timeout_outlasts_semantic_timeout() {
+        let table: HashMap<String, f64> = [("wait-function".to_string(), 10.0)].into();
+        let fallback = Some(Duration::from_secs(30));
+        let cmd = parse_command(&args("wait-function ready --timeout 60000"), &default_flags()).unwrap();
+        assert_eq!(
+            transport_timeout("wait-function", &cmd, &table, fallback),
+            Some(Duration::from_secs(60) + TRANSPORT_TIMEOUT_GRACE)
+        );
+        let wait = parse_command(&args("wait 45000"), &default_flags()).unwrap();
+        assert_eq!(transport_timeout("wait", &wait, &HashMap::new(), fallback), Some(Duration::from_secs(50)));
+        // A longer configured timeout is kept as is
+        let short = parse_command(&args("wait-function ready --timeout 1000"), &default_flags()).unwrap();
+        assert_eq!(transport_timeout("wait-function", &short, &table, fallback), Some(Duration::from_secs(10)));
+        assert_eq!(transport_timeout("wait-function", &short, &HashMap::new(), None), None);
+    }
+
+    #[test]
+    fn test_wait_function_timeout_and_encoding() {
+        let js = "() => document.title === \"Done \\\"ok\\\"\"\n && true";
+        let input: Vec<String> = vec!["wait-function".into(), js.into(), "--timeout".into(), "5000".into()];
+        let cmd = parse_command(&input, &default_flags()).unwrap();
+        assert_eq!(cmd["timeout"], 5000);
+        // The function survives a JSON round trip byte for byte
+        let wire = serde_json::to_string(&cmd).unwrap();
+        let back: Value = serde_json::from_str(&wire).unwrap();
+        assert_eq!(back["expression"], js);
+    }
+
+    #[test]
+    fn test_wait_function_invalid() {
+        assert!(parse_command(&args("wait-function"), &default_flags()).is_err());
+        let blank = vec!["wait-function".to_string(), "   ".to_string()];
+        assert!(parse_command(&blank, &default_flags()).is_err());
+        assert!(parse_command(&args("wait-function ready --timeout soon"), &default_flags()).is_err());
+        assert!(parse_command(&args("wait-function ready --timeout 0"), &default_flags()).is_err());
+        assert!(parse_command(&args("wait-function ready --timeout"), &default_flags()).is_err());
+    }
+
+    #[test]
+    fn test_cdp_url() {
+        let cmd = parse_command(&args("cdp-url"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "cdp_url");
+        assert!(cmd["id"].is_string());
+    }
+
     // === Set Headers Tests ===
 
     #[test]
@@ -1256,19 +1897,105 @@ mod tests {
     #[test]
     fn test_back() {
         let cmd = parse_command(&args("back"), &default_flags()).unwrap();
-        assert_eq!(cmd["action"], "back");
+        assert_eq!(cmd["action"], "goBack");
     }
 
     #[test]
     fn test_forward() {
         let cmd = parse_command(&args("forward"), &default_flags()).unwrap();
-        assert_eq!(cmd["action"], "forward");
+        assert_eq!(cmd["action"], "goForward");
     }
 
     #[test]
     fn test_reload() {
         let cmd = parse_command(&args("reload"), &default_flags()).unwrap();
         assert_eq!(cmd["action"], "reload");
+        assert_eq!(cmd["hard"], false);
+    }
+
+    #[test]
+    fn test_reload_hard() {
+        let cmd = parse_command(&args("reload --hard"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "reload");
+        assert_eq!(cmd["hard"], true);
+    }
+
+    // === Set Viewport ===
+
+    #[test]
+    fn test_set_viewport() {
+        let cmd = parse_command(&args("set-viewport 1280x720"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "setViewport");
+        assert_eq!(cmd["width"], 1280);
+        assert_eq!(cmd["height"], 720);
+    }
+
+    #[test]
+    fn test_set_viewport_invalid() {
+        let result = parse_command(&args("set-viewport 1280"), &default_flags());
+        assert!(matches!(result.unwrap_err(), ParseError::MissingArguments { .. }));
+        let err = parse_command(&args("set-viewport 0x720"), &default_flags()).unwrap_err();
+        assert!(err.format().contains("expected WIDTHxHEIGHT with positive integers, got '0x720'"));
+    }
+
+    #[test]
+    fn test_set_geo_forms() {
+        let cmd = parse_command(&args("set geo 37.77 -122.41"), &default_flags()).unwrap();
+        assert_eq!(cmd["latitude"], 37.77);
+        assert_eq!(cmd["longitude"], -122.41);
+        let cmd = parse_command(&args("set geo 37.77,-122.41"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "geolocation");
+        assert_eq!(cmd["longitude"], -122.41);
+        let err = parse_command(&args("set geo 37.77"), &default_flags()).unwrap_err();
+        assert!(err.format().contains("expected X,Y, got '37.77'"));
+    }
+
+    #[test]
+    fn test_set_viewport_missing() {
+        let result = parse_command(&args("set-viewport"), &default_flags());
+        assert!(matches!(result.unwrap_err(), ParseError::MissingArguments { .. }));
+    }
+
+    // === Launch ===
+
+    #[test]
+    fn test_launch_headless() {
+        let cmd = parse_command(&args("launch"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "launch");
+        assert_eq!(cmd["headless"], true);
+    }
+
+    #[test]
+    fn test_warmup_headed() {
+        let mut flags = default_flags();
+        flags.headed = true;
+        let cmd = parse_command(&args("warmup"), &flags).unwrap();
+        assert_eq!(cmd["action"], "launch");
+        assert_eq!(cmd["headless"], false);
+    }
+
+    // === Network Tail ===
+
+    #[test]
+    fn test_network_tail_no_filters() {
+        let cmd = parse_command(&args("network"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "network_tail");
+        assert!(cmd.get("method").is_none());
+        assert!(cmd.get("status").is_none());
+    }
+
+    #[test]
+    fn test_network_tail_filters() {
+        let cmd = parse_command(&args("network --method GET --status 4xx,5xx"), &default_flags()).unwrap();
+        assert_eq!(cmd["action"], "network_tail");
+        assert_eq!(cmd["method"], "GET");
+        assert_eq!(cmd["status"], "4xx,5xx");
+    }
+
+    #[test]
+    fn test_network_tail_invalid_status() {
+        let result = parse_command(&args("network --status 4yy"), &default_flags());
+        assert!(matches!(result.unwrap_err(), ParseError::MissingArguments { .. }));
     }
 
     // === Core Actions ===
@@ -0,0 +1,316 @@
+//! `agent-browser completions <shell>`: print a tab-completion script.
+//!
+//! Arguments are parsed by hand rather than by a library that could describe
+//! them, so the commands are listed here. The tests check the table against
+//! `parse_command`.
+
+use crate::flags::{GLOBAL_FLAGS, GLOBAL_FLAGS_WITH_VALUE};
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+/// Top-level commands and the words that can follow them.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("open", &[]),
+    ("back", &[]),
+    ("forward", &[]),
+    ("reload", &[]),
+    ("click", &[]),
+    ("dblclick", &[]),
+    ("fill", &[]),
+    ("type", &[]),
+    ("hover", &[]),
+    ("focus", &[]),
+    ("check", &[]),
+    ("uncheck", &[]),
+    ("select", &[]),
+    ("drag", &[]),
+    ("upload", &[]),
+    ("press", &[]),
+    ("keydown", &[]),
+    ("keyup", &[]),
+    ("scroll", &[]),
+    ("scrollintoview", &[]),
+    ("wait", &[]),
+    ("wait-function", &[]),
+    ("screenshot", &[]),
+    ("pdf", &[]),
+    ("snapshot", &[]),
+    ("eval", &[]),
+    ("close", &[]),
+    ("shutdown", &[]),
+    ("ping", &[]),
+    ("launch", &[]),
+    ("warmup", &[]),
+    ("a11y", &[]),
+    ("cdp-url", &[]),
+    ("connect", &[]),
+    ("batch", &[]),
+    ("get", &["text", "html", "value", "attr", "url", "title", "count", "box", "styles"]),
+    ("count", &[]),
+    ("is", &["visible", "enabled", "checked"]),
+    ("find", &["role", "text", "label", "placeholder", "alt", "title", "testid", "first", "last", "nth"]),
+    ("mouse", &["move", "down", "up", "wheel"]),
+    (
+        "set",
+        &["viewport", "device", "geo", "geolocation", "offline", "headers", "credentials", "auth", "media"],
+    ),
+    ("set-viewport", &[]),
+    ("network", &["route", "unroute", "requests"]),
+    ("storage", &["local", "session"]),
+    ("cookies", &["get", "set", "clear"]),
+    ("tab", &["new", "list", "close"]),
+    ("window", &["new"]),
+    ("frame", &["main"]),
+    ("dialog", &["accept", "dismiss"]),
+    ("trace", &["start", "stop"]),
+    ("record", &["start", "stop", "restart"]),
+    ("console", &[]),
+    ("errors", &[]),
+    ("highlight", &[]),
+    ("state", &["save", "load"]),
+    ("assert-visible", &[]),
+    ("assert-text", &[]),
+    ("assert-url", &[]),
+    ("assert-title", &[]),
+    ("assert-count", &[]),
+    // Handled by the CLI itself
+    ("session", &["list", "info", "env", "kill", "clean", "rename", "config"]),
+    ("install", &["--with-deps"]),
+    ("doctor", &["--fix"]),
+    ("completions", SHELLS),
+];
+
+/// Flags the CLI reads itself rather than through `flags.rs`.
+const EXTRA_FLAGS: &[&str] = &["--timeout", "--help", "--version"];
+
+/// The completion script for `shell`, or `None` if it isn't supported.
+pub fn script(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash()),
+        "zsh" => Some(zsh()),
+        "fish" => Some(fish()),
+        "powershell" => Some(powershell()),
+        _ => None,
+    }
+}
+
+fn command_names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|(name, _)| *name).collect()
+}
+
+fn flag_names() -> Vec<&'static str> {
+    let mut flags: Vec<&str> = GLOBAL_FLAGS.iter().chain(GLOBAL_FLAGS_WITH_VALUE).chain(EXTRA_FLAGS).copied().collect();
+    flags.sort_unstable();
+    flags.dedup();
+    flags
+}
+
+/// Flags whose next word is their value rather than a command.
+fn value_flag_names() -> Vec<&'static str> {
+    GLOBAL_FLAGS_WITH_VALUE.iter().copied().chain(["--timeout"]).collect()
+}
+
+fn with_subcommands() -> impl Iterator<Item = (&'static str, &'static [&'static str])> {
+    COMMANDS.iter().copied().filter(|(_, subs)| !subs.is_empty())
+}
+
+fn bash() -> String {
+    let cases: String = with_subcommands()
+        .map(|(name, subs)| format!("            {}) words=\"{}\" ;;\n", name, subs.join(" ")))
+        .collect();
+    format!(
+        r#"# agent-browser completion for bash. Add to ~/.bashrc:
+#   source <(agent-browser completions bash)
+_agent_browser() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" cmd="" i words=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in
+            {value_flags}) ((i++)) ;;
+            -*) ;;
+            *) cmd="${{COMP_WORDS[i]}}"; break ;;
+        esac
+    done
+    if [[ $cur == -* ]]; then
+        words="{flags}"
+    elif [[ -z $cmd ]]; then
+        words="{commands}"
+    elif ((i == COMP_CWORD - 1)); then
+        case "$cmd" in
+{cases}        esac
+    fi
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}}
+complete -F _agent_browser agent-browser
+"#,
+        value_flags = value_flag_names().join("|"),
+        flags = flag_names().join(" "),
+        commands = command_names().join(" "),
+        cases = cases,
+    )
+}
+
+fn zsh() -> String {
+    let cases: String = with_subcommands()
+        .map(|(name, subs)| format!("            {}) compadd -- {} ;;\n", name, subs.join(" ")))
+        .collect();
+    format!(
+        r#"#compdef agent-browser
+# agent-browser completion for zsh. Add to ~/.zshrc after compinit:
+#   source <(agent-browser completions zsh)
+_agent_browser() {{
+    local -a value_flags=({value_flags})
+    local i cmd="" cmd_index=0
+    for ((i = 2; i < CURRENT; i++)); do
+        if (( ${{value_flags[(Ie)${{words[i]}}]}} )); then
+            ((i++))
+        elif [[ ${{words[i]}} != -* ]]; then
+            cmd=${{words[i]}}
+            cmd_index=$i
+            break
+        fi
+    done
+    if [[ ${{words[CURRENT]}} == -* ]]; then
+        compadd -- {flags}
+    elif [[ -z $cmd ]]; then
+        compadd -- {commands}
+    elif (( CURRENT == cmd_index + 1 )); then
+        case $cmd in
+{cases}        esac
+    fi
+}}
+compdef _agent_browser agent-browser
+"#,
+        value_flags = value_flag_names().join(" "),
+        flags = flag_names().join(" "),
+        commands = command_names().join(" "),
+        cases = cases,
+    )
+}
+
+fn fish() -> String {
+    let mut out = String::from(
+        "# agent-browser completion for fish. Save as\n\
+         # ~/.config/fish/completions/agent-browser.fish:\n\
+         #   agent-browser completions fish > ~/.config/fish/completions/agent-browser.fish\n\
+         complete -c agent-browser -f\n",
+    );
+    out.push_str(&format!(
+        "complete -c agent-browser -n __fish_use_subcommand -a '{}'\n",
+        command_names().join(" ")
+    ));
+    for (name, subs) in with_subcommands() {
+        out.push_str(&format!(
+            "complete -c agent-browser -n '__fish_seen_subcommand_from {}' -a '{}'\n",
+            name,
+            subs.join(" ")
+        ));
+    }
+    let value_flags = value_flag_names();
+    for flag in flag_names() {
+        let option = match flag.strip_prefix("--") {
+            Some(long) => format!("-l {}", long),
+            None => format!("-s {}", flag.trim_start_matches('-')),
+        };
+        let takes_value = if value_flags.contains(&flag) { " -r" } else { "" };
+        out.push_str(&format!("complete -c agent-browser {}{}\n", option, takes_value));
+    }
+    out
+}
+
+fn powershell() -> String {
+    let quote = |words: &[&str]| words.iter().map(|w| format!("'{}'", w)).collect::<Vec<_>>().join(", ");
+    let subcommands: String = with_subcommands()
+        .map(|(name, subs)| format!("        '{}' = @({})\n", name, quote(subs)))
+        .collect();
+    format!(
+        r#"# agent-browser completion for PowerShell. Add to $PROFILE:
+#   agent-browser completions powershell | Out-String | Invoke-Expression
+Register-ArgumentCompleter -Native -CommandName agent-browser -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $commands = @({commands})
+    $flags = @({flags})
+    $valueFlags = @({value_flags})
+    $subcommands = @{{
+{subcommands}    }}
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 |
+        Where-Object {{ $_.Extent.EndOffset -lt $cursorPosition }} | ForEach-Object {{ $_.ToString() }})
+    $cmd = $null
+    $afterCmd = 0
+    for ($i = 0; $i -lt $words.Count; $i++) {{
+        if ($valueFlags -contains $words[$i]) {{ $i++; continue }}
+        if ($words[$i].StartsWith('-')) {{ continue }}
+        $cmd = $words[$i]
+        $afterCmd = $words.Count - $i - 1
+        break
+    }}
+    $candidates = if ($wordToComplete.StartsWith('-')) {{ $flags }}
+        elseif (-not $cmd) {{ $commands }}
+        elseif ($afterCmd -eq 0 -and $subcommands.ContainsKey($cmd)) {{ $subcommands[$cmd] }}
+        else {{ @() }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        commands = quote(&command_names()),
+        flags = quote(&flag_names()),
+        value_flags = quote(&value_flag_names()),
+        subcommands = subcommands,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{parse_command, ParseError};
+    use crate::flags::Flags;
+
+    const LOCAL_COMMANDS: &[&str] = &["session", "install", "doctor", "completions"];
+
+    fn parse(words: &[&str]) -> Result<serde_json::Value, ParseError> {
+        let args: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        parse_command(&args, &Flags::default())
+    }
+
+    #[test]
+    fn test_table_matches_parse_command() {
+        for (name, subs) in COMMANDS {
+            if LOCAL_COMMANDS.contains(name) {
+                continue;
+            }
+            assert!(
+                !matches!(parse(&[name]), Err(ParseError::UnknownCommand { .. })),
+                "{} is not a command",
+                name
+            );
+            // Where the parser lists valid subcommands, the table must agree
+            if let Err(ParseError::UnknownSubcommand { valid_options, .. }) = parse(&[name, "no-such-subcommand"]) {
+                assert_eq!(*subs, valid_options, "subcommands of {}", name);
+            }
+        }
+        assert!(matches!(parse(&["no-such-command"]), Err(ParseError::UnknownCommand { .. })));
+    }
+
+    #[test]
+    fn test_scripts_cover_commands_and_flags() {
+        for shell in SHELLS {
+            let script = script(shell).unwrap();
+            // fish spells flags `-l json`, the others `--json`
+            for word in ["open", "session", "install", "rename", "json", "headed", "timeout", "no-color"] {
+                assert!(script.contains(word), "{} script lacks {}", shell, word);
+            }
+        }
+        assert!(script("tcsh").is_none());
+    }
+
+    #[test]
+    fn test_value_flags_are_skipped() {
+        let bash = script("bash").unwrap();
+        assert!(bash.contains("--session|"));
+        assert!(!value_flag_names().contains(&"--json"));
+        let fish = script("fish").unwrap();
+        assert!(fish.contains("complete -c agent-browser -l session -r\n"));
+        assert!(fish.contains("complete -c agent-browser -l json\n"));
+        assert!(fish.contains("complete -c agent-browser -s p -r\n"));
+    }
+}
@@ -0,0 +1,253 @@
+//! The user config file, `~/.config/agent-browser/config.toml`, or the file
+//! named by `--config` / `AGENT_BROWSER_CONFIG`.
+//!
+//! ```toml
+//! [defaults]          # used when the flag isn't given
+//! session = "work"
+//! json = true
+//! proxy = "http://proxy:8080"
+//!
+//! [timeouts.transport]
+//! default = 30        # seconds the CLI waits for any reply; 0 = no limit
+//! wait-function = 120 # keyed by the command name as typed
+//! click = 10
+//! ```
+//!
+//! Launch defaults for a single session live next to it, in
+//! `sessions/<name>.toml`, and are written by `session config set`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub defaults: Defaults,
+    pub timeouts: Timeouts,
+}
+
+/// `[defaults]`: values for global flags. Command-line flags and their
+/// environment variables take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Defaults {
+    pub session: Option<String>,
+    pub json: Option<bool>,
+    pub headed: Option<bool>,
+    pub proxy: Option<String>,
+    pub executable_path: Option<String>,
+}
+
+/// `[timeouts]`. Transport timeouts bound how long the CLI waits on the
+/// socket; they are separate from the semantic timeouts a command sends to
+/// the daemon, like `wait-function --timeout`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Seconds per command name, plus an optional `default` for the rest.
+    pub transport: HashMap<String, f64>,
+}
+
+/// Launch defaults saved for one session. They are read whenever that
+/// session is used, but only take effect when its daemon starts; flags given
+/// on the command line win over them.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SessionConfig {
+    pub headed: Option<bool>,
+    pub proxy: Option<String>,
+    pub executable_path: Option<String>,
+}
+
+impl SessionConfig {
+    pub const KEYS: &'static [&'static str] = &["headed", "proxy", "executable-path"];
+
+    /// Set `key` from its command-line text. An empty value removes the key.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let text = (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "headed" => {
+                self.headed = match value {
+                    "" => None,
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => return Err(format!("headed: expected true or false, got '{}'", value)),
+                }
+            }
+            "proxy" => self.proxy = text,
+            "executable-path" => self.executable_path = text,
+            _ => {
+                return Err(format!(
+                    "Unknown session config key '{}' (expected one of: {})",
+                    key,
+                    Self::KEYS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/agent-browser`, falling back to `~/.config`.
+fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?).join(".config"),
+    };
+    Some(base.join("agent-browser"))
+}
+
+/// `$XDG_CONFIG_HOME/agent-browser/config.toml`, falling back to `~/.config`.
+pub fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Where `session`'s saved launch defaults live.
+pub fn session_config_path(session: &str) -> Option<PathBuf> {
+    Some(config_dir()?.join("sessions").join(format!("{}.toml", session)))
+}
+
+/// Load `explicit`, or the default config file. A missing default file is an
+/// empty config, but a file that was asked for by name has to exist.
+pub fn load(explicit: Option<&Path>) -> Result<Config, String> {
+    match explicit {
+        Some(path) => load_from(path, true),
+        None => match config_path() {
+            Some(path) => load_from(&path, false),
+            None => Ok(Config::default()),
+        },
+    }
+}
+
+fn load_from(path: &Path, required: bool) -> Result<Config, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    parse(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Load a session's saved defaults; a missing file means none.
+pub fn load_session(session: &str) -> Result<SessionConfig, String> {
+    match session_config_path(session) {
+        Some(path) => load_session_from(&path),
+        None => Ok(SessionConfig::default()),
+    }
+}
+
+fn load_session_from(path: &Path) -> Result<SessionConfig, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(SessionConfig::default()),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    toml::from_str(&text).map_err(|e| format!("Invalid session config {}: {}", path.display(), e.message()))
+}
+
+/// Write a session's defaults, creating the `sessions` directory if needed.
+/// Returns the file written.
+pub fn save_session(session: &str, config: &SessionConfig) -> Result<PathBuf, String> {
+    let path = session_config_path(session).ok_or("Could not find a config directory (is HOME set?)")?;
+    save_session_to(&path, config)?;
+    Ok(path)
+}
+
+fn save_session_to(path: &Path, config: &SessionConfig) -> Result<(), String> {
+    let text = toml::to_string(config).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(text).map_err(|e| e.message().to_string())?;
+    for (command, secs) in &config.timeouts.transport {
+        if !secs.is_finite() || *secs < 0.0 {
+            return Err(format!(
+                "timeouts.transport.{}: expected seconds >= 0, got {}",
+                command, secs
+            ));
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transport_timeouts() {
+        let config = parse("[timeouts.transport]\ndefault = 30\nwait-function = 120\nclick = 2.5\n").unwrap();
+        let transport = &config.timeouts.transport;
+        assert_eq!(transport["default"], 30.0);
+        assert_eq!(transport["wait-function"], 120.0);
+        assert_eq!(transport["click"], 2.5);
+        assert!(parse("").unwrap().timeouts.transport.is_empty());
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = parse("[defaults]\nsession = \"work\"\njson = true\nexecutable-path = \"/opt/chrome\"\n").unwrap();
+        assert_eq!(config.defaults.session.as_deref(), Some("work"));
+        assert_eq!(config.defaults.json, Some(true));
+        assert_eq!(config.defaults.executable_path.as_deref(), Some("/opt/chrome"));
+        assert_eq!(config.defaults.proxy, None);
+        assert!(parse("[defaults]\nviewport = \"800x600\"\n").is_err());
+        assert!(parse("[defaults]\njson = \"yes\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_timeouts() {
+        assert!(parse("[timeouts.transport]\nclick = -1\n").unwrap_err().contains("click"));
+        assert!(parse("[timeouts.transport]\nclick = \"fast\"\n").is_err());
+        assert!(parse("[timeouts]\ntransport = 5\n").is_err());
+        assert!(parse("[timeout]\n").is_err());
+    }
+
+    #[test]
+    fn test_session_config_set() {
+        let mut config = SessionConfig::default();
+        config.set("headed", "true").unwrap();
+        config.set("proxy", "http://proxy:8080").unwrap();
+        config.set("executable-path", "/opt/chrome").unwrap();
+        assert_eq!(config.headed, Some(true));
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy:8080"));
+        assert_eq!(config.executable_path.as_deref(), Some("/opt/chrome"));
+
+        config.set("proxy", "").unwrap();
+        assert_eq!(config.proxy, None);
+        assert!(config.set("headed", "yes").unwrap_err().contains("true or false"));
+        assert!(config.set("viewport", "800x600").unwrap_err().contains("executable-path"));
+    }
+
+    #[test]
+    fn test_session_config_round_trip() {
+        let path = env::temp_dir().join(format!("agent-browser-session-config-{}", std::process::id())).join("s.toml");
+        assert_eq!(load_session_from(&path).unwrap(), SessionConfig::default());
+
+        let mut config = SessionConfig::default();
+        config.set("headed", "false").unwrap();
+        config.set("executable-path", r#"C:\Chrome "beta"\chrome.exe"#).unwrap();
+        save_session_to(&path, &config).unwrap();
+        assert_eq!(load_session_from(&path).unwrap(), config);
+
+        fs::write(&path, "headless = true\n").unwrap();
+        assert!(load_session_from(&path).unwrap_err().contains("Invalid session config"));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_missing_file_is_empty_config() {
+        let path = env::temp_dir().join(format!("agent-browser-no-config-{}.toml", std::process::id()));
+        assert!(load_from(&path, false).unwrap().timeouts.transport.is_empty());
+        // Unless it was named explicitly
+        assert!(load_from(&path, true).unwrap_err().contains("Could not read"));
+    }
+}
@@ -1,15 +1,27 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::env;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::TcpStream;
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::fs;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::OnceLock;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::log;
 
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::{CloseHandle, FILETIME};
+#[cfg(windows)]
+use windows_sys::Win32::System::Threading::{
+    GetProcessTimes, OpenProcess, TerminateProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+};
 
 #[derive(Serialize)]
 #[allow(dead_code)]
@@ -20,26 +32,135 @@ pub struct Request {
     pub extra: Value,
 }
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct Response {
+    /// The id of the request this answers, when the daemon echoes it
+    #[serde(default, skip_serializing)]
+    pub id: Option<String>,
     pub success: bool,
     pub data: Option<Value>,
     pub error: Option<String>,
+    /// Machine-readable error category (e.g. `element_not_found`), when the daemon provides one
+    #[serde(default, rename = "errorCode", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// The request's `--metadata` tags, echoed back for correlation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+/// Why a command could not be exchanged with the daemon.
+///
+/// The `Display` text matches the plain messages these errors replaced.
+#[derive(Debug)]
+pub enum CommandError {
+    /// No daemon is listening for the session (no socket or port).
+    DaemonNotFound { attempts: u32, source: io::Error },
+    /// A daemon is there but refused the connection.
+    ConnectionRefused { attempts: u32, source: io::Error },
+    /// The daemon didn't answer within the read timeout.
+    Timeout(io::Error),
+    WriteFailed(io::Error),
+    ReadFailed(io::Error),
+    /// The daemon hung up before its final response.
+    ConnectionClosed,
+    InvalidResponse(serde_json::Error),
+    /// The response carries another request's id: it was stale or out of order.
+    MismatchedResponse { expected: String, got: String },
+    /// The `--abort-signal-file` appeared while waiting for the response.
+    Cancelled(PathBuf),
+    /// The session's socket belongs to another user, or other users can
+    /// connect to it too.
+    InsecureSocket { path: PathBuf, reason: String },
+}
+
+impl CommandError {
+    fn connect(attempts: u32, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            CommandError::DaemonNotFound { attempts, source }
+        } else {
+            CommandError::ConnectionRefused { attempts, source }
+        }
+    }
+
+    fn read(source: io::Error) -> Self {
+        if let Some(AbortSignal(path)) = source.get_ref().and_then(|e| e.downcast_ref::<AbortSignal>()) {
+            return CommandError::Cancelled(path.clone());
+        }
+        match source.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => CommandError::Timeout(source),
+            _ => CommandError::ReadFailed(source),
+        }
+    }
+
+    /// Stable identifier for the `type` field of JSON errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandError::DaemonNotFound { .. } => "daemon_not_found",
+            CommandError::ConnectionRefused { .. } => "connection_refused",
+            CommandError::Timeout(_) => "timeout",
+            CommandError::WriteFailed(_) => "write_failed",
+            CommandError::ReadFailed(_) => "read_failed",
+            CommandError::ConnectionClosed => "connection_closed",
+            CommandError::InvalidResponse(_) | CommandError::MismatchedResponse { .. } => "invalid_response",
+            CommandError::Cancelled(_) => "cancelled",
+            CommandError::InsecureSocket { .. } => "insecure_socket",
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::DaemonNotFound { attempts, source } | CommandError::ConnectionRefused { attempts, source } => {
+                if *attempts > 1 {
+                    write!(f, "Failed to connect after {} attempts: {}", attempts, source)
+                } else {
+                    write!(f, "Failed to connect: {}", source)
+                }
+            }
+            CommandError::Timeout(e) | CommandError::ReadFailed(e) => write!(f, "Failed to read: {}", e),
+            CommandError::WriteFailed(e) => write!(f, "Failed to send: {}", e),
+            CommandError::ConnectionClosed => write!(f, "Connection closed before response"),
+            CommandError::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+            CommandError::MismatchedResponse { expected, got } => {
+                write!(f, "Invalid response: it answers request {}, not {}", got, expected)
+            }
+            CommandError::Cancelled(path) => write!(f, "Cancelled: {} appeared", path.display()),
+            CommandError::InsecureSocket { path, reason } => {
+                write!(f, "Refusing to connect to {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommandError::DaemonNotFound { source, .. } | CommandError::ConnectionRefused { source, .. } => Some(source),
+            CommandError::Timeout(e) | CommandError::WriteFailed(e) | CommandError::ReadFailed(e) => Some(e),
+            CommandError::InvalidResponse(e) => Some(e),
+            CommandError::ConnectionClosed
+            | CommandError::MismatchedResponse { .. }
+            | CommandError::Cancelled(_)
+            | CommandError::InsecureSocket { .. } => None,
+        }
+    }
 }
 
-#[allow(dead_code)]
 pub enum Connection {
     #[cfg(unix)]
     Unix(UnixStream),
+    /// A named pipe client; pipes opened this way have no read/write timeouts.
+    #[cfg(windows)]
+    Pipe(fs::File),
     Tcp(TcpStream),
 }
 
 impl Read for Connection {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        match self {
-            #[cfg(unix)]
-            Connection::Unix(s) => s.read(buf),
-            Connection::Tcp(s) => s.read(buf),
+        match ABORT_SIGNAL_FILE.get().and_then(|p| p.as_deref()) {
+            Some(abort) => self.read_abortable(buf, abort),
+            None => self.read_raw(buf),
         }
     }
 }
@@ -49,6 +170,8 @@ impl Write for Connection {
         match self {
             #[cfg(unix)]
             Connection::Unix(s) => s.write(buf),
+            #[cfg(windows)]
+            Connection::Pipe(s) => s.write(buf),
             Connection::Tcp(s) => s.write(buf),
         }
     }
@@ -57,16 +180,72 @@ impl Write for Connection {
         match self {
             #[cfg(unix)]
             Connection::Unix(s) => s.flush(),
+            #[cfg(windows)]
+            Connection::Pipe(s) => s.flush(),
             Connection::Tcp(s) => s.flush(),
         }
     }
 }
 
 impl Connection {
+    /// Connect to the session's daemon with the standard read/write timeouts.
+    pub fn open(session: &str) -> Result<Self, CommandError> {
+        let conn = connect(session)?;
+        conn.arm_timeouts();
+        Ok(conn)
+    }
+
+    /// (Re)apply the configured read timeout (`--timeout`) and the write
+    /// timeout, undoing any per-command override such as `close --timeout`.
+    pub fn arm_timeouts(&self) {
+        self.set_read_timeout(default_read_timeout()).ok();
+        self.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+    }
+
+    /// Like `arm_timeouts`, with the read timeout chosen for `cmd` (see
+    /// `set_command_read_timeout`).
+    pub fn arm_timeouts_for(&self, cmd: &Value) {
+        self.set_read_timeout(read_timeout_for(cmd)).ok();
+        self.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+    }
+
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(s) => s.read(buf),
+            #[cfg(windows)]
+            Connection::Pipe(s) => s.read(buf),
+            Connection::Tcp(s) => s.read(buf),
+        }
+    }
+
+    /// Read in `ABORT_POLL_INTERVAL` slices, checking for the abort signal
+    /// file in between. The configured read timeout still bounds the wait.
+    fn read_abortable(&mut self, buf: &mut [u8], abort: &Path) -> io::Result<usize> {
+        let timeout = self.read_timeout()?;
+        let deadline = timeout.map(|t| Instant::now() + t);
+        self.set_read_timeout(Some(timeout.map_or(ABORT_POLL_INTERVAL, |t| t.min(ABORT_POLL_INTERVAL))))?;
+        let result = poll_read(|| self.read_raw(buf), abort, deadline);
+        self.set_read_timeout(timeout)?;
+        result
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(s) => s.read_timeout(),
+            #[cfg(windows)]
+            Connection::Pipe(_) => Ok(None),
+            Connection::Tcp(s) => s.read_timeout(),
+        }
+    }
+
     pub fn set_read_timeout(&self, dur: Option<Duration>) -> std::io::Result<()> {
         match self {
             #[cfg(unix)]
             Connection::Unix(s) => s.set_read_timeout(dur),
+            #[cfg(windows)]
+            Connection::Pipe(_) => Ok(()),
             Connection::Tcp(s) => s.set_read_timeout(dur),
         }
     }
@@ -75,37 +254,254 @@ impl Connection {
         match self {
             #[cfg(unix)]
             Connection::Unix(s) => s.set_write_timeout(dur),
+            #[cfg(windows)]
+            Connection::Pipe(_) => Ok(()),
             Connection::Tcp(s) => s.set_write_timeout(dur),
         }
     }
 }
 
+static ABORT_SIGNAL_FILE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// How often a pending read checks for the `--abort-signal-file`.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancel any wait for a response once `path` exists (`--abort-signal-file`).
+/// Call once at startup.
+pub fn set_abort_signal_file(path: Option<PathBuf>) {
+    let _ = ABORT_SIGNAL_FILE.set(path);
+}
+
+/// Carried in the `io::Error` of a read cut short by the abort signal file.
+#[derive(Debug)]
+struct AbortSignal(PathBuf);
+
+impl fmt::Display for AbortSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} appeared", self.0.display())
+    }
+}
+
+impl std::error::Error for AbortSignal {}
+
+/// Retry `read` while it times out, until `abort` exists or `deadline` passes.
+fn poll_read<F>(mut read: F, abort: &Path, deadline: Option<Instant>) -> io::Result<usize>
+where
+    F: FnMut() -> io::Result<usize>,
+{
+    loop {
+        match read() {
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                if abort.exists() {
+                    return Err(io::Error::other(AbortSignal(abort.to_path_buf())));
+                }
+                if deadline.is_some_and(|d| Instant::now() >= d) {
+                    return Err(e);
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Ask the daemon to stop working on `command_id`, without waiting for a reply.
+pub fn send_cancel(session: &str, command_id: &str) {
+    let cancel = json!({ "id": format!("cancel-{}", command_id), "action": "cancel", "commandId": command_id });
+    let _ = Connection::open(session).and_then(|mut conn| {
+        writeln!(conn, "{}", cancel).and_then(|_| conn.flush()).map_err(CommandError::WriteFailed)
+    });
+}
+
+static SESSION_PREFIX: OnceLock<Option<String>> = OnceLock::new();
+
+/// Set the namespace from `--session-prefix` / `AGENT_BROWSER_SESSION_PREFIX`.
+/// Call once at startup, before any session path is built.
+pub fn set_session_prefix(prefix: Option<String>) {
+    let _ = SESSION_PREFIX.set(prefix.filter(|p| !p.is_empty()));
+}
+
+pub fn session_prefix() -> Option<&'static str> {
+    SESSION_PREFIX.get().and_then(|p| p.as_deref())
+}
+
+/// Daemon that hosts every `--compact-sessions` session as a browser context.
+pub const COMPACT_DAEMON_SESSION: &str = "compact";
+
+static CONTEXT_ID: OnceLock<Option<String>> = OnceLock::new();
+
+/// Set the browser context every command is routed to (`--compact-sessions`).
+/// Call once at startup.
+pub fn set_context_id(context_id: Option<String>) {
+    let _ = CONTEXT_ID.set(context_id);
+}
+
+/// `cmd` tagged with `contextId`, unless it already names one.
+fn with_context_id(cmd: &Value, context_id: Option<&str>) -> Value {
+    let mut cmd = cmd.clone();
+    if let (Some(id), Some(obj)) = (context_id, cmd.as_object_mut()) {
+        obj.entry("contextId").or_insert_with(|| Value::String(id.to_string()));
+    }
+    cmd
+}
+
+/// The name a session has on disk: `<prefix>-<session>` when namespaced.
+pub fn qualify_session(prefix: Option<&str>, session: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}-{}", prefix, session),
+        None => session.to_string(),
+    }
+}
+
+/// The display name for an on-disk session name, or `None` if it belongs to
+/// another namespace.
+pub fn unqualify_session<'a>(prefix: Option<&str>, name: &'a str) -> Option<&'a str> {
+    match prefix {
+        Some(prefix) => name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .filter(|rest| !rest.is_empty()),
+        None => Some(name),
+    }
+}
+
+/// Where session files live: `$XDG_RUNTIME_DIR` on Unix when it names an
+/// existing absolute directory, else the temp dir.
+fn pick_session_dir(xdg_runtime_dir: Option<&OsStr>) -> PathBuf {
+    match xdg_runtime_dir.map(Path::new) {
+        Some(dir) if cfg!(unix) && dir.is_absolute() && dir.is_dir() => dir.to_path_buf(),
+        _ => env::temp_dir(),
+    }
+}
+
+static SESSION_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// The directory holding every session's socket, pid and port files. It is
+/// resolved once and handed to spawned daemons, so both sides agree on it.
+pub fn session_dir() -> &'static Path {
+    SESSION_DIR.get_or_init(|| pick_session_dir(env::var_os("XDG_RUNTIME_DIR").as_deref()))
+}
+
+/// Path of a per-session file (`sock`, `pid`, `port`) in the session dir.
+fn session_path(session: &str, ext: &str) -> PathBuf {
+    let name = qualify_session(session_prefix(), session);
+    session_dir().join(format!("agent-browser-{}.{}", name, ext))
+}
+
 #[cfg(unix)]
-fn get_socket_path(session: &str) -> PathBuf {
-    let tmp = env::temp_dir();
-    tmp.join(format!("agent-browser-{}.sock", session))
+pub fn get_socket_path(session: &str) -> PathBuf {
+    session_path(session, "sock")
 }
 
 fn get_pid_path(session: &str) -> PathBuf {
-    let tmp = env::temp_dir();
-    tmp.join(format!("agent-browser-{}.pid", session))
+    session_path(session, "pid")
 }
 
-#[cfg(windows)]
 fn get_port_path(session: &str) -> PathBuf {
-    let tmp = env::temp_dir();
-    tmp.join(format!("agent-browser-{}.port", session))
+    session_path(session, "port")
+}
+
+/// Name of a session's named pipe on Windows, `\\.\pipe\agent-browser-<session>`.
+#[cfg(windows)]
+fn get_pipe_name(session: &str) -> String {
+    format!(r"\\.\pipe\agent-browser-{}", qualify_session(session_prefix(), session))
 }
 
 #[cfg(windows)]
+fn open_pipe(name: &str) -> io::Result<fs::File> {
+    fs::OpenOptions::new().read(true).write(true).open(name)
+}
+
+/// How the CLI and daemon talk to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// A Unix domain socket, or a named pipe on Windows (the default).
+    Local,
+    /// Localhost TCP (`--tcp`), for poking at the daemon with telnet or nc.
+    /// Any local process can connect. The daemon records its port in the
+    /// session's `.port` file.
+    Tcp,
+}
+
+static TRANSPORT: OnceLock<Transport> = OnceLock::new();
+
+/// Select the transport (`--tcp`). Call once at startup; a daemon started
+/// with one transport can't be reached over the other.
+pub fn set_transport(transport: Transport) {
+    let _ = TRANSPORT.set(transport);
+}
+
+fn transport() -> Transport {
+    TRANSPORT.get().copied().unwrap_or(Transport::Local)
+}
+
 fn get_port_for_session(session: &str) -> u16 {
     let mut hash: i32 = 0;
-    for c in session.chars() {
+    for c in qualify_session(session_prefix(), session).chars() {
         hash = ((hash << 5).wrapping_sub(hash)).wrapping_add(c as i32);
     }
     // Correct logic: first take absolute modulo, then cast to u16
     // Using unsigned_abs() to safely handle i32::MIN
-    49152 + ((hash.unsigned_abs() as u32 % 16383) as u16)
+    49152 + ((hash.unsigned_abs() % 16383) as u16)
+}
+
+/// The port recorded in a `.port` file, if it holds one.
+fn read_port_file(path: &Path) -> Option<u16> {
+    fs::read_to_string(path).ok()?.trim().parse().ok().filter(|&port| port != 0)
+}
+
+/// Where a TCP daemon listens: the port it wrote to its `.port` file, or the
+/// hashed port it tries first when there is no file yet.
+fn daemon_addr(session: &str) -> SocketAddr {
+    let port = read_port_file(&get_port_path(session)).unwrap_or_else(|| get_port_for_session(session));
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// Whether a TCP daemon accepts connections. A `.port` file whose port
+/// refuses them was left behind by a dead daemon, so it is removed and the
+/// hashed port is used from then on.
+fn tcp_daemon_ready(session: &str) -> bool {
+    let port_path = get_port_path(session);
+    let recorded = read_port_file(&port_path);
+    let port = recorded.unwrap_or_else(|| get_port_for_session(session));
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    match TcpStream::connect_timeout(&addr, Duration::from_millis(50)) {
+        Ok(_) => true,
+        Err(e) => {
+            if recorded.is_some() && e.kind() == io::ErrorKind::ConnectionRefused {
+                let _ = fs::remove_file(&port_path);
+            }
+            false
+        }
+    }
+}
+
+/// Why a socket with this owner and mode is unsafe to talk to, if it is.
+/// Connecting takes write permission, so read bits don't matter.
+#[cfg(unix)]
+pub fn socket_permission_problem(owner: u32, mode: u32, uid: u32) -> Option<String> {
+    if owner != uid {
+        return Some(format!("it is owned by uid {}, not you (uid {})", owner, uid));
+    }
+    if mode & 0o022 != 0 {
+        return Some(format!("other users can connect to it (mode {:o}, expected 600)", mode & 0o777));
+    }
+    None
+}
+
+/// The socket may sit in the shared temp dir, so make sure it is ours alone
+/// before sending it commands. A missing socket passes; connecting reports it.
+#[cfg(unix)]
+fn check_socket_owner(path: &Path) -> Result<(), CommandError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    let uid = unsafe { libc::getuid() };
+    match socket_permission_problem(meta.uid(), meta.mode(), uid) {
+        Some(reason) => Err(CommandError::InsecureSocket { path: path.to_path_buf(), reason }),
+        None => Ok(()),
+    }
 }
 
 #[cfg(unix)]
@@ -126,192 +522,1812 @@ fn is_daemon_running(session: &str) -> bool {
 
 #[cfg(windows)]
 fn is_daemon_running(session: &str) -> bool {
-    let pid_path = get_pid_path(session);
-    if !pid_path.exists() {
-        return false;
-    }
-    let port = get_port_for_session(session);
-    TcpStream::connect_timeout(
-        &format!("127.0.0.1:{}", port).parse().unwrap(),
-        Duration::from_millis(100),
-    )
-    .is_ok()
+    get_pid_path(session).exists() && daemon_ready(session)
 }
 
 fn daemon_ready(session: &str) -> bool {
+    if transport() == Transport::Tcp {
+        return tcp_daemon_ready(session);
+    }
     #[cfg(unix)]
     {
         let socket_path = get_socket_path(session);
-        UnixStream::connect(&socket_path).is_ok()
+        UnixStream::connect(&socket_path).is_ok() && check_socket_owner(&socket_path).is_ok()
     }
     #[cfg(windows)]
     {
-        let port = get_port_for_session(session);
-        TcpStream::connect_timeout(
-            &format!("127.0.0.1:{}", port).parse().unwrap(),
-            Duration::from_millis(50),
-        )
-        .is_ok()
+        open_pipe(&get_pipe_name(session)).is_ok()
     }
 }
 
-/// Result of ensure_daemon indicating whether a new daemon was started
-pub struct DaemonResult {
-    /// True if we connected to an existing daemon, false if we started a new one
-    pub already_running: bool,
+/// Returns true if a process with the given pid is alive.
+pub fn is_pid_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    let running = unsafe { libc::kill(pid as i32, 0) == 0 };
+    #[cfg(windows)]
+    let running = unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle != 0 {
+            CloseHandle(handle);
+            true
+        } else {
+            false
+        }
+    };
+    running
 }
 
-pub fn ensure_daemon(
-    session: &str,
-    headed: bool,
-    executable_path: Option<&str>,
-    extensions: &[String],
-) -> Result<DaemonResult, String> {
-    if is_daemon_running(session) && daemon_ready(session) {
-        return Ok(DaemonResult {
-            already_running: true,
-        });
+/// The display name of the session owning `file_name` and the file's
+/// extension, if it is a session file (`pid`, `sock`, `port`) in the `prefix`
+/// namespace.
+fn session_from_file<'a>(file_name: &'a str, prefix: Option<&str>) -> Option<(&'a str, &'a str)> {
+    let (name, ext) = file_name.strip_prefix("agent-browser-")?.rsplit_once('.')?;
+    if !matches!(ext, "pid" | "sock" | "port") {
+        return None;
     }
+    let session = unqualify_session(prefix, name).filter(|s| !s.is_empty())?;
+    Some((session, ext))
+}
 
-    let exe_path = env::current_exe().map_err(|e| e.to_string())?;
-    let exe_dir = exe_path.parent().unwrap();
+/// The display name of the session owning pid file `file_name`, if it is a
+/// session pid file in the `prefix` namespace.
+fn session_from_pid_file<'a>(file_name: &'a str, prefix: Option<&str>) -> Option<&'a str> {
+    session_from_file(file_name, prefix).filter(|&(_, ext)| ext == "pid").map(|(session, _)| session)
+}
 
-    let mut daemon_paths = vec![
-        exe_dir.join("daemon.js"),
-        exe_dir.join("../dist/daemon.js"),
-        PathBuf::from("dist/daemon.js"),
-    ];
+/// Every session with a pid, socket or port file in the session dir, live or
+/// not, sorted and without duplicates.
+pub fn sessions_with_files(prefix: Option<&str>) -> Vec<String> {
+    let mut sessions: Vec<String> = fs::read_dir(session_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    session_from_file(&name, prefix).map(|(session, _)| session.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    sessions.sort();
+    sessions.dedup();
+    sessions
+}
 
-    // Check AGENT_BROWSER_HOME environment variable
-    if let Ok(home) = env::var("AGENT_BROWSER_HOME") {
-        let home_path = PathBuf::from(&home);
-        daemon_paths.insert(0, home_path.join("dist/daemon.js"));
-        daemon_paths.insert(1, home_path.join("daemon.js"));
+/// Whether anything answers on the session's socket, pipe or recorded TCP
+/// port, whichever transport the daemon was started with.
+pub fn daemon_reachable(session: &str) -> bool {
+    if let Some(port) = read_port_file(&get_port_path(session)) {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        if TcpStream::connect_timeout(&addr, Duration::from_millis(50)).is_ok() {
+            return true;
+        }
     }
-
-    let daemon_path = daemon_paths
-        .iter()
-        .find(|p| p.exists())
-        .ok_or("Daemon not found. Set AGENT_BROWSER_HOME environment variable or run from project directory.")?;
-
-    // Spawn daemon as a fully detached background process
     #[cfg(unix)]
     {
-        use std::os::unix::process::CommandExt;
-        
-        let mut cmd = Command::new("node");
-        cmd.arg(daemon_path)
-            .env("AGENT_BROWSER_DAEMON", "1")
-            .env("AGENT_BROWSER_SESSION", session);
-
-        if headed {
-            cmd.env("AGENT_BROWSER_HEADED", "1");
-        }
-
-        if let Some(path) = executable_path {
-            cmd.env("AGENT_BROWSER_EXECUTABLE_PATH", path);
-        }
+        UnixStream::connect(get_socket_path(session)).is_ok()
+    }
+    #[cfg(windows)]
+    {
+        open_pipe(&get_pipe_name(session)).is_ok()
+    }
+}
 
-        if !extensions.is_empty() {
-            cmd.env("AGENT_BROWSER_EXTENSIONS", extensions.join(","));
-        }
+/// What a session's files say about its daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SessionStatus {
+    /// The pid file names a live process, or there is no pid file but the
+    /// daemon still answers on its socket or port.
+    Running,
+    /// The pid file names a process that has exited.
+    StalePid,
+    /// A socket or port file without a pid file, that nothing answers on.
+    OrphanSocket,
+}
 
-        // Create new process group and session to fully detach
-        unsafe {
-            cmd.pre_exec(|| {
-                // Create new session (detach from terminal)
-                libc::setsid();
-                Ok(())
-            });
-        }
+impl SessionStatus {
+    pub fn is_stale(self) -> bool {
+        self != SessionStatus::Running
+    }
+}
 
-        cmd.stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .map_err(|e| format!("Failed to start daemon: {}", e))?;
+/// The status of a session from its pid, socket and port files, or `None`
+/// if it has none.
+pub fn session_status(session: &str) -> Option<SessionStatus> {
+    match daemon_pid(session) {
+        Some(pid) if is_pid_running(pid) => Some(SessionStatus::Running),
+        Some(_) => Some(SessionStatus::StalePid),
+        None if !["sock", "port"].iter().any(|ext| session_path(session, ext).exists()) => None,
+        None if daemon_reachable(session) => Some(SessionStatus::Running),
+        None => Some(SessionStatus::OrphanSocket),
     }
+}
 
-    #[cfg(windows)]
-    {
-        use std::os::windows::process::CommandExt;
-        
-        // On Windows, call node directly. Command::new handles PATH resolution (node.exe or node.cmd)
-        // and automatically quotes arguments containing spaces.
-        let mut cmd = Command::new("node");
-        cmd.arg(daemon_path)
-            .env("AGENT_BROWSER_DAEMON", "1")
-            .env("AGENT_BROWSER_SESSION", session);
+/// Every session with files in the `prefix` namespace and its status, sorted
+/// by name. Unlike `list_sessions`, this includes dead daemons' leftovers.
+pub fn list_session_statuses(prefix: Option<&str>) -> Vec<(String, SessionStatus)> {
+    sessions_with_files(prefix)
+        .into_iter()
+        .filter_map(|session| {
+            let status = session_status(&session)?;
+            Some((session, status))
+        })
+        .collect()
+}
 
-        if headed {
-            cmd.env("AGENT_BROWSER_HEADED", "1");
-        }
+/// Scan the session dir for session pid files whose daemon is still running.
+/// With no `prefix`, sessions from every namespace are listed.
+pub fn list_sessions(prefix: Option<&str>) -> Vec<String> {
+    let tmp = session_dir();
+    let mut sessions: Vec<String> = Vec::new();
 
-        if let Some(path) = executable_path {
-            cmd.env("AGENT_BROWSER_EXECUTABLE_PATH", path);
+    if let Ok(entries) = fs::read_dir(tmp) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Look for socket files (Unix) or pid files
+            if let Some(session_name) = session_from_pid_file(&name, prefix) {
+                // Check if session is actually running
+                let pid_path = tmp.join(&name);
+                if let Ok(pid_str) = fs::read_to_string(&pid_path) {
+                    if let Ok(pid) = pid_str.trim().parse::<u32>() {
+                        if is_pid_running(pid) {
+                            sessions.push(session_name.to_string());
+                        }
+                    }
+                }
+            }
         }
+    }
 
-        if !extensions.is_empty() {
-            cmd.env("AGENT_BROWSER_EXTENSIONS", extensions.join(","));
-        }
+    sessions.sort();
+    sessions
+}
 
-        // CREATE_NO_WINDOW only - DETACHED_PROCESS and CREATE_NO_WINDOW conflict
-        // for console apps like node.exe
-        const CREATE_NO_WINDOW: u32 = 0x08000000;
+/// The pid recorded for a session's daemon, if any.
+pub fn daemon_pid(session: &str) -> Option<u32> {
+    fs::read_to_string(get_pid_path(session)).ok()?.trim().parse().ok()
+}
 
-        cmd.creation_flags(CREATE_NO_WINDOW)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .map_err(|e| format!("Failed to start daemon: {}", e))?;
+/// What `session info` reports about a session's daemon.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub session: String,
+    pub pid: Option<u32>,
+    pub alive: bool,
+    /// The socket path, the named pipe on Windows, or the `.port` file with `--tcp`.
+    pub endpoint: String,
+    /// Whether the daemon accepts connections right now.
+    pub responsive: bool,
+    /// When the daemon process started, in seconds since the Unix epoch.
+    pub started_at: Option<u64>,
+    pub uptime_secs: Option<u64>,
+}
+
+pub fn session_info(session: &str) -> SessionInfo {
+    let pid = daemon_pid(session);
+    let alive = pid.is_some_and(is_pid_running);
+    let endpoint = if transport() == Transport::Tcp {
+        get_port_path(session).display().to_string()
+    } else {
+        #[cfg(unix)]
+        let endpoint = get_socket_path(session).display().to_string();
+        #[cfg(windows)]
+        let endpoint = get_pipe_name(session);
+        endpoint
+    };
+    let started = pid.filter(|_| alive).and_then(process_start_time);
+    SessionInfo {
+        session: session.to_string(),
+        pid,
+        alive,
+        endpoint,
+        responsive: daemon_ready(session),
+        started_at: started.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()),
+        uptime_secs: started.and_then(|t| t.elapsed().ok()).map(|d| d.as_secs()),
     }
+}
+
+/// When a process started, if the platform can tell us.
+#[cfg(target_os = "linux")]
+fn process_start_time(pid: u32) -> Option<SystemTime> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let boot = fs::read_to_string("/proc/stat").ok()?;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let millis = proc_start_millis(&stat, &boot, u64::try_from(ticks).ok()?)?;
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
 
-    for _ in 0..50 {
-        if daemon_ready(session) {
-            return Ok(DaemonResult { already_running: false });
+#[cfg(windows)]
+fn process_start_time(pid: u32) -> Option<SystemTime> {
+    // FILETIMEs count 100ns intervals since 1601-01-01
+    const UNIX_EPOCH_AS_FILETIME: u64 = 116_444_736_000_000_000;
+    let zero = || FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    let (mut created, mut exited, mut kernel, mut user) = (zero(), zero(), zero(), zero());
+    let ok = unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            return None;
         }
-        thread::sleep(Duration::from_millis(100));
-    }
+        let ok = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user);
+        CloseHandle(handle);
+        ok != 0
+    };
+    let intervals = (u64::from(created.dwHighDateTime) << 32) | u64::from(created.dwLowDateTime);
+    let since_epoch = intervals.checked_sub(UNIX_EPOCH_AS_FILETIME).filter(|_| ok)?;
+    Some(UNIX_EPOCH + Duration::from_nanos(since_epoch * 100))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn process_start_time(_pid: u32) -> Option<SystemTime> {
+    None
+}
 
-    Err("Daemon failed to start".to_string())
+/// Milliseconds since the Unix epoch at which the process described by a
+/// `/proc/<pid>/stat` line started. Its start time is in clock ticks since
+/// boot; `/proc/stat`'s `btime` line says when that was.
+#[cfg(any(target_os = "linux", test))]
+fn proc_start_millis(stat: &str, proc_stat: &str, ticks_per_sec: u64) -> Option<u64> {
+    // The command name is parenthesized and may itself contain spaces or
+    // parentheses, so count fields from the last `)`. `starttime` is field
+    // 22 overall, and the state (field 3) is the first one after the name.
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let start_ticks: u64 = rest.split_whitespace().nth(19)?.parse().ok()?;
+    let boot_secs: u64 = proc_stat
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    if ticks_per_sec == 0 {
+        return None;
+    }
+    Some(boot_secs * 1000 + start_ticks * 1000 / ticks_per_sec)
 }
 
-fn connect(session: &str) -> Result<Connection, String> {
+/// Signal a process to exit: SIGTERM, or SIGKILL when `force` is set.
+/// Windows has no polite signal, so both terminate the process.
+pub fn kill_pid(pid: u32, force: bool) {
     #[cfg(unix)]
-    {
-        let socket_path = get_socket_path(session);
-        UnixStream::connect(&socket_path)
-            .map(Connection::Unix)
-            .map_err(|e| format!("Failed to connect: {}", e))
+    unsafe {
+        libc::kill(pid as i32, if force { libc::SIGKILL } else { libc::SIGTERM });
     }
     #[cfg(windows)]
-    {
-        let port = get_port_for_session(session);
-        TcpStream::connect(format!("127.0.0.1:{}", port))
-            .map(Connection::Tcp)
-            .map_err(|e| format!("Failed to connect: {}", e))
+    unsafe {
+        let _ = force;
+        let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if handle != 0 {
+            TerminateProcess(handle, 1);
+            CloseHandle(handle);
+        }
     }
 }
 
-pub fn send_command(cmd: Value, session: &str) -> Result<Response, String> {
-    let mut stream = connect(session)?;
+/// Remove a session's pid, socket and port files, and its version handshake
+/// record. Files that are already gone, or vanish while we look, are fine.
+pub fn remove_session_files(session: &str) {
+    for ext in ["pid", "sock", "port", "version"] {
+        let _ = fs::remove_file(session_path(session, ext));
+    }
+}
 
-    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
-    stream.set_write_timeout(Some(Duration::from_secs(5))).ok();
+/// Clear out the files of a daemon that died without cleaning up after
+/// itself: its pid file names a process that no longer exists, or there is
+/// no pid file and nothing answers on its socket or port. Returns whether
+/// anything was stale.
+pub fn clean_stale_session(session: &str) -> bool {
+    let stale = session_status(session).is_some_and(SessionStatus::is_stale);
+    if stale {
+        remove_session_files(session);
+    }
+    stale
+}
 
-    let mut json_str = serde_json::to_string(&cmd).map_err(|e| e.to_string())?;
-    json_str.push('\n');
+/// The version the daemon reported, if `record` (the session's `.version`
+/// file) was written for daemon `pid`.
+fn recorded_version(record: &str, pid: u32) -> Option<&str> {
+    let (recorded_pid, version) = record.trim().split_once(' ')?;
+    (recorded_pid.parse() == Ok(pid)).then_some(version)
+}
 
-    stream
+/// The version reported by a daemon's answer to `{"action":"version"}`. A
+/// daemon that rejects the action predates the handshake.
+fn reported_version(resp: &Response) -> String {
+    match resp.data.as_ref().and_then(|d| d["version"].as_str()) {
+        Some(version) if resp.success => version.to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// The daemon's version when it differs from this CLI's, which happens when a
+/// daemon from an older install is still running. The answer is recorded per
+/// daemon pid, so each daemon is asked once rather than on every command.
+pub fn daemon_version_mismatch(session: &str) -> Option<String> {
+    let record_path = session_path(session, "version");
+    let pid = daemon_pid(session);
+    let recorded = pid
+        .zip(fs::read_to_string(&record_path).ok())
+        .and_then(|(pid, record)| recorded_version(&record, pid).map(String::from));
+    let version = match recorded {
+        Some(version) => version,
+        None => {
+            let cmd = json!({ "id": format!("version-{}", std::process::id()), "action": "version" });
+            let version = reported_version(&send_command(cmd, session).ok()?);
+            if let Some(pid) = pid {
+                let _ = fs::write(&record_path, format!("{} {}", pid, version));
+            }
+            version
+        }
+    };
+    (version != env!("CARGO_PKG_VERSION")).then_some(version)
+}
+
+/// Wait up to `timeout` for the session's daemon to exit on its own, then
+/// remove anything it left behind. Returns whether it exited; a daemon that
+/// is still running keeps its files.
+pub fn wait_for_shutdown(session: &str, timeout: Duration) -> bool {
+    let exited = poll_until(timeout, || !is_daemon_running(session)).is_ok();
+    if exited {
+        remove_session_files(session);
+    }
+    exited
+}
+
+/// Check a session name is safe to put in a file name: ASCII letters,
+/// digits, `-`, `_` and `.`, not starting with a dot.
+pub fn validate_session_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Session name can't be empty".to_string());
+    }
+    if name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(format!(
+            "Invalid session name '{}': use letters, digits, '-', '_' and '.' (not leading)",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Move a stopped session's pid, socket and port files to a new name. A
+/// running daemon keeps using the paths it started with, so it is refused.
+/// If a rename fails partway, the files already moved are moved back.
+pub fn rename_session(old: &str, new: &str) -> Result<(), String> {
+    validate_session_name(new)?;
+    if old == new {
+        return Err(format!("Session is already named '{}'", new));
+    }
+    if daemon_pid(old).is_some_and(is_pid_running) || daemon_reachable(old) {
+        return Err(format!(
+            "Session '{}' is running; stop it first with 'agent-browser --session {} close'",
+            old, old
+        ));
+    }
+    const EXTS: [&str; 3] = ["pid", "sock", "port"];
+    let present: Vec<&str> = EXTS.into_iter().filter(|ext| session_path(old, ext).exists()).collect();
+    if present.is_empty() {
+        return Err(format!("No session '{}'", old));
+    }
+    if EXTS.iter().any(|ext| session_path(new, ext).exists()) {
+        return Err(format!("Session '{}' already exists", new));
+    }
+    for (done, ext) in present.iter().enumerate() {
+        if let Err(e) = fs::rename(session_path(old, ext), session_path(new, ext)) {
+            for ext in &present[..done] {
+                let _ = fs::rename(session_path(new, ext), session_path(old, ext));
+            }
+            return Err(format!("Could not rename session '{}': {}", old, e));
+        }
+    }
+    // The version record belongs to a daemon that is gone
+    let _ = fs::remove_file(session_path(old, "version"));
+    Ok(())
+}
+
+/// How far `stop_daemon` had to go before the daemon exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escalation {
+    Graceful,
+    Terminate,
+    Kill,
+}
+
+impl Escalation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Escalation::Graceful => "graceful",
+            Escalation::Terminate => "terminate",
+            Escalation::Kill => "kill",
+        }
+    }
+}
+
+/// How long a daemon gets to exit after SIGTERM before it is killed.
+const TERMINATE_GRACE: Duration = Duration::from_secs(2);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, PartialEq, Eq)]
+enum StopStep {
+    Done,
+    Wait,
+    Terminate,
+    Kill,
+}
+
+/// What to do about a daemon `elapsed` after it was asked to close: wait out
+/// `timeout`, then SIGTERM, then SIGKILL once the grace period has passed too.
+fn stop_step(running: bool, elapsed: Duration, timeout: Duration) -> StopStep {
+    if !running {
+        StopStep::Done
+    } else if elapsed < timeout {
+        StopStep::Wait
+    } else if elapsed < timeout + TERMINATE_GRACE {
+        StopStep::Terminate
+    } else {
+        StopStep::Kill
+    }
+}
+
+/// Wait for daemon `pid` to exit after a graceful `close`, escalating if it
+/// doesn't, then remove the session's files.
+pub fn stop_daemon(session: &str, pid: Option<u32>, timeout: Duration) -> Escalation {
+    let start = Instant::now();
+    let mut escalation = Escalation::Graceful;
+    if let Some(pid) = pid {
+        loop {
+            match stop_step(is_pid_running(pid), start.elapsed(), timeout) {
+                StopStep::Done => break,
+                StopStep::Wait => {}
+                StopStep::Terminate => {
+                    if escalation == Escalation::Graceful {
+                        kill_pid(pid, false);
+                        escalation = Escalation::Terminate;
+                    }
+                }
+                StopStep::Kill => {
+                    kill_pid(pid, true);
+                    escalation = Escalation::Kill;
+                    break;
+                }
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+    }
+    remove_session_files(session);
+    escalation
+}
+
+/// Default cap on live daemons (`--max-sessions` / `AGENT_BROWSER_MAX_SESSIONS`).
+pub const DEFAULT_MAX_SESSIONS: usize = 20;
+
+static MAX_SESSIONS: OnceLock<usize> = OnceLock::new();
+
+/// Set how many live daemons may exist before `ensure_daemon` refuses to
+/// spawn another. Call once at startup; defaults to `DEFAULT_MAX_SESSIONS`.
+pub fn set_max_sessions(max: usize) {
+    let _ = MAX_SESSIONS.set(max);
+}
+
+/// Refuse to spawn a daemon once `live` sessions already reach `max`.
+fn check_session_cap(max: usize, live: impl FnOnce() -> usize) -> Result<(), String> {
+    let live = live();
+    if live >= max {
+        return Err(format!(
+            "Refusing to start another daemon: {} sessions are already running (limit {}). Close some with 'agent-browser --session <name> close', or raise --max-sessions.",
+            live, max
+        ));
+    }
+    Ok(())
+}
+
+/// Result of ensure_daemon indicating whether a new daemon was started
+pub struct DaemonResult {
+    /// True if we connected to an existing daemon, false if we started a new one
+    pub already_running: bool,
+}
+
+/// Like `ensure_daemon`, but never spawns: errors unless a daemon is already up.
+pub fn require_running_daemon(session: &str) -> Result<DaemonResult, String> {
+    if is_daemon_running(session) && daemon_ready(session) {
+        Ok(DaemonResult {
+            already_running: true,
+        })
+    } else {
+        Err(format!("No running daemon for session {}", session))
+    }
+}
+
+pub fn ensure_daemon(
+    session: &str,
+    headed: bool,
+    executable_path: Option<&str>,
+    extensions: &[String],
+) -> Result<DaemonResult, String> {
+    if is_daemon_running(session) && daemon_ready(session) {
+        log::verbose(format!("Daemon for session {} is already running", session));
+        return Ok(DaemonResult {
+            already_running: true,
+        });
+    }
+
+    // A crashed daemon leaves its socket behind for the new one to trip over
+    clean_stale_session(session);
+
+    // A foreign socket can't be replaced, and must not be used
+    #[cfg(unix)]
+    if transport() == Transport::Local {
+        check_socket_owner(&get_socket_path(session)).map_err(|e| e.to_string())?;
+    }
+
+    // Count every namespace: the cap guards the machine, not one prefix
+    check_session_cap(*MAX_SESSIONS.get().unwrap_or(&DEFAULT_MAX_SESSIONS), || list_sessions(None).len())?;
+
+    let exe_path = env::current_exe().map_err(|e| e.to_string())?;
+    let exe_dir = exe_path.parent().unwrap();
+
+    let mut daemon_paths = vec![
+        exe_dir.join("daemon.js"),
+        exe_dir.join("../dist/daemon.js"),
+        PathBuf::from("dist/daemon.js"),
+    ];
+
+    // Check AGENT_BROWSER_HOME environment variable
+    if let Ok(home) = env::var("AGENT_BROWSER_HOME") {
+        let home_path = PathBuf::from(&home);
+        daemon_paths.insert(0, home_path.join("dist/daemon.js"));
+        daemon_paths.insert(1, home_path.join("daemon.js"));
+    }
+
+    let daemon_path = daemon_paths
+        .iter()
+        .find(|p| p.exists())
+        .ok_or("Daemon not found. Set AGENT_BROWSER_HOME environment variable or run from project directory.")?;
+
+    let runtime = Runtime::from_env()?;
+    let runtime_args = parse_runtime_args(&env::var("AGENT_BROWSER_NODE_ARGS").unwrap_or_default())?;
+    let program = match runtime {
+        Runtime::Node => node_program(NODE_PATH.get().and_then(|p| p.as_deref()))?,
+        Runtime::Bun | Runtime::Deno => PathBuf::from(runtime.name()),
+    };
+    let spawn_error = |e: io::Error| match e.kind() {
+        io::ErrorKind::NotFound if program == Path::new("node") => {
+            "Failed to start daemon: node not found on PATH. Install Node.js or point --node / AGENT_BROWSER_NODE at it."
+                .to_string()
+        }
+        io::ErrorKind::NotFound if runtime != Runtime::Node => format!(
+            "Failed to start daemon: {} not found on PATH (AGENT_BROWSER_RUNTIME={})",
+            program.display(),
+            runtime.name()
+        ),
+        _ => format!("Failed to start daemon with {}: {}", program.display(), e),
+    };
+
+    let log = DAEMON_LOG.get().and_then(|p| p.as_deref());
+
+    // Spawn daemon as a fully detached background process
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        
+        let mut cmd = daemon_command(runtime, &program, &runtime_args, daemon_path);
+        cmd.env("AGENT_BROWSER_DAEMON", "1")
+            .env("AGENT_BROWSER_SOCKET_DIR", session_dir())
+            .env("AGENT_BROWSER_SESSION", qualify_session(session_prefix(), session));
+
+        if transport() == Transport::Tcp {
+            cmd.env("AGENT_BROWSER_TRANSPORT", "tcp");
+        }
+
+        if headed {
+            cmd.env("AGENT_BROWSER_HEADED", "1");
+        }
+
+        pass_idle_timeout(&mut cmd);
+
+        if let Some(path) = executable_path {
+            cmd.env("AGENT_BROWSER_EXECUTABLE_PATH", path);
+        }
+
+        if !extensions.is_empty() {
+            cmd.env("AGENT_BROWSER_EXTENSIONS", extensions.join(","));
+        }
+
+        // Create new process group and session to fully detach
+        unsafe {
+            cmd.pre_exec(|| {
+                // Create new session (detach from terminal)
+                libc::setsid();
+                Ok(())
+            });
+        }
+
+        log::verbose(format!("Starting daemon: {}", describe_command(&cmd)));
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(daemon_stderr(log)?)
+            .spawn()
+            .map_err(spawn_error)?;
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        
+        // On Windows, call the runtime directly. Command::new handles PATH resolution (node.exe or node.cmd)
+        // and automatically quotes arguments containing spaces.
+        let mut cmd = daemon_command(runtime, &program, &runtime_args, daemon_path);
+        cmd.env("AGENT_BROWSER_DAEMON", "1")
+            .env("AGENT_BROWSER_SOCKET_DIR", session_dir())
+            .env("AGENT_BROWSER_SESSION", qualify_session(session_prefix(), session));
+
+        if transport() == Transport::Tcp {
+            cmd.env("AGENT_BROWSER_TRANSPORT", "tcp");
+        }
+
+        if headed {
+            cmd.env("AGENT_BROWSER_HEADED", "1");
+        }
+
+        pass_idle_timeout(&mut cmd);
+
+        if let Some(path) = executable_path {
+            cmd.env("AGENT_BROWSER_EXECUTABLE_PATH", path);
+        }
+
+        if !extensions.is_empty() {
+            cmd.env("AGENT_BROWSER_EXTENSIONS", extensions.join(","));
+        }
+
+        // CREATE_NO_WINDOW only - DETACHED_PROCESS and CREATE_NO_WINDOW conflict
+        // for console apps like node.exe
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+        log::verbose(format!("Starting daemon: {}", describe_command(&cmd)));
+        cmd.creation_flags(CREATE_NO_WINDOW)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(daemon_stderr(log)?)
+            .spawn()
+            .map_err(spawn_error)?;
+    }
+
+    // Wait for the new daemon within the startup budget; independent of
+    // `--timeout`, which only applies once a command has been sent.
+    let budget = STARTUP_TIMEOUT.get().copied().unwrap_or(DEFAULT_STARTUP_TIMEOUT);
+    log::verbose(format!("Waiting up to {:.1}s for the daemon to start", budget.as_secs_f64()));
+    match poll_until(budget, || daemon_ready(session)) {
+        Ok(()) => Ok(DaemonResult { already_running: false }),
+        Err(waited) => Err(startup_failure(waited, log)),
+    }
+}
+
+/// The environment a command sets and its command line, for `--verbose`.
+fn describe_command(cmd: &Command) -> String {
+    let env = cmd.get_envs().filter_map(|(key, value)| {
+        Some(format!("{}={}", key.to_string_lossy(), value?.to_string_lossy()))
+    });
+    let line = std::iter::once(cmd.get_program()).chain(cmd.get_args()).map(|s| s.to_string_lossy().into_owned());
+    env.chain(line).collect::<Vec<_>>().join(" ")
+}
+
+fn startup_failure(waited: Duration, log: Option<&Path>) -> String {
+    let mut msg = format!(
+        "Daemon failed to start within {:.1}s (raise it with --startup-timeout or AGENT_BROWSER_STARTUP_TIMEOUT)",
+        waited.as_secs_f64()
+    );
+    if let Some(path) = log {
+        msg.push_str(&format!(". Its output is in {}", path.display()));
+    }
+    msg
+}
+
+static IDLE_TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// Make newly spawned daemons exit after this long without commands
+/// (`AGENT_BROWSER_IDLE_TIMEOUT`); `None` keeps them running (`--keep-alive`).
+/// Call once at startup. A daemon keeps the value it was spawned with.
+pub fn set_idle_timeout(timeout: Option<Duration>) {
+    let _ = IDLE_TIMEOUT.set(timeout);
+}
+
+/// Hand the idle timeout to a daemon about to be spawned, or make sure it
+/// doesn't inherit one from the environment.
+fn pass_idle_timeout(cmd: &mut Command) {
+    match IDLE_TIMEOUT.get().copied().flatten() {
+        Some(idle) => cmd.env("AGENT_BROWSER_IDLE_TIMEOUT", idle.as_secs_f64().to_string()),
+        None => cmd.env_remove("AGENT_BROWSER_IDLE_TIMEOUT"),
+    };
+}
+
+static DAEMON_LOG: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Send a newly spawned daemon's stderr to this file (`--daemon-log` /
+/// `AGENT_BROWSER_LOG`). Call once at startup; by default it is discarded.
+pub fn set_daemon_log(path: Option<PathBuf>) {
+    let _ = DAEMON_LOG.set(path);
+}
+
+/// Where the daemon's stderr goes: appended to the log file when there is
+/// one, so earlier failed starts stay readable, else nowhere.
+fn daemon_stderr(log: Option<&Path>) -> Result<Stdio, String> {
+    let Some(path) = log else {
+        return Ok(Stdio::null());
+    };
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(Stdio::from)
+        .map_err(|e| format!("Could not open daemon log {}: {}", path.display(), e))
+}
+
+static NODE_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Set the Node.js interpreter that runs the daemon (`--node` /
+/// `AGENT_BROWSER_NODE`). Call once at startup; defaults to `node` on PATH.
+pub fn set_node_path(path: Option<PathBuf>) {
+    let _ = NODE_PATH.set(path);
+}
+
+/// The interpreter to spawn the daemon with. An explicit path must exist,
+/// so a typo is reported as such rather than as a failed spawn.
+fn node_program(configured: Option<&Path>) -> Result<PathBuf, String> {
+    match configured {
+        None => Ok(PathBuf::from("node")),
+        Some(path) if path.is_file() => Ok(path.to_path_buf()),
+        Some(path) => Err(format!(
+            "Node.js not found at {} (from --node / AGENT_BROWSER_NODE)",
+            path.display()
+        )),
+    }
+}
+
+/// The JavaScript runtime that runs the daemon (`AGENT_BROWSER_RUNTIME`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Node,
+    Bun,
+    Deno,
+}
+
+impl Runtime {
+    pub fn parse(name: &str) -> Result<Runtime, String> {
+        match name {
+            "node" => Ok(Runtime::Node),
+            "bun" => Ok(Runtime::Bun),
+            "deno" => Ok(Runtime::Deno),
+            _ => Err(format!("Invalid AGENT_BROWSER_RUNTIME '{}' (expected node, bun or deno)", name)),
+        }
+    }
+
+    fn from_env() -> Result<Runtime, String> {
+        match env::var("AGENT_BROWSER_RUNTIME") {
+            Ok(name) if !name.is_empty() => Runtime::parse(&name),
+            _ => Ok(Runtime::Node),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Runtime::Node => "node",
+            Runtime::Bun => "bun",
+            Runtime::Deno => "deno",
+        }
+    }
+}
+
+/// Split `AGENT_BROWSER_NODE_ARGS` on whitespace. Every piece must be an
+/// option: anything else would be taken for the script. There is no shell
+/// here to strip quotes, so a quote means the value was written for one.
+pub fn parse_runtime_args(text: &str) -> Result<Vec<String>, String> {
+    text.split_whitespace()
+        .map(|arg| {
+            if arg.contains(['"', '\'', '`']) {
+                Err(format!("Invalid AGENT_BROWSER_NODE_ARGS: quotes aren't supported ({})", arg))
+            } else if !arg.starts_with('-') {
+                Err(format!("Invalid AGENT_BROWSER_NODE_ARGS: '{}' is not an option", arg))
+            } else {
+                Ok(arg.to_string())
+            }
+        })
+        .collect()
+}
+
+/// The command that runs `daemon_path` under `runtime`, with `runtime_args`
+/// (`AGENT_BROWSER_NODE_ARGS`) before the script. Deno is sandboxed by
+/// default, and the daemon needs the filesystem, network, env and subprocesses.
+/// `NODE_OPTIONS` is inherited along with the rest of the environment.
+fn daemon_command(runtime: Runtime, program: &Path, runtime_args: &[String], daemon_path: &Path) -> Command {
+    let mut cmd = Command::new(program);
+    if runtime == Runtime::Deno {
+        cmd.args(["run", "-A"]);
+    }
+    cmd.args(runtime_args).arg(daemon_path);
+    cmd
+}
+
+/// How long `ensure_daemon` waits for a newly spawned daemon by default.
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First pause between readiness checks; doubles up to `STARTUP_POLL_MAX`.
+const STARTUP_POLL_MIN: Duration = Duration::from_millis(25);
+const STARTUP_POLL_MAX: Duration = Duration::from_millis(250);
+
+static STARTUP_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Set how long to wait for a spawned daemon to accept connections
+/// (`--startup-timeout` / `AGENT_BROWSER_STARTUP_TIMEOUT`). Call once at
+/// startup; defaults to `DEFAULT_STARTUP_TIMEOUT`.
+pub fn set_startup_timeout(timeout: Duration) {
+    let _ = STARTUP_TIMEOUT.set(timeout);
+}
+
+/// Check `ready` until it holds or `budget` runs out, backing off from
+/// `STARTUP_POLL_MIN` to `STARTUP_POLL_MAX` so fast starts return quickly.
+/// On timeout, returns how long it waited.
+fn poll_until<F: FnMut() -> bool>(budget: Duration, mut ready: F) -> Result<(), Duration> {
+    let start = Instant::now();
+    let mut delay = STARTUP_POLL_MIN;
+    loop {
+        if ready() {
+            return Ok(());
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= budget {
+            return Err(elapsed);
+        }
+        thread::sleep(delay.min(budget - elapsed));
+        delay = (delay * 2).min(STARTUP_POLL_MAX);
+    }
+}
+
+static MAX_CONNECT_ATTEMPTS: OnceLock<u32> = OnceLock::new();
+
+/// Attempts `connect` makes by default: enough to ride out a daemon restart
+/// (100 + 200 + 400 + 800ms of backoff, about 1.5s).
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 5;
+
+/// Pause before the first retry; doubles after each further failure.
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Set how many times `connect` tries a daemon that refuses connections
+/// (`--max-connect-attempts` / `--connect-retries`). Call once at startup;
+/// defaults to `DEFAULT_CONNECT_ATTEMPTS`.
+pub fn set_max_connect_attempts(attempts: u32) {
+    let _ = MAX_CONNECT_ATTEMPTS.set(attempts.max(1));
+}
+
+/// Whether a failed connect is worth retrying: the socket is missing or
+/// nobody is accepting on it yet, as while a daemon restarts. Nothing has
+/// been sent at this point, so retrying can't repeat a command.
+fn is_retryable_connect_error(e: &io::Error) -> bool {
+    // ERROR_PIPE_BUSY: every instance of the daemon's named pipe is in use
+    #[cfg(windows)]
+    if e.raw_os_error() == Some(231) {
+        return true;
+    }
+    matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound)
+}
+
+/// Exponential backoff: `base`, `2 * base`, `4 * base`, ... for retry 1, 2, 3, ...
+fn retry_delay(base: Duration, retry: u32) -> Duration {
+    base.saturating_mul(1 << (retry.saturating_sub(1)).min(16))
+}
+
+/// Call `attempt` up to `max_attempts` times, backing off exponentially from
+/// `delay` between retryable failures, and return the first success or the
+/// last error. Other errors are returned straight away.
+fn connect_with_retries<T, F>(max_attempts: u32, delay: Duration, mut attempt: F) -> Result<T, CommandError>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let max_attempts = max_attempts.max(1);
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        match attempt() {
+            Ok(conn) => return Ok(conn),
+            Err(e) if tries >= max_attempts || !is_retryable_connect_error(&e) => {
+                return Err(CommandError::connect(tries, e))
+            }
+            Err(e) => {
+                let wait = retry_delay(delay, tries);
+                log::verbose(format!(
+                    "Connect attempt {}/{} failed: {}; retrying in {}ms",
+                    tries,
+                    max_attempts,
+                    e,
+                    wait.as_millis()
+                ));
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+fn connect(session: &str) -> Result<Connection, CommandError> {
+    let attempts = MAX_CONNECT_ATTEMPTS.get().copied().unwrap_or(DEFAULT_CONNECT_ATTEMPTS);
+    if transport() == Transport::Tcp {
+        log::verbose(format!("Connecting to session {} at {}", session, daemon_addr(session)));
+        // Re-read the port file on each try: a restarting daemon may move
+        return connect_with_retries(attempts, CONNECT_RETRY_DELAY, || {
+            TcpStream::connect(daemon_addr(session)).map(Connection::Tcp)
+        });
+    }
+    #[cfg(unix)]
+    {
+        let socket_path = get_socket_path(session);
+        log::verbose(format!("Connecting to session {} at {}", session, socket_path.display()));
+        let conn = connect_with_retries(attempts, CONNECT_RETRY_DELAY, || {
+            UnixStream::connect(&socket_path).map(Connection::Unix)
+        })?;
+        check_socket_owner(&socket_path)?;
+        Ok(conn)
+    }
+    #[cfg(windows)]
+    {
+        let pipe_name = get_pipe_name(session);
+        log::verbose(format!("Connecting to session {} at {}", session, pipe_name));
+        connect_with_retries(attempts, CONNECT_RETRY_DELAY, || open_pipe(&pipe_name).map(Connection::Pipe))
+    }
+}
+
+pub fn send_command(cmd: Value, session: &str) -> Result<Response, CommandError> {
+    send_command_streaming(cmd, session, |_| {})
+}
+
+/// Send a command and hand any streamed items to `on_item` as they arrive.
+///
+/// The daemon may emit intermediate lines marked `"partial": true` before the
+/// final response; each one is passed to `on_item` immediately as a `Response`
+/// (an item without `success` counts as successful). The first line without
+/// the marker is parsed as the terminating `Response`.
+///
+/// Only `network_tail` emits partial lines (one per finished request); every
+/// other command arrives as a single final response, and `jsonl-stream` still
+/// splits an array in it into lines (see `StreamWriter::finish`).
+pub fn send_command_streaming<F>(cmd: Value, session: &str, on_item: F) -> Result<Response, CommandError>
+where
+    F: FnMut(&Response),
+{
+    let mut reader = BufReader::new(Connection::open(session)?);
+    exchange(&mut reader, &cmd, on_item)
+}
+
+/// How long to wait for a command's response unless `--timeout` says otherwise.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long writing a command to the daemon may block.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static READ_TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// Set how long to wait for each command's response (`--timeout` /
+/// `AGENT_BROWSER_TIMEOUT`); `None` waits indefinitely. Call once at startup;
+/// defaults to `DEFAULT_READ_TIMEOUT`.
+///
+/// This only bounds the wait for a response on an established connection. The
+/// readiness poll in `ensure_daemon` after spawning a daemon is separate and
+/// keeps its own limit.
+pub fn set_read_timeout(timeout: Option<Duration>) {
+    let _ = READ_TIMEOUT.set(timeout);
+}
+
+/// Map a `--timeout` value in seconds to a read timeout; `0` disables it.
+pub fn read_timeout_from_secs(secs: f64) -> Option<Duration> {
+    (secs > 0.0).then(|| Duration::from_secs_f64(secs))
+}
+
+fn default_read_timeout() -> Option<Duration> {
+    READ_TIMEOUT.get().copied().unwrap_or(Some(DEFAULT_READ_TIMEOUT))
+}
+
+static COMMAND_READ_TIMEOUT: OnceLock<(String, Option<Duration>)> = OnceLock::new();
+
+/// Give the command with id `id` its own read timeout, as picked by
+/// `commands::transport_timeout`. Call once at startup; other commands sent in
+/// the same invocation, such as a launch, keep the default.
+pub fn set_command_read_timeout(id: &str, timeout: Option<Duration>) {
+    let _ = COMMAND_READ_TIMEOUT.set((id.to_string(), timeout));
+}
+
+fn read_timeout_for(cmd: &Value) -> Option<Duration> {
+    match COMMAND_READ_TIMEOUT.get() {
+        Some((id, timeout)) if cmd.get("id").and_then(Value::as_str) == Some(id.as_str()) => *timeout,
+        _ => default_read_timeout(),
+    }
+}
+
+/// Send one command on a connection kept open across commands (see
+/// `Connection::open`), re-arming its timeouts first so each command gets the
+/// full read timeout.
+pub fn send_on(conn: &mut BufReader<Connection>, cmd: &Value) -> Result<Response, CommandError> {
+    conn.get_ref().arm_timeouts_for(cmd);
+    exchange(conn, cmd, |_| {})
+}
+
+/// Write one command to an open connection and read lines until its final response.
+pub fn exchange<S, F>(reader: &mut BufReader<S>, cmd: &Value, mut on_item: F) -> Result<Response, CommandError>
+where
+    S: Read + Write,
+    F: FnMut(&Response),
+{
+    let cmd = with_context_id(cmd, CONTEXT_ID.get().and_then(|c| c.as_deref()));
+    let mut json_str = cmd.to_string();
+    json_str.push('\n');
+
+    let stream = reader.get_mut();
+    stream
         .write_all(json_str.as_bytes())
-        .map_err(|e| format!("Failed to send: {}", e))?;
+        .and_then(|_| stream.flush())
+        .map_err(CommandError::WriteFailed)?;
+
+    loop {
+        let value = read_response(reader)?;
+        if value.get("partial").and_then(|v| v.as_bool()) == Some(true) {
+            on_item(&Response {
+                id: value.get("id").and_then(|v| v.as_str()).map(String::from),
+                success: value.get("success").and_then(|v| v.as_bool()).unwrap_or(true),
+                data: value.get("data").cloned(),
+                error: value.get("error").and_then(|v| v.as_str()).map(String::from),
+                error_code: value.get("errorCode").and_then(|v| v.as_str()).map(String::from),
+                metadata: value.get("metadata").cloned(),
+            });
+            continue;
+        }
+        let resp: Response = serde_json::from_value(value).map_err(CommandError::InvalidResponse)?;
+        return check_response_id(&cmd, resp);
+    }
+}
+
+/// Reject a response that answers some other request. Responses without an
+/// id, or to commands sent without one, can't be checked and pass.
+fn check_response_id(cmd: &Value, resp: Response) -> Result<Response, CommandError> {
+    match (cmd.get("id").and_then(|v| v.as_str()), resp.id.as_deref()) {
+        (Some(expected), Some(got)) if expected != got => Err(CommandError::MismatchedResponse {
+            expected: expected.to_string(),
+            got: got.to_string(),
+        }),
+        _ => Ok(resp),
+    }
+}
+
+/// Read the next complete JSON value sent by the daemon.
+///
+/// Responses are normally one line each, but a value is only finished when it
+/// parses: one that arrives over several reads, or whose text contains raw
+/// newlines (large DOM dumps, pretty-printed data), is read until its closing
+/// brace rather than cut off at the first newline.
+pub fn read_response<R: BufRead>(reader: &mut R) -> Result<Value, CommandError> {
+    match serde_json::Deserializer::from_reader(reader).into_iter::<Value>().next() {
+        Some(Ok(value)) => Ok(value),
+        None => Err(CommandError::ConnectionClosed),
+        Some(Err(e)) if e.is_eof() => Err(CommandError::ConnectionClosed),
+        Some(Err(e)) if e.is_io() => Err(CommandError::read(e.into())),
+        Some(Err(e)) => Err(CommandError::InvalidResponse(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_timeout_from_secs() {
+        assert_eq!(read_timeout_from_secs(0.0), None);
+        assert_eq!(read_timeout_from_secs(90.0), Some(Duration::from_secs(90)));
+        assert_eq!(read_timeout_from_secs(0.5), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_require_running_daemon_does_not_spawn() {
+        let session = format!("no-spawn-test-{}", std::process::id());
+        let err = require_running_daemon(&session).err().unwrap();
+        assert_eq!(err, format!("No running daemon for session {}", session));
+        assert!(!get_pid_path(&session).exists());
+    }
+
+    #[test]
+    fn test_connect_retries_give_up_after_max_attempts() {
+        let mut calls = 0;
+        let result: Result<(), CommandError> = connect_with_retries(3, Duration::ZERO, || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))
+        });
+        assert_eq!(calls, 3);
+        let err = result.unwrap_err();
+        assert_eq!(err.to_string(), "Failed to connect after 3 attempts: refused");
+        assert_eq!(err.kind(), "connection_refused");
+    }
+
+    #[test]
+    fn test_connect_retries_stop_on_success() {
+        let mut calls = 0;
+        let result = connect_with_retries(5, Duration::ZERO, || {
+            calls += 1;
+            if calls < 2 {
+                Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_connect_single_attempt_keeps_plain_error() {
+        let mut calls = 0;
+        let result: Result<(), CommandError> = connect_with_retries(1, Duration::ZERO, || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::NotFound, "no socket"))
+        });
+        assert_eq!(calls, 1);
+        let err = result.unwrap_err();
+        assert_eq!(err.to_string(), "Failed to connect: no socket");
+        assert_eq!(err.kind(), "daemon_not_found");
+    }
+
+    #[test]
+    fn test_connect_does_not_retry_other_errors() {
+        let mut calls = 0;
+        let result: Result<(), CommandError> = connect_with_retries(5, Duration::ZERO, || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+        });
+        assert_eq!(calls, 1);
+        assert_eq!(result.unwrap_err().to_string(), "Failed to connect: denied");
+    }
+
+    #[test]
+    fn test_connect_retries_missing_socket() {
+        let mut calls = 0;
+        let result = connect_with_retries(5, Duration::ZERO, || {
+            calls += 1;
+            if calls < 4 {
+                Err(io::Error::new(io::ErrorKind::NotFound, "no socket"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 4);
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_exponentially() {
+        let base = Duration::from_millis(100);
+        let delays: Vec<u128> = (1..=4).map(|n| retry_delay(base, n).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800]);
+        assert_eq!(delays.iter().sum::<u128>(), 1500);
+    }
+
+    /// A scripted daemon: `reply` is what it writes back, `Err` for a read error.
+    struct Scripted {
+        reply: Result<std::io::Cursor<Vec<u8>>, io::ErrorKind>,
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match &mut self.reply {
+                Ok(cursor) => cursor.read(buf),
+                Err(kind) => Err(io::Error::new(*kind, "scripted")),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn exchange_with(reply: Result<&str, io::ErrorKind>) -> Result<Response, CommandError> {
+        let stream = Scripted {
+            reply: reply.map(|r| std::io::Cursor::new(r.as_bytes().to_vec())),
+        };
+        exchange(&mut BufReader::new(stream), &json!({ "id": "1", "action": "url" }), |_| {})
+    }
+
+    #[test]
+    fn test_exchange_error_variants() {
+        let err = exchange_with(Err(io::ErrorKind::WouldBlock)).unwrap_err();
+        assert!(matches!(err, CommandError::Timeout(_)));
+        assert_eq!(err.kind(), "timeout");
+        assert!(err.to_string().starts_with("Failed to read: "));
+
+        let err = exchange_with(Err(io::ErrorKind::ConnectionReset)).unwrap_err();
+        assert!(matches!(err, CommandError::ReadFailed(_)));
+
+        let err = exchange_with(Ok("")).unwrap_err();
+        assert!(matches!(err, CommandError::ConnectionClosed));
+        assert_eq!(err.to_string(), "Connection closed before response");
+
+        let err = exchange_with(Ok("not json\n")).unwrap_err();
+        assert!(matches!(err, CommandError::InvalidResponse(_)));
+        assert!(err.to_string().starts_with("Invalid response: "));
+
+        let resp = exchange_with(Ok("{\"success\":true,\"data\":{\"url\":\"/\"}}\n")).unwrap();
+        assert!(resp.success);
+    }
+
+    #[test]
+    fn test_exchange_rejects_mismatched_id() {
+        let resp = exchange_with(Ok("{\"id\":\"1\",\"success\":true}\n")).unwrap();
+        assert_eq!(resp.id.as_deref(), Some("1"));
+
+        let err = exchange_with(Ok("{\"id\":\"0\",\"success\":true}\n")).unwrap_err();
+        assert!(matches!(err, CommandError::MismatchedResponse { .. }));
+        assert_eq!(err.kind(), "invalid_response");
+        assert_eq!(err.to_string(), "Invalid response: it answers request 0, not 1");
+
+        // Nothing to compare against when the command had no id
+        let stream = Scripted { reply: Ok(std::io::Cursor::new(b"{\"id\":\"0\",\"success\":true}\n".to_vec())) };
+        assert!(exchange(&mut BufReader::new(stream), &json!({ "action": "url" }), |_| {}).is_ok());
+    }
+
+    /// Hands out one chunk per `read`, like a socket delivering a response in pieces.
+    struct Chunked(std::collections::VecDeque<&'static [u8]>);
+
+    impl Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(chunk) = self.0.pop_front() else { return Ok(0) };
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.0.push_front(&chunk[n..]);
+            }
+            Ok(n)
+        }
+    }
 
-    let mut reader = BufReader::new(stream);
-    let mut response_line = String::new();
-    reader
-        .read_line(&mut response_line)
-        .map_err(|e| format!("Failed to read: {}", e))?;
+    impl Write for Chunked {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn chunked(chunks: &[&'static str]) -> BufReader<Chunked> {
+        BufReader::new(Chunked(chunks.iter().map(|c| c.as_bytes()).collect()))
+    }
+
+    #[test]
+    fn test_read_response_across_two_reads() {
+        let mut reader = chunked(&["{\"success\":true,\"data\":{\"scr", "eenshot\":\"iVBORw0KGgo=\"}}\n"]);
+        let value = read_response(&mut reader).unwrap();
+        assert_eq!(value["data"]["screenshot"], "iVBORw0KGgo=");
+        assert!(matches!(read_response(&mut reader), Err(CommandError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn test_read_response_spanning_lines() {
+        let mut reader = chunked(&["{\n  \"success\": true,\n", "  \"data\": { \"html\": \"<p>\\n</p>\" }\n}\n", "{\"success\":false}\n"]);
+        let first = read_response(&mut reader).unwrap();
+        assert_eq!(first, json!({ "success": true, "data": { "html": "<p>\n</p>" } }));
+        assert_eq!(read_response(&mut reader).unwrap(), json!({ "success": false }));
+    }
+
+    #[test]
+    fn test_read_response_truncated() {
+        let mut reader = chunked(&["{\"success\":true,\"data\":"]);
+        assert!(matches!(read_response(&mut reader), Err(CommandError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn test_exchange_partial_items_then_final_in_pieces() {
+        let chunks = ["{\"partial\":true,\"data\":1}\n{\"succ", "ess\":true,\"data\":", "2}\n"];
+        let mut reader = chunked(&chunks);
+        let mut items = Vec::new();
+        let resp = exchange(&mut reader, &json!({ "id": "1", "action": "x" }), |item| items.push(item.data.clone()))
+            .unwrap();
+        assert_eq!(items, vec![Some(json!(1))]);
+        assert_eq!(resp.data, Some(json!(2)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_on_reuses_connection_and_rearms_timeouts() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let daemon = thread::spawn(move || {
+            let mut reader = BufReader::new(server);
+            let mut actions = Vec::new();
+            for _ in 0..2 {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                actions.push(serde_json::from_str::<Value>(&line).unwrap()["action"].clone());
+                reader.get_mut().write_all(b"{\"success\":true}\n").unwrap();
+            }
+            actions
+        });
+        let mut conn = BufReader::new(Connection::Unix(client));
+        for action in ["launch", "url"] {
+            // e.g. left over from close --timeout; must not cut the next command short
+            conn.get_ref().set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+            let resp = send_on(&mut conn, &json!({ "id": "1", "action": action })).unwrap();
+            assert!(resp.success);
+            let Connection::Unix(stream) = conn.get_ref() else { unreachable!() };
+            assert_eq!(stream.read_timeout().unwrap(), Some(DEFAULT_READ_TIMEOUT));
+            assert_eq!(stream.write_timeout().unwrap(), Some(WRITE_TIMEOUT));
+        }
+        assert_eq!(daemon.join().unwrap(), vec!["launch", "url"]);
+    }
+
+    fn abort_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("agent-browser-abort-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_poll_read_cancels_when_file_appears() {
+        let abort = abort_path("poll");
+        let mut calls = 0;
+        let err = poll_read(
+            || {
+                calls += 1;
+                if calls == 3 {
+                    fs::write(&abort, "").unwrap();
+                }
+                Err(io::Error::from(io::ErrorKind::WouldBlock))
+            },
+            &abort,
+            None,
+        )
+        .unwrap_err();
+        fs::remove_file(&abort).unwrap();
+        assert_eq!(calls, 3);
+        let err = CommandError::read(err);
+        assert!(matches!(err, CommandError::Cancelled(ref p) if *p == abort));
+        assert_eq!(err.kind(), "cancelled");
+    }
+
+    #[test]
+    fn test_poll_read_keeps_timeout_and_data() {
+        let abort = abort_path("absent");
+        let expired = Some(Instant::now());
+        let err = poll_read(|| Err(io::Error::from(io::ErrorKind::TimedOut)), &abort, expired).unwrap_err();
+        assert!(matches!(CommandError::read(err), CommandError::Timeout(_)));
+        assert_eq!(poll_read(|| Ok(7), &abort, None).unwrap(), 7);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_abort_file_cancels_pending_read() {
+        let abort = abort_path("read");
+        let (client, _daemon) = UnixStream::pair().unwrap();
+        let mut conn = Connection::Unix(client);
+        conn.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        let creator = {
+            let abort = abort.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(150));
+                fs::write(&abort, "").unwrap();
+            })
+        };
+        let start = Instant::now();
+        let err = conn.read_abortable(&mut [0; 16], &abort).unwrap_err();
+        creator.join().unwrap();
+        fs::remove_file(&abort).unwrap();
+        assert!(matches!(CommandError::read(err), CommandError::Cancelled(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the configured timeout is restored afterwards
+        assert_eq!(conn.read_timeout().unwrap(), Some(Duration::from_secs(30)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_permission_problem() {
+        assert_eq!(socket_permission_problem(1000, 0o140600, 1000), None);
+        // Read bits alone don't let anyone connect
+        assert_eq!(socket_permission_problem(1000, 0o140755, 1000), None);
+        assert!(socket_permission_problem(1000, 0o140777, 1000).unwrap().contains("mode 777"));
+        assert!(socket_permission_problem(1000, 0o140620, 1000).is_some());
+        assert!(socket_permission_problem(0, 0o140600, 1000).unwrap().contains("uid 0"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_socket_owner_refuses_open_socket() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixListener;
+
+        let path = env::temp_dir().join(format!("agent-browser-perm-test-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _listener = UnixListener::bind(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(check_socket_owner(&path).is_ok());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        let err = check_socket_owner(&path).unwrap_err();
+        assert_eq!(err.kind(), "insecure_socket");
+        assert!(err.to_string().starts_with("Refusing to connect to"));
+        fs::remove_file(&path).unwrap();
+        // A missing socket is left for connect to report
+        assert!(check_socket_owner(&path).is_ok());
+    }
+
+    #[test]
+    fn test_node_program() {
+        assert_eq!(node_program(None).unwrap(), PathBuf::from("node"));
+        let exe = env::current_exe().unwrap();
+        assert_eq!(node_program(Some(&exe)).unwrap(), exe);
+        let missing = env::temp_dir().join("agent-browser-no-such-node");
+        let err = node_program(Some(&missing)).unwrap_err();
+        assert!(err.contains("agent-browser-no-such-node"), "{}", err);
+        assert!(err.contains("AGENT_BROWSER_NODE"), "{}", err);
+        // A directory is not an interpreter
+        assert!(node_program(Some(&env::temp_dir())).is_err());
+    }
+
+    #[test]
+    fn test_startup_failure_points_at_log() {
+        let waited = Duration::from_millis(5000);
+        let msg = startup_failure(waited, None);
+        assert!(msg.starts_with("Daemon failed to start within 5.0s"), "{}", msg);
+        assert!(!msg.contains("output is in"));
+        let msg = startup_failure(waited, Some(Path::new("/tmp/daemon.log")));
+        assert!(msg.ends_with("Its output is in /tmp/daemon.log"), "{}", msg);
+    }
+
+    #[test]
+    fn test_daemon_stderr_appends_to_log() {
+        assert!(daemon_stderr(None).is_ok());
+        let path = env::temp_dir().join(format!("agent-browser-test-log-{}.log", std::process::id()));
+        fs::write(&path, "earlier\n").unwrap();
+        let stderr = daemon_stderr(Some(&path)).unwrap();
+        let status = Command::new(env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(stderr)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(text.starts_with("earlier\n") && text.len() > "earlier\n".len(), "{}", text);
+
+        let missing_dir = env::temp_dir().join("agent-browser-no-such-dir").join("daemon.log");
+        assert!(daemon_stderr(Some(&missing_dir)).unwrap_err().contains("Could not open daemon log"));
+    }
+
+    #[test]
+    fn test_recorded_version() {
+        assert_eq!(recorded_version("4242 0.6.0\n", 4242), Some("0.6.0"));
+        // Written for an earlier daemon of the same session
+        assert_eq!(recorded_version("4242 0.6.0", 4243), None);
+        assert_eq!(recorded_version("garbage", 4242), None);
+        assert_eq!(recorded_version("", 4242), None);
+    }
+
+    #[test]
+    fn test_reported_version() {
+        let ok = Response { success: true, data: Some(json!({ "version": "0.5.1" })), ..Default::default() };
+        assert_eq!(reported_version(&ok), "0.5.1");
+        let rejected = Response { success: false, error: Some("Unknown action".into()), ..Default::default() };
+        assert_eq!(reported_version(&rejected), "unknown");
+    }
+
+    #[test]
+    fn test_runtime_parse() {
+        assert_eq!(Runtime::parse("node").unwrap(), Runtime::Node);
+        assert_eq!(Runtime::parse("bun").unwrap(), Runtime::Bun);
+        assert_eq!(Runtime::parse("deno").unwrap(), Runtime::Deno);
+        assert!(Runtime::parse("Bun").unwrap_err().contains("expected node, bun or deno"));
+    }
+
+    #[test]
+    fn test_daemon_command_per_runtime() {
+        let daemon = Path::new("/opt/agent-browser/dist/daemon.js");
+        let cases = [
+            (Runtime::Node, "/usr/local/bin/node", vec!["/opt/agent-browser/dist/daemon.js"]),
+            (Runtime::Bun, "bun", vec!["/opt/agent-browser/dist/daemon.js"]),
+            (Runtime::Deno, "deno", vec!["run", "-A", "/opt/agent-browser/dist/daemon.js"]),
+        ];
+        for (runtime, program, args) in cases {
+            let cmd = daemon_command(runtime, Path::new(program), &[], daemon);
+            assert_eq!(cmd.get_program(), program, "{:?}", runtime);
+            let actual: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+            assert_eq!(actual, args, "{:?}", runtime);
+        }
+    }
+
+    #[test]
+    fn test_daemon_command_runtime_args_before_script() {
+        let daemon = Path::new("dist/daemon.js");
+        let runtime_args = parse_runtime_args("  --max-old-space-size=4096\t--inspect=9229 ").unwrap();
+        let cmd = daemon_command(Runtime::Node, Path::new("node"), &runtime_args, daemon);
+        let actual: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(actual, ["--max-old-space-size=4096", "--inspect=9229", "dist/daemon.js"]);
+        let cmd = daemon_command(Runtime::Deno, Path::new("deno"), &runtime_args, daemon);
+        let actual: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(actual, ["run", "-A", "--max-old-space-size=4096", "--inspect=9229", "dist/daemon.js"]);
+    }
+
+    #[test]
+    fn test_describe_command() {
+        let mut cmd = daemon_command(Runtime::Node, Path::new("node"), &[], Path::new("dist/daemon.js"));
+        cmd.env("AGENT_BROWSER_SESSION", "work").env("AGENT_BROWSER_DAEMON", "1").env_remove("AGENT_BROWSER_IDLE_TIMEOUT");
+        assert_eq!(
+            describe_command(&cmd),
+            "AGENT_BROWSER_DAEMON=1 AGENT_BROWSER_SESSION=work node dist/daemon.js"
+        );
+    }
 
-    serde_json::from_str(&response_line).map_err(|e| format!("Invalid response: {}", e))
+    #[test]
+    fn test_parse_runtime_args() {
+        assert!(parse_runtime_args("").unwrap().is_empty());
+        assert!(parse_runtime_args("--title=\"ab\"").unwrap_err().contains("quotes"));
+        assert!(parse_runtime_args("--require 'x.js'").is_err());
+        assert!(parse_runtime_args("--inspect evil.js").unwrap_err().contains("'evil.js' is not an option"));
+    }
+
+    #[test]
+    fn test_poll_until_backs_off_and_times_out() {
+        let mut checks = Vec::new();
+        let start = Instant::now();
+        let waited = poll_until(Duration::from_millis(300), || {
+            checks.push(start.elapsed());
+            false
+        })
+        .unwrap_err();
+        assert!(waited >= Duration::from_millis(300));
+        assert!(waited < Duration::from_secs(2));
+        // 25, 50, 100ms pauses, then capped: far fewer checks than fixed 25ms polling
+        assert!((3..=7).contains(&checks.len()), "{:?}", checks);
+        assert!(checks[1] - checks[0] < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_poll_until_returns_once_ready() {
+        let mut calls = 0;
+        let start = Instant::now();
+        assert!(poll_until(Duration::from_secs(5), || {
+            calls += 1;
+            calls == 3
+        })
+        .is_ok());
+        assert_eq!(calls, 3);
+        // Two short pauses (25 + 50ms), not the full budget
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_port_for_session_matches_daemon() {
+        // Values from getPortForSession in src/daemon.ts
+        assert_eq!(get_port_for_session("default"), 50838);
+        assert_eq!(get_port_for_session("agent1"), 62644);
+        assert_eq!(get_port_for_session("a-much-longer-session-name-for-overflow"), 59789);
+    }
+
+    #[test]
+    fn test_daemon_addr_prefers_port_file() {
+        let session = format!("port-file-test-{}", std::process::id());
+        let port_path = get_port_path(&session);
+        let hashed = get_port_for_session(&session);
+        assert_eq!(daemon_addr(&session).port(), hashed);
+        fs::write(&port_path, "41234\n").unwrap();
+        assert_eq!(daemon_addr(&session).port(), 41234);
+        fs::write(&port_path, "not a port").unwrap();
+        assert_eq!(daemon_addr(&session).port(), hashed);
+        fs::remove_file(&port_path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_daemon_ready_reads_and_clears_port_file() {
+        let session = format!("stale-port-test-{}", std::process::id());
+        let port_path = get_port_path(&session);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        fs::write(&port_path, port.to_string()).unwrap();
+        assert!(tcp_daemon_ready(&session));
+        assert!(port_path.exists());
+
+        // Once nothing listens there, the file is stale and gets removed
+        drop(listener);
+        assert!(!tcp_daemon_ready(&session));
+        assert!(!port_path.exists());
+    }
+
+    #[test]
+    fn test_session_from_pid_file() {
+        assert_eq!(session_from_pid_file("agent-browser-work.pid", None), Some("work"));
+        assert_eq!(session_from_pid_file("agent-browser-work.sock", None), None);
+        assert_eq!(session_from_pid_file("other-work.pid", None), None);
+        assert_eq!(session_from_pid_file("agent-browser-.pid", None), None);
+    }
+
+    #[test]
+    fn test_pick_session_dir() {
+        let dir = env::current_dir().unwrap();
+        if cfg!(unix) {
+            assert_eq!(pick_session_dir(Some(dir.as_os_str())), dir);
+        }
+        assert_eq!(pick_session_dir(None), env::temp_dir());
+        assert_eq!(pick_session_dir(Some(OsStr::new(""))), env::temp_dir());
+        assert_eq!(pick_session_dir(Some(OsStr::new("relative/run"))), env::temp_dir());
+        let missing = env::temp_dir().join("agent-browser-no-such-runtime-dir");
+        assert_eq!(pick_session_dir(Some(missing.as_os_str())), env::temp_dir());
+    }
+
+    #[test]
+    fn test_session_from_file() {
+        assert_eq!(session_from_file("agent-browser-work.sock", None), Some(("work", "sock")));
+        assert_eq!(session_from_file("agent-browser-work.port", None), Some(("work", "port")));
+        assert_eq!(session_from_file("agent-browser-my.site.pid", None), Some(("my.site", "pid")));
+        assert_eq!(session_from_file("agent-browser-work.log", None), None);
+        assert_eq!(session_from_file("agent-browser-alice-work.sock", Some("alice")), Some(("work", "sock")));
+    }
+
+    #[test]
+    fn test_session_from_pid_file_with_prefix() {
+        let prefix = Some("alice");
+        assert_eq!(session_from_pid_file("agent-browser-alice-work.pid", prefix), Some("work"));
+        assert_eq!(session_from_pid_file("agent-browser-bob-work.pid", prefix), None);
+        assert_eq!(session_from_pid_file("agent-browser-work.pid", prefix), None);
+    }
+
+    #[test]
+    fn test_stop_step_escalation() {
+        let timeout = Duration::from_millis(500);
+        assert_eq!(stop_step(false, Duration::ZERO, timeout), StopStep::Done);
+        assert_eq!(stop_step(false, Duration::from_secs(60), timeout), StopStep::Done);
+        assert_eq!(stop_step(true, Duration::from_millis(100), timeout), StopStep::Wait);
+        assert_eq!(stop_step(true, timeout, timeout), StopStep::Terminate);
+        assert_eq!(stop_step(true, timeout + TERMINATE_GRACE / 2, timeout), StopStep::Terminate);
+        assert_eq!(stop_step(true, timeout + TERMINATE_GRACE, timeout), StopStep::Kill);
+    }
+
+    #[test]
+    fn test_stop_daemon_without_pid_cleans_up() {
+        let session = format!("stop-test-{}", std::process::id());
+        fs::write(get_pid_path(&session), "not a pid").unwrap();
+        assert_eq!(daemon_pid(&session), None);
+        assert_eq!(stop_daemon(&session, None, Duration::from_millis(10)), Escalation::Graceful);
+        assert!(!get_pid_path(&session).exists());
+    }
+
+    #[test]
+    fn test_clean_stale_session() {
+        let session = format!("stale-test-{}", std::process::id());
+        let mut child = Command::new(env::current_exe().unwrap()).arg("--list").stdout(Stdio::null()).spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        for ext in ["pid", "sock", "port"] {
+            fs::write(session_path(&session, ext), dead.to_string()).unwrap();
+        }
+        assert!(clean_stale_session(&session));
+        for ext in ["pid", "sock", "port"] {
+            assert!(!session_path(&session, ext).exists(), "{} left behind", ext);
+        }
+        // Nothing left to clean
+        assert!(!clean_stale_session(&session));
+
+        // A socket left without its pid file, that nothing listens on
+        fs::write(session_path(&session, "sock"), "").unwrap();
+        assert!(clean_stale_session(&session));
+        assert!(!session_path(&session, "sock").exists());
+
+        // A live daemon's files are kept
+        fs::write(get_pid_path(&session), std::process::id().to_string()).unwrap();
+        assert!(!clean_stale_session(&session));
+        assert!(get_pid_path(&session).exists());
+        remove_session_files(&session);
+    }
+
+    #[test]
+    fn test_session_status() {
+        let session = format!("status-test-{}", std::process::id());
+        let mut child = Command::new(env::current_exe().unwrap()).arg("--list").stdout(Stdio::null()).spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        assert_eq!(session_status(&session), None);
+
+        fs::write(session_path(&session, "sock"), "").unwrap();
+        assert_eq!(session_status(&session), Some(SessionStatus::OrphanSocket));
+        fs::write(get_pid_path(&session), dead.to_string()).unwrap();
+        assert_eq!(session_status(&session), Some(SessionStatus::StalePid));
+        fs::write(get_pid_path(&session), std::process::id().to_string()).unwrap();
+        assert_eq!(session_status(&session), Some(SessionStatus::Running));
+
+        let listed = list_session_statuses(None);
+        assert!(listed.contains(&(session.clone(), SessionStatus::Running)));
+        assert_eq!(serde_json::to_value(SessionStatus::OrphanSocket).unwrap(), json!("orphan-socket"));
+        remove_session_files(&session);
+    }
+
+    #[test]
+    fn test_proc_start_millis() {
+        let stat = "4242 (node (daemon) x) S 1 4242 4242 0 -1 4194560 1 0 0 0 3 1 0 0 20 0 11 0 250 1000";
+        let proc_stat = "cpu  1 2 3\nbtime 1700000000\nprocesses 9\n";
+        assert_eq!(proc_start_millis(stat, proc_stat, 100), Some(1_700_000_002_500));
+        assert_eq!(proc_start_millis(stat, "cpu 1 2 3\n", 100), None);
+        assert_eq!(proc_start_millis("4242 (node", proc_stat, 100), None);
+    }
+
+    #[cfg(any(target_os = "linux", windows))]
+    #[test]
+    fn test_process_start_time_of_self() {
+        let started = process_start_time(std::process::id()).unwrap();
+        let age = started.elapsed().unwrap_or_default();
+        assert!(age < Duration::from_secs(3600), "{:?}", age);
+    }
+
+    #[test]
+    fn test_validate_session_name() {
+        assert!(validate_session_name("work").is_ok());
+        assert!(validate_session_name("ci-run_2.a").is_ok());
+        assert!(validate_session_name("").is_err());
+        assert!(validate_session_name(".hidden").is_err());
+        assert!(validate_session_name("a/b").is_err());
+        assert!(validate_session_name("my session").unwrap_err().contains("'my session'"));
+    }
+
+    #[test]
+    fn test_rename_session() {
+        let old = format!("rename-old-{}", std::process::id());
+        let new = format!("rename-new-{}", std::process::id());
+        let mut child = Command::new(env::current_exe().unwrap()).arg("--list").stdout(Stdio::null()).spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        fs::write(session_path(&old, "pid"), dead.to_string()).unwrap();
+        fs::write(session_path(&old, "sock"), "").unwrap();
+
+        assert!(rename_session(&old, "bad name").is_err());
+        assert!(rename_session(&old, &old).is_err());
+        rename_session(&old, &new).unwrap();
+        assert!(!session_path(&old, "pid").exists() && !session_path(&old, "sock").exists());
+        assert_eq!(fs::read_to_string(session_path(&new, "pid")).unwrap(), dead.to_string());
+        assert!(session_path(&new, "sock").exists());
+        assert!(rename_session(&old, &new).unwrap_err().contains("No session"));
+
+        // The target must be free
+        fs::write(session_path(&old, "pid"), dead.to_string()).unwrap();
+        assert!(rename_session(&old, &new).unwrap_err().contains("already exists"));
+
+        // A running daemon is refused
+        fs::write(session_path(&old, "pid"), std::process::id().to_string()).unwrap();
+        remove_session_files(&new);
+        assert!(rename_session(&old, &new).unwrap_err().contains("stop it first"));
+        remove_session_files(&old);
+    }
+
+    #[test]
+    fn test_session_cap_refuses_at_limit() {
+        assert!(check_session_cap(20, || 19).is_ok());
+        let err = check_session_cap(20, || 20).unwrap_err();
+        assert!(err.contains("20 sessions are already running (limit 20)"));
+        assert!(err.contains("--max-sessions"));
+        assert!(check_session_cap(3, || 7).is_err());
+    }
+
+    #[test]
+    fn test_with_context_id() {
+        let cmd = json!({ "id": "1", "action": "click", "selector": "#go" });
+        let tagged = with_context_id(&cmd, Some("agent1"));
+        assert_eq!(tagged["contextId"], "agent1");
+        assert_eq!(tagged["selector"], "#go");
+        assert!(with_context_id(&cmd, None).get("contextId").is_none());
+
+        let explicit = json!({ "id": "2", "action": "launch", "contextId": "other" });
+        assert_eq!(with_context_id(&explicit, Some("agent1"))["contextId"], "other");
+    }
+
+    #[test]
+    fn test_qualify_session() {
+        assert_eq!(qualify_session(Some("alice"), "work"), "alice-work");
+        assert_eq!(qualify_session(None, "work"), "work");
+    }
+
+    #[test]
+    fn test_unqualify_session() {
+        assert_eq!(unqualify_session(Some("alice"), "alice-work"), Some("work"));
+        assert_eq!(unqualify_session(Some("alice"), "alice-team-a"), Some("team-a"));
+        assert_eq!(unqualify_session(Some("alice"), "bob-work"), None);
+        assert_eq!(unqualify_session(Some("alice"), "alicework"), None);
+        assert_eq!(unqualify_session(Some("alice"), "alice-"), None);
+        assert_eq!(unqualify_session(None, "bob-work"), Some("bob-work"));
+    }
 }
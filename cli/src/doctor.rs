@@ -0,0 +1,353 @@
+//! `agent-browser doctor [--fix] [--dry-run]`: find what a crashed or
+//! misbehaving daemon left behind in the session dir and, with `--fix`, repair it.
+
+use crate::color;
+use crate::connection::{
+    daemon_pid, daemon_reachable, is_pid_running, remove_session_files, session_dir, sessions_with_files,
+    stop_daemon,
+};
+use crate::output::confirm;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Something wrong with the daemon's environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The dir that holds sockets and pid files doesn't exist.
+    MissingTempDir(PathBuf),
+    /// Files of a daemon that is gone, or of a pid now used by another process.
+    StaleFiles { session: String, pid: Option<u32> },
+    /// A daemon that is running but doesn't answer on its socket or port, so
+    /// `close` can't reach it. `verified` when the process is known to be a
+    /// daemon; otherwise it is only reported, never killed.
+    OrphanDaemon { session: String, pid: u32, verified: bool },
+    /// A daemon answers, but there is no pid file to stop it by.
+    MissingPidFile { session: String },
+    /// A socket other users can connect to, or that someone else owns.
+    InsecureSocket { session: String, path: PathBuf, reason: String, mode: Option<u32> },
+}
+
+impl Issue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Issue::MissingTempDir(_) => "missing_temp_dir",
+            Issue::StaleFiles { .. } => "stale_files",
+            Issue::OrphanDaemon { .. } => "orphan_daemon",
+            Issue::MissingPidFile { .. } => "missing_pid_file",
+            Issue::InsecureSocket { .. } => "insecure_socket",
+        }
+    }
+
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            Issue::MissingTempDir(_) => None,
+            Issue::StaleFiles { session, .. }
+            | Issue::OrphanDaemon { session, .. }
+            | Issue::MissingPidFile { session }
+            | Issue::InsecureSocket { session, .. } => Some(session),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Issue::MissingTempDir(path) => format!("Temp dir {} does not exist", path.display()),
+            Issue::StaleFiles { session, pid: Some(pid) } => {
+                format!("Session '{}': daemon {} is gone but its files remain", session, pid)
+            }
+            Issue::StaleFiles { session, pid: None } => {
+                format!("Session '{}': socket or port file with no daemon behind it", session)
+            }
+            Issue::OrphanDaemon { session, pid, verified: true } => {
+                format!("Session '{}': daemon {} is running but unreachable", session, pid)
+            }
+            Issue::OrphanDaemon { session, pid, verified: false } => format!(
+                "Session '{}': pid {} is running but unreachable, and can't be confirmed to be a daemon",
+                session, pid
+            ),
+            Issue::MissingPidFile { session } => format!(
+                "Session '{}': daemon answers but has no pid file (stop it with --session {} close)",
+                session, session
+            ),
+            Issue::InsecureSocket { session, reason, .. } => format!("Session '{}': socket is unsafe: {}", session, reason),
+        }
+    }
+
+    /// What `--fix` does about this issue, or `None` if it is only reported.
+    /// Only repairs that can't hurt another user's or an unrelated process
+    /// are automatic.
+    pub fn fix(&self) -> Option<String> {
+        match self {
+            Issue::MissingTempDir(path) => Some(format!("create {}", path.display())),
+            Issue::StaleFiles { session, .. } => Some(format!("remove the files of session '{}'", session)),
+            Issue::OrphanDaemon { pid, verified: true, .. } => Some(format!("stop daemon {} and remove its files", pid)),
+            Issue::OrphanDaemon { verified: false, .. } | Issue::MissingPidFile { .. } => None,
+            Issue::InsecureSocket { path, mode: Some(mode), .. } => Some(format!(
+                "chmod 600 {} (was {:o}; chmod {:o} to undo)",
+                path.display(),
+                mode,
+                mode
+            )),
+            Issue::InsecureSocket { mode: None, .. } => None,
+        }
+    }
+}
+
+/// What was found on disk for one session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionState {
+    pub pid: Option<u32>,
+    pub alive: bool,
+    pub reachable: bool,
+    /// Whether the pid's command line is a daemon's, when that can be read.
+    pub is_daemon: Option<bool>,
+}
+
+/// The issue with a session in `state`, if any.
+pub fn diagnose(session: &str, state: SessionState) -> Option<Issue> {
+    let session = session.to_string();
+    match state {
+        SessionState { pid: Some(pid), alive: false, .. } => Some(Issue::StaleFiles { session, pid: Some(pid) }),
+        SessionState { reachable: true, pid: Some(_), .. } => None,
+        SessionState { reachable: true, pid: None, .. } => Some(Issue::MissingPidFile { session }),
+        SessionState { pid: None, .. } => Some(Issue::StaleFiles { session, pid: None }),
+        // The pid was reused by something else: the daemon is long gone
+        SessionState { pid: Some(pid), is_daemon: Some(false), .. } => Some(Issue::StaleFiles { session, pid: Some(pid) }),
+        SessionState { pid: Some(pid), is_daemon, .. } => Some(Issue::OrphanDaemon {
+            session,
+            pid,
+            verified: is_daemon == Some(true),
+        }),
+    }
+}
+
+/// Whether `pid` is running `daemon.js`. Only Linux exposes the command line
+/// cheaply; elsewhere this is unknown.
+fn is_daemon_process(pid: u32) -> Option<bool> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    Some(cmdline.split(|&b| b == 0).any(|arg| arg.ends_with(b"daemon.js")))
+}
+
+fn session_state(session: &str) -> SessionState {
+    let pid = daemon_pid(session);
+    let alive = pid.is_some_and(is_pid_running);
+    SessionState {
+        pid,
+        alive,
+        reachable: daemon_reachable(session),
+        is_daemon: pid.filter(|_| alive).and_then(is_daemon_process),
+    }
+}
+
+#[cfg(unix)]
+fn socket_issue(session: &str) -> Option<Issue> {
+    use crate::connection::{get_socket_path, socket_permission_problem};
+    use std::os::unix::fs::MetadataExt;
+
+    let path = get_socket_path(session);
+    let meta = fs::symlink_metadata(&path).ok()?;
+    let uid = unsafe { libc::getuid() };
+    let reason = socket_permission_problem(meta.uid(), meta.mode(), uid)?;
+    // Only our own socket's mode is ours to change
+    let mode = (meta.uid() == uid).then_some(meta.mode() & 0o777);
+    Some(Issue::InsecureSocket { session: session.to_string(), path, reason, mode })
+}
+
+#[cfg(windows)]
+fn socket_issue(_session: &str) -> Option<Issue> {
+    None
+}
+
+/// Everything wrong in the current session namespace.
+pub fn find_issues(prefix: Option<&str>) -> Vec<Issue> {
+    let dir = session_dir();
+    if !dir.is_dir() {
+        return vec![Issue::MissingTempDir(dir.to_path_buf())];
+    }
+    let mut issues = Vec::new();
+    for session in sessions_with_files(prefix) {
+        match diagnose(&session, session_state(&session)) {
+            // Stale files go wholesale, socket included
+            Some(issue @ Issue::StaleFiles { .. }) => issues.push(issue),
+            other => issues.extend(other.into_iter().chain(socket_issue(&session))),
+        }
+    }
+    issues
+}
+
+fn apply_fix(issue: &Issue) -> Result<(), String> {
+    match issue {
+        Issue::MissingTempDir(path) => {
+            fs::create_dir_all(path).map_err(|e| e.to_string())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o700)).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        Issue::StaleFiles { session, .. } => {
+            remove_session_files(session);
+            Ok(())
+        }
+        Issue::OrphanDaemon { session, pid, verified: true } => {
+            // It can't hear `close`, so go straight to SIGTERM
+            stop_daemon(session, Some(*pid), Duration::ZERO);
+            Ok(())
+        }
+        #[cfg(unix)]
+        Issue::InsecureSocket { path, mode: Some(_), .. } => {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())
+        }
+        _ => Err("not fixable automatically".to_string()),
+    }
+}
+
+/// Stopping a daemon ends its browser, so `--fix` asks first unless `yes`.
+fn fix_confirmed(issue: &Issue, yes: bool) -> bool {
+    match issue {
+        Issue::OrphanDaemon { session, pid, .. } => {
+            confirm(&format!("This will stop daemon {} of session '{}'.", pid, session), yes)
+        }
+        _ => true,
+    }
+}
+
+/// `dry_run` is the global `--dry-run`, which only matters with `--fix`;
+/// `yes` is `--yes`, which skips confirmation before stopping a daemon.
+pub fn run_doctor(args: &[String], dry_run: bool, yes: bool, prefix: Option<&str>, json_mode: bool) -> bool {
+    let fix = args.iter().any(|a| a == "--fix");
+
+    let issues = find_issues(prefix);
+    let mut remaining = 0;
+    let mut report: Vec<Value> = Vec::new();
+
+    for issue in &issues {
+        let fix_desc = issue.fix();
+        let outcome = match &fix_desc {
+            Some(_) if fix && !dry_run && !fix_confirmed(issue, yes) => {
+                Some(Err("not confirmed (pass --yes to skip)".to_string()))
+            }
+            Some(_) if fix && !dry_run => Some(apply_fix(issue)),
+            _ => None,
+        };
+        let fixed = matches!(outcome, Some(Ok(())));
+        if !fixed {
+            remaining += 1;
+        }
+
+        if json_mode {
+            report.push(json!({
+                "issue": issue.kind(),
+                "session": issue.session(),
+                "message": issue.describe(),
+                "fixable": fix_desc.is_some(),
+                "fix": fix_desc,
+                "fixed": fixed,
+                "error": outcome.and_then(|r| r.err()),
+            }));
+            continue;
+        }
+
+        println!("{} {}", if fixed { color::success_indicator() } else { color::error_indicator() }, issue.describe());
+        match (&fix_desc, outcome) {
+            (Some(desc), Some(Ok(()))) => println!("  {}", color::green(&format!("Fixed ({})", desc))),
+            (Some(desc), Some(Err(e))) => println!("  {}", color::red(&format!("Could not {}: {}", desc, e))),
+            (Some(desc), None) if dry_run => println!("  {}", color::dim(&format!("Would {}", desc))),
+            (Some(desc), None) => println!("  {}", color::dim(&format!("Run with --fix to {}", desc))),
+            (None, _) => println!("  {}", color::dim("Needs manual attention")),
+        }
+    }
+
+    if json_mode {
+        println!(
+            "{}",
+            json!({ "success": remaining == 0, "data": { "issues": report, "fix": fix, "dry_run": dry_run } })
+        );
+    } else if issues.is_empty() {
+        println!("{} No problems found", color::success_indicator());
+    }
+    remaining == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(pid: Option<u32>, alive: bool, reachable: bool, is_daemon: Option<bool>) -> SessionState {
+        SessionState { pid, alive, reachable, is_daemon }
+    }
+
+    #[test]
+    fn test_diagnose_healthy_session() {
+        assert_eq!(diagnose("s", state(Some(1), true, true, Some(true))), None);
+        assert_eq!(diagnose("s", state(Some(1), true, true, None)), None);
+    }
+
+    #[test]
+    fn test_diagnose_stale_files() {
+        let dead = diagnose("s", state(Some(7), false, false, None)).unwrap();
+        assert_eq!(dead, Issue::StaleFiles { session: "s".into(), pid: Some(7) });
+        let no_pid = diagnose("s", state(None, false, false, None)).unwrap();
+        assert_eq!(no_pid, Issue::StaleFiles { session: "s".into(), pid: None });
+        // A live pid that isn't a daemon means the pid was reused
+        let reused = diagnose("s", state(Some(7), true, false, Some(false))).unwrap();
+        assert_eq!(reused, Issue::StaleFiles { session: "s".into(), pid: Some(7) });
+    }
+
+    #[test]
+    fn test_diagnose_orphans() {
+        let verified = diagnose("s", state(Some(7), true, false, Some(true))).unwrap();
+        assert_eq!(verified, Issue::OrphanDaemon { session: "s".into(), pid: 7, verified: true });
+        let unknown = diagnose("s", state(Some(7), true, false, None)).unwrap();
+        assert_eq!(unknown, Issue::OrphanDaemon { session: "s".into(), pid: 7, verified: false });
+        let no_pid = diagnose("s", state(None, false, true, None)).unwrap();
+        assert_eq!(no_pid, Issue::MissingPidFile { session: "s".into() });
+    }
+
+    #[test]
+    fn test_fixable_issues() {
+        let s = || "s".to_string();
+        let fixable = [
+            Issue::MissingTempDir(PathBuf::from("/tmp/x")),
+            Issue::StaleFiles { session: s(), pid: None },
+            Issue::OrphanDaemon { session: s(), pid: 7, verified: true },
+            Issue::InsecureSocket { session: s(), path: "/tmp/s.sock".into(), reason: "r".into(), mode: Some(0o777) },
+        ];
+        for issue in &fixable {
+            assert!(issue.fix().is_some(), "{:?}", issue);
+        }
+        let reported = [
+            Issue::OrphanDaemon { session: s(), pid: 7, verified: false },
+            Issue::MissingPidFile { session: s() },
+            Issue::InsecureSocket { session: s(), path: "/tmp/s.sock".into(), reason: "r".into(), mode: None },
+        ];
+        for issue in &reported {
+            assert!(issue.fix().is_none(), "{:?}", issue);
+        }
+    }
+
+    #[test]
+    fn test_only_stopping_a_daemon_needs_confirmation() {
+        let stale = Issue::StaleFiles { session: "s".into(), pid: None };
+        assert!(fix_confirmed(&stale, false));
+        let orphan = Issue::OrphanDaemon { session: "s".into(), pid: 7, verified: true };
+        assert!(fix_confirmed(&orphan, true));
+    }
+
+    #[test]
+    fn test_chmod_fix_says_how_to_undo() {
+        let issue = Issue::InsecureSocket {
+            session: "s".into(),
+            path: "/tmp/s.sock".into(),
+            reason: "r".into(),
+            mode: Some(0o777),
+        };
+        assert_eq!(issue.fix().unwrap(), "chmod 600 /tmp/s.sock (was 777; chmod 777 to undo)");
+    }
+}
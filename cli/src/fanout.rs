@@ -0,0 +1,186 @@
+//! Bounded parallel execution for running one command across many sessions.
+
+use serde_json::{json, Value};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::connection::Response;
+use crate::output::infer_error_code;
+
+/// Default number of sessions driven in parallel when `--max-concurrency` is not set.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Run `f` over every item using at most `max_concurrency` worker threads.
+///
+/// Work is handed out through a bounded channel so no more than
+/// `max_concurrency` calls are ever in flight. Results are returned in the
+/// same order as `items`, regardless of completion order.
+pub fn fan_out<T, R, F>(items: &[T], max_concurrency: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if items.is_empty() {
+        return Vec::new();
+    }
+
+    let workers = max_concurrency.max(1).min(items.len());
+    let (job_tx, job_rx) = mpsc::sync_channel::<usize>(workers);
+    let job_rx = Mutex::new(job_rx);
+    let (result_tx, result_rx) = mpsc::channel::<(usize, R)>();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let job_rx = &job_rx;
+            let result_tx = result_tx.clone();
+            let f = &f;
+            scope.spawn(move || loop {
+                let next = job_rx.lock().map(|rx| rx.recv());
+                let Ok(Ok(index)) = next else {
+                    break;
+                };
+                if result_tx.send((index, f(&items[index]))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        for index in 0..items.len() {
+            if job_tx.send(index).is_err() {
+                break;
+            }
+        }
+        drop(job_tx);
+    });
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (index, result) in result_rx {
+        results[index] = Some(result);
+    }
+    results.into_iter().flatten().collect()
+}
+
+/// Collect the failures of an aggregated run as `{<key>, error, errorCode}` objects.
+///
+/// `key` names the identifying field (`"session"` for fan-out, `"line"` for
+/// batch files, `"selector"` for bulk runs) and `ids` supplies its value for
+/// each result, in order. The code is keyed `errorCode`, as in daemon responses.
+pub fn collect_errors<I>(key: &str, ids: I, results: &[Response]) -> Vec<Value>
+where
+    I: IntoIterator<Item = Value>,
+{
+    ids.into_iter()
+        .zip(results)
+        .filter(|(_, resp)| !resp.success)
+        .map(|(id, resp)| {
+            let message = resp.error.as_deref().unwrap_or("Unknown error");
+            let code = resp
+                .error_code
+                .clone()
+                .or_else(|| infer_error_code(message).map(String::from));
+            let mut entry = json!({ "error": message, "errorCode": code });
+            entry[key] = id;
+            entry
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_fan_out_preserves_order() {
+        let items: Vec<u64> = (0..10).collect();
+        // Later items finish first to shake up completion order
+        let results = fan_out(&items, 3, |&n| {
+            thread::sleep(Duration::from_millis(10 - n));
+            n * 2
+        });
+        assert_eq!(results, (0..10).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fan_out_respects_max_concurrency() {
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..12).collect();
+
+        fan_out(&items, 3, |_| {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(peak <= 3, "peak concurrency was {}", peak);
+        assert!(peak >= 2, "expected some parallelism, peak was {}", peak);
+    }
+
+    fn failed(error: &str, code: Option<&str>) -> Response {
+        Response {
+            success: false,
+            error: Some(error.to_string()),
+            error_code: code.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_collect_errors_by_session() {
+        let results = vec![
+            Response { success: true, ..Default::default() },
+            failed("Timeout 30000ms exceeded", None),
+            failed("boom", Some("custom_code")),
+        ];
+        let sessions = ["a", "b", "c"];
+        let errors = collect_errors("session", sessions.iter().map(|s| json!(s)), &results);
+        assert_eq!(
+            errors,
+            vec![
+                json!({ "session": "b", "error": "Timeout 30000ms exceeded", "errorCode": "timeout" }),
+                json!({ "session": "c", "error": "boom", "errorCode": "custom_code" }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_errors_by_line() {
+        let results = vec![
+            failed("locator resolved to 0 elements", None),
+            Response { success: true, ..Default::default() },
+            failed("mystery", None),
+        ];
+        let lines = [2, 4, 7].map(|n| json!(n));
+        let errors = collect_errors("line", lines, &results);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0]["line"], 2);
+        assert_eq!(errors[0]["errorCode"], "element_not_found");
+        assert_eq!(errors[1]["line"], 7);
+        assert!(errors[1]["errorCode"].is_null());
+    }
+
+    #[test]
+    fn test_collect_errors_all_ok() {
+        let results = vec![Response { success: true, ..Default::default() }];
+        assert!(collect_errors("session", vec![json!("a")], &results).is_empty());
+    }
+
+    #[test]
+    fn test_fan_out_empty() {
+        let items: Vec<u8> = Vec::new();
+        assert!(fan_out(&items, 4, |&n| n).is_empty());
+    }
+
+    #[test]
+    fn test_fan_out_zero_concurrency_still_runs() {
+        let items = vec![1, 2, 3];
+        assert_eq!(fan_out(&items, 0, |&n| n + 1), vec![2, 3, 4]);
+    }
+}
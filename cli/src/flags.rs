@@ -1,5 +1,11 @@
+use serde_json::{Map, Value};
 use std::env;
+use std::time::Duration;
 
+use crate::config::{Defaults, SessionConfig};
+use crate::parse;
+
+#[derive(Default)]
 pub struct Flags {
     pub json: bool,
     pub full: bool,
@@ -12,41 +18,527 @@ pub struct Flags {
     pub extensions: Vec<String>,
     pub proxy: Option<String>,
     pub provider: Option<String>,
+    pub output: Option<String>,
+    pub abort_on_console_error: bool,
+    pub ignore_console: Vec<String>,
+    pub all_sessions: bool,
+    pub max_concurrency: Option<String>,
+    pub yes: bool,
+    pub record_commands: Option<String>,
+    pub viewport: Option<String>,
+    pub viewport_from_terminal: bool,
+    pub no_spawn: bool,
+    pub local_storage: Vec<String>,
+    pub session_storage: Vec<String>,
+    pub max_idle_reconnect: Option<String>,
+    pub retry_on_navigation: bool,
+    pub keep_browser_on_crash: bool,
+    pub diagnostics_dir: Option<String>,
+    pub throttle_network: Option<String>,
+    pub throttle_cpu: Option<String>,
+    pub template: Option<String>,
+    pub template_file: Option<String>,
+    pub session_prefix: Option<String>,
+    pub no_sandbox: bool,
+    pub result_field: Option<String>,
+    pub max_connect_attempts: Option<String>,
+    pub connect_retries: Option<String>,
+    pub output_file: Option<String>,
+    pub output_append: bool,
+    pub redact: Vec<String>,
+    pub output_split_by: Option<String>,
+    pub output_dir: Option<String>,
+    pub connect_existing: bool,
+    pub max_sessions: Option<String>,
+    pub keepalive_interval: Option<String>,
+    pub output_on_success: Option<String>,
+    pub output_on_success_data: bool,
+    pub compact_sessions: bool,
+    pub fail_on_empty: bool,
+    pub selector_file: Option<String>,
+    pub emit_id: bool,
+    pub flatten: bool,
+    pub timeout: Option<String>,
+    pub parse_only: bool,
+    pub metadata: Vec<String>,
+    pub stdin: bool,
+    pub abort_signal_file: Option<String>,
+    pub tcp: bool,
+    pub chromium_flags: Vec<String>,
+    pub output_jq: Option<String>,
+    pub frame: Option<String>,
+    pub startup_timeout: Option<String>,
+    pub wait_before: Option<String>,
+    pub wait_after: Option<String>,
+    pub node: Option<String>,
+    pub daemon_log: Option<String>,
+    pub idle_timeout: Option<String>,
+    pub keep_alive: bool,
+    pub no_color: bool,
+    pub color: Option<String>,
+    pub quiet: bool,
+    pub verbose: bool,
+    pub dry_run: bool,
+    pub max_col_width: Option<String>,
+    pub field: Option<String>,
+    pub no_proxy: Option<String>,
+}
+
+/// Split a `key=value` flag argument. The key must be non-empty; the value may
+/// be empty and may itself contain `=`.
+pub fn parse_key_value(s: &str) -> Option<(&str, &str)> {
+    let (key, value) = s.split_once('=')?;
+    if key.trim().is_empty() {
+        return None;
+    }
+    Some((key.trim(), value))
+}
+
+/// Collect `--metadata key=value` tags into the object attached to the request.
+/// Values are kept as strings; a repeated key keeps its last value.
+pub fn parse_metadata(pairs: &[String]) -> Result<Map<String, Value>, String> {
+    pairs
+        .iter()
+        .map(|pair| {
+            parse_key_value(pair)
+                .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+                .ok_or_else(|| format!("Invalid --metadata '{}': expected key=value", pair))
+        })
+        .collect()
+}
+
+/// Check `--chromium-flag` values before they reach the browser launch. Each
+/// must be a switch such as `--disable-gpu` or `--lang=fr`.
+pub fn validate_chromium_flags(chromium_flags: &[String]) -> Result<(), String> {
+    for flag in chromium_flags {
+        let name = flag.strip_prefix("--").unwrap_or("");
+        if name.is_empty() || name.starts_with(['-', '=']) {
+            return Err(format!(
+                "Invalid --chromium-flag '{}': expected a switch starting with '--'",
+                flag
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a `--wait-before` / `--wait-after` delay in whole milliseconds.
+pub fn parse_delay_ms(flag: &str, value: &str) -> Result<Duration, String> {
+    value
+        .parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("Invalid {}: '{}' must be a non-negative integer (milliseconds)", flag, value))
+}
+
+/// Network conditions for `--throttle-network`. Throughputs are in bytes per
+/// second and latency in milliseconds, as CDP `Network.emulateNetworkConditions` expects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkThrottle {
+    pub offline: bool,
+    pub download: f64,
+    pub upload: f64,
+    pub latency: f64,
+}
+
+impl NetworkThrottle {
+    /// Parse a named profile (`slow-3g`, `fast-3g`, `offline`) or a custom
+    /// `down,up,latency` triple given in kbit/s, kbit/s and milliseconds.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        // Profile numbers match the Chrome DevTools presets
+        match s.trim().to_lowercase().as_str() {
+            "slow-3g" => {
+                return Ok(NetworkThrottle { offline: false, download: 50_000.0, upload: 50_000.0, latency: 2000.0 })
+            }
+            "fast-3g" => {
+                return Ok(NetworkThrottle { offline: false, download: 180_000.0, upload: 84_375.0, latency: 562.5 })
+            }
+            "offline" => return Ok(NetworkThrottle { offline: true, download: 0.0, upload: 0.0, latency: 0.0 }),
+            _ => {}
+        }
+
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if parts.len() != 3 {
+            return Err(format!(
+                "expected slow-3g, fast-3g, offline, or down,up,latency; got '{}'",
+                s
+            ));
+        }
+        let mut values = [0.0; 3];
+        for (value, (part, name)) in values.iter_mut().zip(parts.iter().zip(["down", "up", "latency"])) {
+            *value = parse::non_negative(part).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(NetworkThrottle {
+            offline: false,
+            download: values[0] * 1000.0 / 8.0,
+            upload: values[1] * 1000.0 / 8.0,
+            latency: values[2],
+        })
+    }
+}
+
+/// Parse a `--throttle-cpu` slowdown factor; `1` means no throttling.
+pub fn parse_cpu_throttle(s: &str) -> Result<f64, String> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|f| f.is_finite() && *f >= 1.0)
+        .ok_or_else(|| format!("expected a number >= 1, got '{}'", s))
+}
+
+/// Default for headed mode when neither `--headed` nor `--headless` is given:
+/// headed on desktops with a display, headless in CI or over SSH.
+fn default_headed(os: &str, has_display: bool, in_ci: bool, over_ssh: bool) -> bool {
+    if in_ci || over_ssh {
+        return false;
+    }
+    match os {
+        "macos" | "windows" => true,
+        _ => has_display,
+    }
+}
+
+fn detect_default_headed() -> bool {
+    let set = |name: &str| env::var(name).map(|v| !v.is_empty()).unwrap_or(false);
+    let in_ci = env::var("CI").map(|v| !v.is_empty() && v != "0" && v != "false").unwrap_or(false);
+    default_headed(
+        env::consts::OS,
+        set("DISPLAY") || set("WAYLAND_DISPLAY"),
+        in_ci,
+        set("SSH_CONNECTION") || set("SSH_TTY"),
+    )
 }
 
-pub fn parse_flags(args: &[String]) -> Flags {
+/// The config file named by `--config` or `AGENT_BROWSER_CONFIG`. It is read
+/// before the other flags are parsed, since it supplies their defaults.
+pub fn config_file(args: &[String]) -> Option<String> {
+    args.iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+        .or_else(|| env::var("AGENT_BROWSER_CONFIG").ok().filter(|s| !s.is_empty()))
+}
+
+/// Parse global flags. Each one comes from the command line if given, then
+/// its environment variable, then the config file's `defaults`, then the
+/// built-in default.
+pub fn parse_flags(args: &[String], defaults: &Defaults) -> Flags {
     let extensions_env = env::var("AGENT_BROWSER_EXTENSIONS")
         .ok()
         .map(|s| s.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect::<Vec<_>>())
         .unwrap_or_default();
 
     let mut flags = Flags {
-        json: false,
+        json: defaults.json.unwrap_or(false),
         full: false,
         headed: false,
         debug: false,
-        session: env::var("AGENT_BROWSER_SESSION").unwrap_or_else(|_| "default".to_string()),
+        session: env::var("AGENT_BROWSER_SESSION")
+            .ok()
+            .or_else(|| defaults.session.clone())
+            .unwrap_or_else(|| "default".to_string()),
         headers: None,
-        executable_path: env::var("AGENT_BROWSER_EXECUTABLE_PATH").ok(),
+        executable_path: env::var("AGENT_BROWSER_EXECUTABLE_PATH").ok().or_else(|| defaults.executable_path.clone()),
         cdp: None,
         extensions: extensions_env,
-        proxy: None,
+        proxy: defaults.proxy.clone(),
         provider: env::var("AGENT_BROWSER_PROVIDER").ok(),
+        output: None,
+        abort_on_console_error: false,
+        ignore_console: Vec::new(),
+        all_sessions: false,
+        max_concurrency: None,
+        yes: false,
+        record_commands: env::var("AGENT_BROWSER_RECORD_COMMANDS").ok(),
+        viewport: None,
+        viewport_from_terminal: false,
+        no_spawn: false,
+        local_storage: Vec::new(),
+        session_storage: Vec::new(),
+        max_idle_reconnect: None,
+        retry_on_navigation: false,
+        keep_browser_on_crash: false,
+        diagnostics_dir: None,
+        throttle_network: None,
+        throttle_cpu: None,
+        template: None,
+        template_file: None,
+        session_prefix: env::var("AGENT_BROWSER_SESSION_PREFIX").ok(),
+        no_sandbox: false,
+        result_field: None,
+        max_connect_attempts: None,
+        connect_retries: None,
+        output_file: None,
+        output_append: false,
+        redact: Vec::new(),
+        output_split_by: None,
+        output_dir: None,
+        connect_existing: false,
+        max_sessions: env::var("AGENT_BROWSER_MAX_SESSIONS").ok(),
+        keepalive_interval: None,
+        output_on_success: None,
+        output_on_success_data: false,
+        compact_sessions: false,
+        fail_on_empty: false,
+        selector_file: None,
+        emit_id: false,
+        flatten: false,
+        timeout: env::var("AGENT_BROWSER_TIMEOUT").ok(),
+        parse_only: false,
+        metadata: Vec::new(),
+        stdin: false,
+        abort_signal_file: None,
+        tcp: false,
+        chromium_flags: Vec::new(),
+        output_jq: None,
+        frame: None,
+        startup_timeout: env::var("AGENT_BROWSER_STARTUP_TIMEOUT").ok(),
+        wait_before: None,
+        wait_after: None,
+        node: env::var("AGENT_BROWSER_NODE").ok().filter(|s| !s.is_empty()),
+        daemon_log: env::var("AGENT_BROWSER_LOG").ok().filter(|s| !s.is_empty()),
+        idle_timeout: env::var("AGENT_BROWSER_IDLE_TIMEOUT").ok().filter(|s| !s.is_empty()),
+        keep_alive: false,
+        no_color: false,
+        color: None,
+        quiet: false,
+        verbose: false,
+        dry_run: false,
+        max_col_width: None,
+        field: None,
+        no_proxy: None,
     };
+    let global_timeout = global_timeout_index(args);
 
+    let mut headed_explicit = false;
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
             "--json" => flags.json = true,
             "--full" | "-f" => flags.full = true,
-            "--headed" => flags.headed = true,
+            "--headed" => {
+                flags.headed = true;
+                headed_explicit = true;
+            }
+            "--headless" => {
+                flags.headed = false;
+                headed_explicit = true;
+            }
             "--debug" => flags.debug = true,
+            "--abort-on-console-error" => flags.abort_on_console_error = true,
+            "--all" => flags.all_sessions = true,
+            "--yes" | "--force" => flags.yes = true,
+            "--viewport" => {
+                if let Some(v) = args.get(i + 1) {
+                    flags.viewport = Some(v.clone());
+                    i += 1;
+                }
+            }
+            "--viewport-from-terminal" => flags.viewport_from_terminal = true,
+            "--no-spawn" => flags.no_spawn = true,
+            "--retry-on-navigation" => flags.retry_on_navigation = true,
+            "--keep-browser-on-crash" => flags.keep_browser_on_crash = true,
+            "--keep-alive" => flags.keep_alive = true,
+            "--no-color" => flags.no_color = true,
+            "--quiet" => flags.quiet = true,
+            "--verbose" => flags.verbose = true,
+            "--dry-run" => flags.dry_run = true,
+            "--table" => flags.output = Some("table".to_string()),
+            "--ndjson" => {
+                flags.output = Some("ndjson".to_string());
+                flags.json = true;
+            }
+            "--field" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.field = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--max-col-width" => {
+                if let Some(n) = args.get(i + 1) {
+                    flags.max_col_width = Some(n.clone());
+                    i += 1;
+                }
+            }
+            "--color" => {
+                if let Some(mode) = args.get(i + 1) {
+                    flags.color = Some(mode.clone());
+                    i += 1;
+                }
+            }
+            arg if arg.starts_with("--color=") => flags.color = Some(arg["--color=".len()..].to_string()),
+            "--no-sandbox" => flags.no_sandbox = true,
+            "--connect-existing" => flags.connect_existing = true,
+            "--compact-sessions" => flags.compact_sessions = true,
+            "--fail-on-empty" => flags.fail_on_empty = true,
+            "--emit-id" => flags.emit_id = true,
+            "--flatten" => flags.flatten = true,
+            "--parse-only" => flags.parse_only = true,
+            "--stdin" => flags.stdin = true,
+            "--tcp" => flags.tcp = true,
+            "--output-append" => flags.output_append = true,
+            "--output-on-success-data" => flags.output_on_success_data = true,
+            "--selector-file" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.selector_file = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--output-on-success" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.output_on_success = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--output-split-by" => {
+                if let Some(field) = args.get(i + 1) {
+                    flags.output_split_by = Some(field.clone());
+                    i += 1;
+                }
+            }
+            "--output-dir" => {
+                if let Some(dir) = args.get(i + 1) {
+                    flags.output_dir = Some(dir.clone());
+                    i += 1;
+                }
+            }
+            "--output-file" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.output_file = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--throttle-network" => {
+                if let Some(profile) = args.get(i + 1) {
+                    flags.throttle_network = Some(profile.clone());
+                    i += 1;
+                }
+            }
+            "--throttle-cpu" => {
+                if let Some(factor) = args.get(i + 1) {
+                    flags.throttle_cpu = Some(factor.clone());
+                    i += 1;
+                }
+            }
+            "--result-field" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.result_field = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--template" => {
+                if let Some(t) = args.get(i + 1) {
+                    flags.template = Some(t.clone());
+                    i += 1;
+                }
+            }
+            "--output-template-file" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.template_file = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--diagnostics-dir" => {
+                if let Some(dir) = args.get(i + 1) {
+                    flags.diagnostics_dir = Some(dir.clone());
+                    i += 1;
+                }
+            }
+            "--set-local-storage" => {
+                if let Some(kv) = args.get(i + 1) {
+                    flags.local_storage.push(kv.clone());
+                    i += 1;
+                }
+            }
+            "--abort-signal-file" => {
+                if let Some(path) = args.get(i + 1) {
+                    flags.abort_signal_file = Some(path.clone());
+                    i += 1;
+                }
+            }
+            "--metadata" => {
+                if let Some(kv) = args.get(i + 1) {
+                    flags.metadata.push(kv.clone());
+                    i += 1;
+                }
+            }
+            "--set-session-storage" => {
+                if let Some(kv) = args.get(i + 1) {
+                    flags.session_storage.push(kv.clone());
+                    i += 1;
+                }
+            }
+            "--record-commands" => {
+                if let Some(p) = args.get(i + 1) {
+                    flags.record_commands = Some(p.clone());
+                    i += 1;
+                }
+            }
+            "--max-idle-reconnect" => {
+                if let Some(ms) = args.get(i + 1) {
+                    flags.max_idle_reconnect = Some(ms.clone());
+                    i += 1;
+                }
+            }
+            "--timeout" if Some(i) == global_timeout => {
+                if let Some(secs) = args.get(i + 1) {
+                    flags.timeout = Some(secs.clone());
+                    i += 1;
+                }
+            }
+            "--keepalive-interval" => {
+                if let Some(secs) = args.get(i + 1) {
+                    flags.keepalive_interval = Some(secs.clone());
+                    i += 1;
+                }
+            }
+            "--max-sessions" => {
+                if let Some(n) = args.get(i + 1) {
+                    flags.max_sessions = Some(n.clone());
+                    i += 1;
+                }
+            }
+            "--max-connect-attempts" => {
+                if let Some(n) = args.get(i + 1) {
+                    flags.max_connect_attempts = Some(n.clone());
+                    i += 1;
+                }
+            }
+            "--connect-retries" => {
+                if let Some(n) = args.get(i + 1) {
+                    flags.connect_retries = Some(n.clone());
+                    i += 1;
+                }
+            }
+            "--max-concurrency" => {
+                if let Some(n) = args.get(i + 1) {
+                    flags.max_concurrency = Some(n.clone());
+                    i += 1;
+                }
+            }
+            "--redact" => {
+                if let Some(p) = args.get(i + 1) {
+                    flags.redact.push(p.clone());
+                    i += 1;
+                }
+            }
+            "--ignore-console" => {
+                if let Some(p) = args.get(i + 1) {
+                    flags.ignore_console.push(p.clone());
+                    i += 1;
+                }
+            }
             "--session" => {
                 if let Some(s) = args.get(i + 1) {
                     flags.session = s.clone();
                     i += 1;
                 }
             }
+            "--session-prefix" => {
+                if let Some(p) = args.get(i + 1) {
+                    flags.session_prefix = Some(p.clone());
+                    i += 1;
+                }
+            }
             "--headers" => {
                 if let Some(h) = args.get(i + 1) {
                     flags.headers = Some(h.clone());
@@ -65,6 +557,50 @@ pub fn parse_flags(args: &[String]) -> Flags {
                     i += 1;
                 }
             },
+            "--frame" | "--select-frame" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.frame = Some(s.clone());
+                    i += 1;
+                }
+            },
+            "--daemon-log" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.daemon_log = Some(s.clone());
+                    i += 1;
+                }
+            },
+            // Already read by `config_file`
+            "--config" => i += 1,
+            "--node" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.node = Some(s.clone());
+                    i += 1;
+                }
+            },
+            "--wait-before" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.wait_before = Some(s.clone());
+                    i += 1;
+                }
+            },
+            "--wait-after" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.wait_after = Some(s.clone());
+                    i += 1;
+                }
+            },
+            "--startup-timeout" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.startup_timeout = Some(s.clone());
+                    i += 1;
+                }
+            },
+            "--chromium-flag" => {
+                if let Some(s) = args.get(i + 1) {
+                    flags.chromium_flags.push(s.clone());
+                    i += 1;
+                }
+            },
             "--cdp" => {
                 if let Some(s) = args.get(i + 1) {
                     flags.cdp = Some(s.clone());
@@ -77,39 +613,117 @@ pub fn parse_flags(args: &[String]) -> Flags {
                     i += 1;
                 }
             }
+            "--no-proxy" => {
+                if let Some(hosts) = args.get(i + 1) {
+                    flags.no_proxy = Some(hosts.clone());
+                    i += 1;
+                }
+            }
             "-p" | "--provider" => {
                 if let Some(p) = args.get(i + 1) {
                     flags.provider = Some(p.clone());
                     i += 1;
                 }
             }
+            "--output" | "--format" => {
+                if let Some(o) = args.get(i + 1) {
+                    flags.output = Some(o.clone());
+                    i += 1;
+                    if o == "jq" {
+                        if let Some(program) = args.get(i + 1) {
+                            flags.output_jq = Some(program.clone());
+                            i += 1;
+                        }
+                    }
+                }
+            }
             _ => {}
         }
         i += 1;
     }
+    if !headed_explicit {
+        flags.headed = defaults.headed.unwrap_or_else(detect_default_headed);
+    }
     flags
 }
 
+/// Fill in launch settings saved for the session, except those given on the
+/// command line. Saved values take precedence over environment variables.
+pub fn apply_session_config(flags: &mut Flags, args: &[String], config: &SessionConfig) {
+    let given = |names: &[&str]| args.iter().any(|a| names.contains(&a.as_str()));
+    if let Some(headed) = config.headed.filter(|_| !given(&["--headed", "--headless"])) {
+        flags.headed = headed;
+    }
+    if let Some(proxy) = config.proxy.as_ref().filter(|_| !given(&["--proxy"])) {
+        flags.proxy = Some(proxy.clone());
+    }
+    if let Some(path) = config.executable_path.as_ref().filter(|_| !given(&["--executable-path"])) {
+        flags.executable_path = Some(path.clone());
+    }
+}
+
+// Global flags that should be stripped from command args
+pub const GLOBAL_FLAGS: &[&str] = &["--json", "--full", "--headed", "--headless", "--debug", "--abort-on-console-error", "--all", "--yes", "--force", "--viewport-from-terminal", "--no-spawn", "--retry-on-navigation", "--keep-browser-on-crash", "--no-sandbox", "--connect-existing", "--output-append", "--output-on-success-data", "--compact-sessions", "--fail-on-empty", "--emit-id", "--flatten", "--parse-only", "--stdin", "--tcp", "--keep-alive", "--no-color", "--quiet", "--verbose", "--dry-run", "--table", "--ndjson"];
+// Global flags that take a value (need to skip the next arg too)
+pub const GLOBAL_FLAGS_WITH_VALUE: &[&str] = &["--session", "--headers", "--executable-path", "--cdp", "--extension", "--proxy", "-p", "--provider", "--output", "--format", "--ignore-console", "--max-concurrency", "--record-commands", "--viewport", "--set-local-storage", "--metadata", "--abort-signal-file", "--set-session-storage", "--max-idle-reconnect", "--diagnostics-dir", "--throttle-network", "--throttle-cpu", "--template", "--output-template-file", "--session-prefix", "--result-field", "--max-connect-attempts", "--connect-retries", "--output-file", "--redact", "--output-split-by", "--output-dir", "--max-sessions", "--keepalive-interval", "--output-on-success", "--selector-file", "--chromium-flag", "--frame", "--select-frame", "--startup-timeout", "--wait-before", "--wait-after", "--node", "--daemon-log", "--config", "--color", "--max-col-width", "--field", "--no-proxy"];
+
+/// Commands with their own `--timeout <ms>`. For these, the global
+/// `--timeout <seconds>` has to come before the command name.
+const COMMANDS_WITH_OWN_TIMEOUT: &[&str] = &["close", "quit", "exit", "shutdown", "wait-function"];
+
+/// How many values follow the global flag at `args[i]`: one, or two for
+/// `--output jq <program>`.
+fn value_count(args: &[String], i: usize) -> usize {
+    let output = matches!(args[i].as_str(), "--output" | "--format");
+    if output && args.get(i + 1).map(String::as_str) == Some("jq") {
+        2
+    } else {
+        1
+    }
+}
+
+/// Position of the first argument that isn't a global flag or its value.
+fn command_index(args: &[String]) -> Option<usize> {
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if GLOBAL_FLAGS_WITH_VALUE.contains(&arg) || arg == "--timeout" {
+            i += 1 + value_count(args, i);
+        } else if arg.starts_with('-') {
+            i += 1;
+        } else {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Position of the `--timeout` that is the global flag rather than an
+/// option of a command listed in `COMMANDS_WITH_OWN_TIMEOUT`.
+fn global_timeout_index(args: &[String]) -> Option<usize> {
+    let command = command_index(args);
+    let owns_timeout = command.is_some_and(|c| COMMANDS_WITH_OWN_TIMEOUT.contains(&args[c].as_str()));
+    args.iter()
+        .enumerate()
+        .position(|(i, a)| a == "--timeout" && (!owns_timeout || command.is_some_and(|c| i < c)))
+}
+
 pub fn clean_args(args: &[String]) -> Vec<String> {
     let mut result = Vec::new();
-    let mut skip_next = false;
+    let mut skip = 0;
+    let global_timeout = global_timeout_index(args);
 
-    // Global flags that should be stripped from command args
-    const GLOBAL_FLAGS: &[&str] = &["--json", "--full", "--headed", "--debug"];
-    // Global flags that take a value (need to skip the next arg too)
-    const GLOBAL_FLAGS_WITH_VALUE: &[&str] = &["--session", "--headers", "--executable-path", "--cdp", "--extension", "--proxy", "-p", "--provider"];
-
-    for arg in args.iter() {
-        if skip_next {
-            skip_next = false;
+    for (i, arg) in args.iter().enumerate() {
+        if skip > 0 {
+            skip -= 1;
             continue;
         }
-        if GLOBAL_FLAGS_WITH_VALUE.contains(&arg.as_str()) {
-            skip_next = true;
+        if GLOBAL_FLAGS_WITH_VALUE.contains(&arg.as_str()) || Some(i) == global_timeout {
+            skip = value_count(args, i);
             continue;
         }
         // Only strip known global flags, not command-specific flags
-        if GLOBAL_FLAGS.contains(&arg.as_str()) || arg == "-f" {
+        if GLOBAL_FLAGS.contains(&arg.as_str()) || arg == "-f" || arg.starts_with("--color=") {
             continue;
         }
         result.push(arg.clone());
@@ -125,6 +739,10 @@ mod tests {
         s.split_whitespace().map(String::from).collect()
     }
 
+    fn parse_flags(args: &[String]) -> Flags {
+        super::parse_flags(args, &Defaults::default())
+    }
+
     #[test]
     fn test_parse_headers_flag() {
         let flags = parse_flags(&args(r#"open example.com --headers {"Auth":"token"}"#));
@@ -217,6 +835,593 @@ mod tests {
         assert_eq!(cleaned, vec!["open", "example.com"]);
     }
 
+    #[test]
+    fn test_parse_output_format_flag() {
+        let flags = parse_flags(&args("--format jsonl-stream tab list"));
+        assert_eq!(flags.output, Some("jsonl-stream".to_string()));
+        let cleaned = clean_args(&args("--output jsonl-stream tab list"));
+        assert_eq!(cleaned, vec!["tab", "list"]);
+    }
+
+    #[test]
+    fn test_parse_console_gate_flags() {
+        let input = args("--abort-on-console-error --ignore-console favicon --ignore-console ^Warning open example.com");
+        let flags = parse_flags(&input);
+        assert!(flags.abort_on_console_error);
+        assert_eq!(flags.ignore_console, vec!["favicon", "^Warning"]);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_redact_repeatable() {
+        let input = args("--redact sk-[a-z0-9]+ get text #key --redact \\d{16}");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.redact, vec!["sk-[a-z0-9]+", "\\d{16}"]);
+        assert_eq!(clean_args(&input), vec!["get", "text", "#key"]);
+    }
+
+    #[test]
+    fn test_parse_fan_out_flags() {
+        let input = args("--all --max-concurrency 2 get url");
+        let flags = parse_flags(&input);
+        assert!(flags.all_sessions);
+        assert_eq!(flags.max_concurrency, Some("2".to_string()));
+        assert_eq!(clean_args(&input), vec!["get", "url"]);
+    }
+
+    #[test]
+    fn test_parse_output_file_flags() {
+        let input = args("--output-file results.ndjson --output-append get url");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output_file, Some("results.ndjson".to_string()));
+        assert!(flags.output_append);
+        assert_eq!(clean_args(&input), vec!["get", "url"]);
+        assert!(!parse_flags(&args("get url")).output_append);
+    }
+
+    #[test]
+    fn test_parse_output_on_success() {
+        let input = args("--output-on-success last.json --output-on-success-data snapshot");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output_on_success, Some("last.json".to_string()));
+        assert!(flags.output_on_success_data);
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+    }
+
+    #[test]
+    fn test_parse_output_split_flags() {
+        let input = args("--output-split-by status --output-dir out network requests");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output_split_by, Some("status".to_string()));
+        assert_eq!(flags.output_dir, Some("out".to_string()));
+        assert_eq!(clean_args(&input), vec!["network", "requests"]);
+    }
+
+    #[test]
+    fn test_parse_keepalive_interval() {
+        let input = args("--keepalive-interval 15 network requests");
+        assert_eq!(parse_flags(&input).keepalive_interval, Some("15".to_string()));
+        assert_eq!(clean_args(&input), vec!["network", "requests"]);
+    }
+
+    #[test]
+    fn test_parse_max_sessions() {
+        let input = args("--max-sessions 5 --session a open example.com");
+        assert_eq!(parse_flags(&input).max_sessions, Some("5".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_max_connect_attempts() {
+        let input = args("--max-connect-attempts 5 snapshot");
+        assert_eq!(parse_flags(&input).max_connect_attempts, Some("5".to_string()));
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+    }
+
+    #[test]
+    fn test_parse_connect_retries() {
+        let input = args("click @e1 --connect-retries 0");
+        assert_eq!(parse_flags(&input).connect_retries, Some("0".to_string()));
+        assert_eq!(clean_args(&input), vec!["click", "@e1"]);
+    }
+
+    #[test]
+    fn test_parse_max_idle_reconnect() {
+        let input = args("--max-idle-reconnect 250 snapshot");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.max_idle_reconnect, Some("250".to_string()));
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+    }
+
+    #[test]
+    fn test_parse_retry_on_navigation() {
+        let input = args("--retry-on-navigation click #submit");
+        assert!(parse_flags(&input).retry_on_navigation);
+        assert!(!parse_flags(&args("click #submit")).retry_on_navigation);
+        assert_eq!(clean_args(&input), vec!["click", "#submit"]);
+    }
+
+    #[test]
+    fn test_parse_keep_browser_on_crash() {
+        let input = args("--keep-browser-on-crash --diagnostics-dir /tmp/diag open example.com");
+        let flags = parse_flags(&input);
+        assert!(flags.keep_browser_on_crash);
+        assert_eq!(flags.diagnostics_dir, Some("/tmp/diag".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+
+        let flags = parse_flags(&args("open example.com"));
+        assert!(!flags.keep_browser_on_crash);
+        assert!(flags.diagnostics_dir.is_none());
+    }
+
+    #[test]
+    fn test_network_throttle_profiles() {
+        let slow = NetworkThrottle::parse("slow-3g").unwrap();
+        assert_eq!(slow, NetworkThrottle { offline: false, download: 50_000.0, upload: 50_000.0, latency: 2000.0 });
+        let fast = NetworkThrottle::parse("Fast-3G").unwrap();
+        assert_eq!(fast.download, 180_000.0);
+        assert_eq!(fast.latency, 562.5);
+        assert!(NetworkThrottle::parse("offline").unwrap().offline);
+    }
+
+    #[test]
+    fn test_network_throttle_custom_triple() {
+        let custom = NetworkThrottle::parse("1600, 800,150").unwrap();
+        assert_eq!(custom, NetworkThrottle { offline: false, download: 200_000.0, upload: 100_000.0, latency: 150.0 });
+
+        assert!(NetworkThrottle::parse("1600,800").is_err());
+        assert!(NetworkThrottle::parse("2g").is_err());
+        assert_eq!(
+            NetworkThrottle::parse("1600,fast,150").unwrap_err(),
+            "up: expected a non-negative number, got 'fast'"
+        );
+        assert!(NetworkThrottle::parse("-1,800,150").is_err());
+    }
+
+    #[test]
+    fn test_cpu_throttle_validation() {
+        assert_eq!(parse_cpu_throttle("4"), Ok(4.0));
+        assert_eq!(parse_cpu_throttle("1"), Ok(1.0));
+        assert_eq!(parse_cpu_throttle("2.5"), Ok(2.5));
+        assert_eq!(parse_cpu_throttle("0.5").unwrap_err(), "expected a number >= 1, got '0.5'");
+        assert!(parse_cpu_throttle("fast").is_err());
+        assert!(parse_cpu_throttle("inf").is_err());
+
+        let input = args("--throttle-cpu 4 open example.com");
+        assert_eq!(parse_flags(&input).throttle_cpu, Some("4".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_template_flags() {
+        let input = args("--template {url} --output-template-file report.tmpl get url");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.template, Some("{url}".to_string()));
+        assert_eq!(flags.template_file, Some("report.tmpl".to_string()));
+        assert_eq!(clean_args(&input), vec!["get", "url"]);
+    }
+
+    #[test]
+    fn test_parse_session_prefix() {
+        let input = args("--session-prefix alice --session work get url");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.session_prefix, Some("alice".to_string()));
+        assert_eq!(flags.session, "work");
+        assert_eq!(clean_args(&input), vec!["get", "url"]);
+    }
+
+    #[test]
+    fn test_parse_no_sandbox() {
+        let input = args("--no-sandbox open example.com");
+        assert!(parse_flags(&input).no_sandbox);
+        assert!(!parse_flags(&args("open example.com")).no_sandbox);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_output_jq_takes_program() {
+        let input = vec!["--output".to_string(), "jq".to_string(), ".links | length".to_string(), "snapshot".to_string()];
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output.as_deref(), Some("jq"));
+        assert_eq!(flags.output_jq.as_deref(), Some(".links | length"));
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+        // Other formats still take a single value
+        let input = args("--output kv get url");
+        assert_eq!(parse_flags(&input).output_jq, None);
+        assert_eq!(clean_args(&input), vec!["get", "url"]);
+    }
+
+    #[test]
+    fn test_parse_node_path() {
+        let input = args("--node /opt/node20/bin/node open example.com");
+        assert_eq!(parse_flags(&input).node.as_deref(), Some("/opt/node20/bin/node"));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_keep_alive() {
+        let input = args("--keep-alive open example.com");
+        assert!(parse_flags(&input).keep_alive);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+        assert!(!parse_flags(&args("open example.com")).keep_alive);
+    }
+
+    #[test]
+    fn test_parse_color_flags() {
+        let flags = parse_flags(&args("--no-color open example.com"));
+        assert!(flags.no_color);
+        assert_eq!(flags.color, None);
+        assert_eq!(parse_flags(&args("--color always snapshot")).color.as_deref(), Some("always"));
+        assert_eq!(parse_flags(&args("snapshot --color=never")).color.as_deref(), Some("never"));
+        assert_eq!(clean_args(&args("--color always snapshot --color=never --no-color")), args("snapshot"));
+    }
+
+    #[test]
+    fn test_parse_quiet_verbose() {
+        let flags = parse_flags(&args("--quiet open example.com"));
+        assert!(flags.quiet && !flags.verbose);
+        let flags = parse_flags(&args("snapshot --verbose"));
+        assert!(flags.verbose && !flags.quiet);
+        assert_eq!(clean_args(&args("--quiet snapshot --verbose -i")), args("snapshot -i"));
+    }
+
+    #[test]
+    fn test_parse_dry_run() {
+        assert!(parse_flags(&args("--dry-run open example.com")).dry_run);
+        assert!(!parse_flags(&args("open example.com")).dry_run);
+        assert_eq!(clean_args(&args("doctor --fix --dry-run")), args("doctor --fix"));
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let input = args("--table --max-col-width 20 cookies get");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output.as_deref(), Some("table"));
+        assert_eq!(flags.max_col_width, Some("20".to_string()));
+        assert_eq!(clean_args(&input), vec!["cookies", "get"]);
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let input = args("console --ndjson");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.output.as_deref(), Some("ndjson"));
+        assert!(flags.json);
+        assert_eq!(clean_args(&input), vec!["console"]);
+    }
+
+    #[test]
+    fn test_parse_field() {
+        let input = args("cookies get --field data.cookies.0.value");
+        assert_eq!(parse_flags(&input).field, Some("data.cookies.0.value".to_string()));
+        assert_eq!(clean_args(&input), vec!["cookies", "get"]);
+    }
+
+    #[test]
+    fn test_parse_no_proxy() {
+        let input = args("--proxy http://proxy:8080 --no-proxy localhost,*.internal open example.com");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.no_proxy.as_deref(), Some("localhost,*.internal"));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_daemon_log() {
+        let input = args("--daemon-log /tmp/ab.log open example.com");
+        assert_eq!(parse_flags(&input).daemon_log.as_deref(), Some("/tmp/ab.log"));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_wait_before_after() {
+        let input = args("--wait-before 250 click #go --wait-after 1000");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.wait_before.as_deref(), Some("250"));
+        assert_eq!(flags.wait_after.as_deref(), Some("1000"));
+        assert_eq!(clean_args(&input), vec!["click", "#go"]);
+    }
+
+    #[test]
+    fn test_parse_delay_ms() {
+        assert_eq!(parse_delay_ms("--wait-before", "0"), Ok(Duration::ZERO));
+        assert_eq!(parse_delay_ms("--wait-after", "1500"), Ok(Duration::from_millis(1500)));
+        for bad in ["-5", "1.5", "soon", ""] {
+            let err = parse_delay_ms("--wait-after", bad).unwrap_err();
+            assert!(err.starts_with("Invalid --wait-after"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_startup_timeout() {
+        let input = args("--startup-timeout 30 open example.com");
+        assert_eq!(parse_flags(&input).startup_timeout.as_deref(), Some("30"));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_parse_frame_flag() {
+        let input = args("--frame iframe#checkout click #pay");
+        assert_eq!(parse_flags(&input).frame.as_deref(), Some("iframe#checkout"));
+        assert_eq!(clean_args(&input), vec!["click", "#pay"]);
+        let input = args("fill #card 4242 --select-frame stripe.com");
+        assert_eq!(parse_flags(&input).frame.as_deref(), Some("stripe.com"));
+        assert_eq!(clean_args(&input), vec!["fill", "#card", "4242"]);
+        assert_eq!(parse_flags(&args("click #pay")).frame, None);
+    }
+
+    #[test]
+    fn test_parse_chromium_flags_accumulate() {
+        let input = args("--chromium-flag --disable-gpu open example.com --chromium-flag --lang=fr");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.chromium_flags, vec!["--disable-gpu", "--lang=fr"]);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+        assert!(parse_flags(&args("open example.com")).chromium_flags.is_empty());
+    }
+
+    #[test]
+    fn test_validate_chromium_flags() {
+        let ok = vec!["--disable-gpu".to_string(), "--window-size=800,600".to_string()];
+        assert!(validate_chromium_flags(&ok).is_ok());
+        assert!(validate_chromium_flags(&[]).is_ok());
+        for bad in ["disable-gpu", "-d", "--", "---x", "--=1", ""] {
+            let err = validate_chromium_flags(&[bad.to_string()]).unwrap_err();
+            assert!(err.contains("--chromium-flag"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_file() {
+        let input = args("click --selector-file buttons.txt");
+        assert_eq!(parse_flags(&input).selector_file, Some("buttons.txt".to_string()));
+        assert_eq!(clean_args(&input), vec!["click"]);
+    }
+
+    #[test]
+    fn test_parse_global_timeout() {
+        let input = args("--timeout 90 screenshot --full");
+        assert_eq!(parse_flags(&input).timeout, Some("90".to_string()));
+        assert_eq!(clean_args(&input), vec!["screenshot"]);
+
+        let input = args("open example.com --timeout 0");
+        assert_eq!(parse_flags(&input).timeout, Some("0".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_command_timeout_is_not_global() {
+        // close and wait-function keep their own --timeout <ms>
+        let input = args("close --timeout 3000");
+        assert_eq!(parse_flags(&input).timeout, None);
+        assert_eq!(clean_args(&input), vec!["close", "--timeout", "3000"]);
+
+        let input = args("--session a wait-function window.ready --timeout 5000");
+        assert_eq!(parse_flags(&input).timeout, None);
+        assert_eq!(clean_args(&input), vec!["wait-function", "window.ready", "--timeout", "5000"]);
+
+        // ...but a --timeout before the command is still the global one
+        let input = args("--timeout 10 close --timeout 3000");
+        assert_eq!(parse_flags(&input).timeout, Some("10".to_string()));
+        assert_eq!(clean_args(&input), vec!["close", "--timeout", "3000"]);
+    }
+
+    #[test]
+    fn test_shutdown_keeps_its_timeout() {
+        let input = args("shutdown --timeout 3000");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.timeout, None);
+        let cmd = crate::commands::parse_command(&clean_args(&input), &flags).unwrap();
+        assert_eq!(cmd["action"], "shutdown");
+        assert_eq!(cmd["timeout"], 3000);
+    }
+
+    #[test]
+    fn test_parse_abort_signal_file() {
+        let input = args("wait-function window.done --abort-signal-file /tmp/stop");
+        assert_eq!(parse_flags(&input).abort_signal_file, Some("/tmp/stop".to_string()));
+        assert_eq!(clean_args(&input), vec!["wait-function", "window.done"]);
+    }
+
+    #[test]
+    fn test_parse_tcp_flag() {
+        let input = args("--tcp --session debug snapshot");
+        assert!(parse_flags(&input).tcp);
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+        assert!(!parse_flags(&args("snapshot")).tcp);
+    }
+
+    #[test]
+    fn test_parse_stdin_flag() {
+        let input = args("--stdin --session work");
+        let flags = parse_flags(&input);
+        assert!(flags.stdin);
+        assert_eq!(flags.session, "work");
+        assert!(clean_args(&input).is_empty());
+    }
+
+    #[test]
+    fn test_parse_only_flag() {
+        let input = args("--parse-only click @e1 --json");
+        assert!(parse_flags(&input).parse_only);
+        assert_eq!(clean_args(&input), vec!["click", "@e1"]);
+    }
+
+    #[test]
+    fn test_parse_flatten() {
+        let input = args("--output kv --flatten get box #hero");
+        let flags = parse_flags(&input);
+        assert!(flags.flatten);
+        assert_eq!(flags.output, Some("kv".to_string()));
+        assert_eq!(clean_args(&input), vec!["get", "box", "#hero"]);
+    }
+
+    #[test]
+    fn test_parse_emit_id() {
+        let input = args("--emit-id click #submit");
+        assert!(parse_flags(&input).emit_id);
+        assert_eq!(clean_args(&input), vec!["click", "#submit"]);
+    }
+
+    #[test]
+    fn test_parse_fail_on_empty() {
+        let input = args("count .result --fail-on-empty");
+        assert!(parse_flags(&input).fail_on_empty);
+        assert_eq!(clean_args(&input), vec!["count", ".result"]);
+    }
+
+    #[test]
+    fn test_parse_compact_sessions() {
+        let input = args("--compact-sessions --session a snapshot --compact");
+        let flags = parse_flags(&input);
+        assert!(flags.compact_sessions);
+        assert_eq!(flags.session, "a");
+        // snapshot's own --compact is left alone
+        assert_eq!(clean_args(&input), vec!["snapshot", "--compact"]);
+    }
+
+    #[test]
+    fn test_parse_connect_existing() {
+        let input = args("--cdp 9222 --connect-existing snapshot");
+        assert!(parse_flags(&input).connect_existing);
+        assert!(!parse_flags(&args("--cdp 9222 snapshot")).connect_existing);
+        assert_eq!(clean_args(&input), vec!["snapshot"]);
+    }
+
+    #[test]
+    fn test_parse_result_field() {
+        let input = args("--result-field checks.passed eval check()");
+        assert_eq!(parse_flags(&input).result_field, Some("checks.passed".to_string()));
+        assert_eq!(clean_args(&input), vec!["eval", "check()"]);
+    }
+
+    #[test]
+    fn test_parse_throttle_network_flag() {
+        let input = args("--throttle-network slow-3g open example.com");
+        assert_eq!(parse_flags(&input).throttle_network, Some("slow-3g".to_string()));
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_default_headed_matrix() {
+        // Linux follows the display
+        assert!(default_headed("linux", true, false, false));
+        assert!(!default_headed("linux", false, false, false));
+        // Desktop platforms are headed unless in CI or over SSH
+        assert!(default_headed("macos", false, false, false));
+        assert!(default_headed("windows", false, false, false));
+        // CI and SSH always mean headless
+        assert!(!default_headed("linux", true, true, false));
+        assert!(!default_headed("macos", true, true, false));
+        assert!(!default_headed("linux", true, false, true));
+        assert!(!default_headed("windows", false, false, true));
+    }
+
+    #[test]
+    fn test_explicit_headed_and_headless_win() {
+        assert!(parse_flags(&args("--headed open example.com")).headed);
+        assert!(!parse_flags(&args("--headless open example.com")).headed);
+        assert!(!parse_flags(&args("--headed --headless open example.com")).headed);
+        assert_eq!(clean_args(&args("--headless open example.com")), vec!["open", "example.com"]);
+    }
+
+    #[test]
+    fn test_config_defaults_precedence() {
+        let defaults = Defaults {
+            session: Some("from-file".to_string()),
+            json: Some(true),
+            headed: Some(true),
+            proxy: Some("http://file:8080".to_string()),
+            executable_path: None,
+        };
+        // Built-in defaults when the file says nothing
+        let flags = parse_flags(&args("open example.com"));
+        assert!(!flags.json);
+        assert_eq!(flags.proxy, None);
+
+        // The file beats built-in defaults
+        let flags = super::parse_flags(&args("open example.com"), &defaults);
+        assert!(flags.json);
+        assert!(flags.headed);
+        assert_eq!(flags.proxy.as_deref(), Some("http://file:8080"));
+        if env::var_os("AGENT_BROWSER_SESSION").is_none() {
+            assert_eq!(flags.session, "from-file");
+        }
+
+        // The command line beats the file
+        let flags =
+            super::parse_flags(&args("--session cli --headless open example.com --proxy http://cli:1"), &defaults);
+        assert_eq!(flags.session, "cli");
+        assert!(!flags.headed);
+        assert_eq!(flags.proxy.as_deref(), Some("http://cli:1"));
+    }
+
+    #[test]
+    fn test_config_file_flag() {
+        let argv = args("--config /tmp/ab.toml open example.com");
+        assert_eq!(config_file(&argv).as_deref(), Some("/tmp/ab.toml"));
+        assert_eq!(clean_args(&argv), args("open example.com"));
+    }
+
+    #[test]
+    fn test_session_config_fills_unset_flags() {
+        let config = SessionConfig {
+            headed: Some(true),
+            proxy: Some("http://saved:8080".to_string()),
+            executable_path: Some("/opt/saved-chrome".to_string()),
+        };
+        let argv = args("open example.com");
+        let mut flags = parse_flags(&argv);
+        apply_session_config(&mut flags, &argv, &config);
+        assert!(flags.headed);
+        assert_eq!(flags.proxy.as_deref(), Some("http://saved:8080"));
+        assert_eq!(flags.executable_path.as_deref(), Some("/opt/saved-chrome"));
+
+        // Command-line flags win
+        let argv = args("--headless open example.com --proxy http://cli:1 --executable-path /cli");
+        let mut flags = parse_flags(&argv);
+        apply_session_config(&mut flags, &argv, &config);
+        assert!(!flags.headed);
+        assert_eq!(flags.proxy.as_deref(), Some("http://cli:1"));
+        assert_eq!(flags.executable_path.as_deref(), Some("/cli"));
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        assert_eq!(parse_key_value("token=abc"), Some(("token", "abc")));
+        assert_eq!(parse_key_value("jwt=a.b=c"), Some(("jwt", "a.b=c")));
+        assert_eq!(parse_key_value("empty="), Some(("empty", "")));
+        assert_eq!(parse_key_value("=value"), None);
+        assert_eq!(parse_key_value("novalue"), None);
+    }
+
+    #[test]
+    fn test_parse_metadata_flags() {
+        let input = args("--metadata task=signup click @e1 --metadata step=2");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.metadata, vec!["task=signup", "step=2"]);
+        assert_eq!(clean_args(&input), vec!["click", "@e1"]);
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let pairs = vec!["task=signup".to_string(), "query=a=b".to_string(), "task=login".to_string()];
+        let map = parse_metadata(&pairs).unwrap();
+        assert_eq!(Value::Object(map), serde_json::json!({ "task": "login", "query": "a=b" }));
+        assert!(parse_metadata(&[]).unwrap().is_empty());
+        assert_eq!(
+            parse_metadata(&["oops".to_string()]).unwrap_err(),
+            "Invalid --metadata 'oops': expected key=value"
+        );
+    }
+
+    #[test]
+    fn test_parse_storage_injection_flags() {
+        let input = args("open example.com --set-local-storage token=abc --set-local-storage theme=dark --set-session-storage tab=1");
+        let flags = parse_flags(&input);
+        assert_eq!(flags.local_storage, vec!["token=abc", "theme=dark"]);
+        assert_eq!(flags.session_storage, vec!["tab=1"]);
+        assert_eq!(clean_args(&input), vec!["open", "example.com"]);
+    }
+
     #[test]
     fn test_parse_flags_with_session_and_executable_path() {
         let flags = parse_flags(&args("--session test --executable-path /custom/chrome open example.com"));
@@ -1,4 +1,5 @@
 use crate::color;
+use crate::log;
 use std::process::{exit, Command, Stdio};
 
 pub fn run_install(with_deps: bool) {
@@ -121,11 +122,8 @@ pub fn run_install(with_deps: bool) {
                 Ok(s) if s.success() => {
                     println!("{} System dependencies installed", color::success_indicator())
                 }
-                Ok(_) => eprintln!(
-                    "{} Failed to install some dependencies. You may need to run manually with sudo.",
-                    color::warning_indicator()
-                ),
-                Err(e) => eprintln!("{} Could not run install command: {}", color::warning_indicator(), e),
+                Ok(_) => log::warn("Failed to install some dependencies. You may need to run manually with sudo."),
+                Err(e) => log::warn(format!("Could not run install command: {}", e)),
             }
         } else {
             println!("{} Linux detected. If browser fails to launch, run:", color::warning_indicator());
@@ -0,0 +1,70 @@
+//! Diagnostics on stderr, filtered by `--quiet` and `--verbose`.
+//!
+//! Command results and errors are printed whatever the level; this only
+//! covers warnings and the extra detail `--verbose` asks for. Both go to
+//! stderr, so they never mix with `--json` output.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use crate::color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// `--quiet`: errors only.
+    Quiet,
+    Normal,
+    /// `--verbose`: also where the CLI connects, how it starts the daemon,
+    /// and each retry.
+    Verbose,
+}
+
+impl Verbosity {
+    pub fn from_flags(quiet: bool, verbose: bool) -> Result<Self, String> {
+        match (quiet, verbose) {
+            (true, true) => Err("--quiet and --verbose can't be combined".to_string()),
+            (true, false) => Ok(Verbosity::Quiet),
+            (false, true) => Ok(Verbosity::Verbose),
+            (false, false) => Ok(Verbosity::Normal),
+        }
+    }
+}
+
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// Set the level from `--quiet` / `--verbose`. Call once at startup.
+pub fn set_verbosity(verbosity: Verbosity) {
+    let _ = VERBOSITY.set(verbosity);
+}
+
+pub fn verbosity() -> Verbosity {
+    VERBOSITY.get().copied().unwrap_or(Verbosity::Normal)
+}
+
+/// Print a warning, unless `--quiet`.
+pub fn warn(msg: impl Display) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{} {}", color::warning_indicator(), msg);
+    }
+}
+
+/// Print a detail for `--verbose`.
+pub fn verbose(msg: impl Display) {
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("{} {}", color::dim("[verbose]"), msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::from_flags(false, false), Ok(Verbosity::Normal));
+        assert_eq!(Verbosity::from_flags(true, false), Ok(Verbosity::Quiet));
+        assert_eq!(Verbosity::from_flags(false, true), Ok(Verbosity::Verbose));
+        assert!(Verbosity::from_flags(true, true).is_err());
+        assert!(Verbosity::Quiet < Verbosity::Normal && Verbosity::Normal < Verbosity::Verbose);
+    }
+}
@@ -0,0 +1,198 @@
+//! Client-side filtering and table rendering for `network` live tailing.
+
+use serde_json::Value;
+
+use crate::parse;
+
+/// One entry of a `--status` filter: an exact code, a class like `4xx`, or a
+/// range like `200-299`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusMatch {
+    Exact(u16),
+    Class(u16),
+    Range(u16, u16),
+}
+
+impl StatusMatch {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_lowercase();
+        if let Some(digit) = s.strip_suffix("xx") {
+            let class = digit.parse::<u16>().ok().filter(|d| (1..=5).contains(d))?;
+            return Some(StatusMatch::Class(class));
+        }
+        if s.contains('-') {
+            let (start, end) = parse::range(&s).ok()?;
+            let valid = 100..=599;
+            if !valid.contains(&start) || !valid.contains(&end) {
+                return None;
+            }
+            return Some(StatusMatch::Range(start as u16, end as u16));
+        }
+        let code = s.parse::<u16>().ok().filter(|c| (100..=599).contains(c))?;
+        Some(StatusMatch::Exact(code))
+    }
+
+    fn matches(&self, status: u16) -> bool {
+        match *self {
+            StatusMatch::Exact(code) => status == code,
+            StatusMatch::Class(class) => status / 100 == class,
+            StatusMatch::Range(start, end) => (start..=end).contains(&status),
+        }
+    }
+}
+
+/// Filters applied to streamed network events before they are printed.
+#[derive(Debug, Default)]
+pub struct NetworkFilter {
+    methods: Vec<String>,
+    statuses: Vec<StatusMatch>,
+}
+
+impl NetworkFilter {
+    /// Build a filter from comma-separated `--method` and `--status` values.
+    ///
+    /// Returns the offending entry if a status is not a code, `Nxx` or `START-END`.
+    pub fn new(methods: Option<&str>, statuses: Option<&str>) -> Result<Self, String> {
+        let methods = methods
+            .map(|m| {
+                m.split(',')
+                    .map(|s| s.trim().to_uppercase())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut parsed = Vec::new();
+        for entry in statuses.into_iter().flat_map(|s| s.split(',')) {
+            if entry.trim().is_empty() {
+                continue;
+            }
+            parsed.push(StatusMatch::parse(entry).ok_or_else(|| entry.trim().to_string())?);
+        }
+        Ok(NetworkFilter {
+            methods,
+            statuses: parsed,
+        })
+    }
+
+    /// Whether a network event passes the method and status filters.
+    ///
+    /// Events without a status (still pending) only match when no status
+    /// filter is set.
+    pub fn matches(&self, event: &Value) -> bool {
+        if !self.methods.is_empty() {
+            let method = event.get("method").and_then(|v| v.as_str()).unwrap_or("");
+            if !self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+                return false;
+            }
+        }
+        if !self.statuses.is_empty() {
+            let Some(status) = event.get("status").and_then(|v| v.as_u64()) else {
+                return false;
+            };
+            let status = status as u16;
+            if !self.statuses.iter().any(|s| s.matches(status)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Header line for the live table.
+pub fn table_header() -> String {
+    format!("{:<7} {:>6} {:>9}  {}", "METHOD", "STATUS", "DURATION", "URL")
+}
+
+/// Render one network event as a fixed-width table row.
+pub fn table_row(event: &Value) -> String {
+    let method = event.get("method").and_then(|v| v.as_str()).unwrap_or("GET");
+    let status = event
+        .get("status")
+        .and_then(|v| v.as_u64())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let duration = event
+        .get("duration")
+        .and_then(|v| v.as_f64())
+        .map(|d| format!("{:.0}ms", d))
+        .unwrap_or_else(|| "-".to_string());
+    let url = event.get("url").and_then(|v| v.as_str()).unwrap_or("");
+    format!("{:<7} {:>6} {:>9}  {}", method, status, duration, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(method: &str, status: u16) -> Value {
+        json!({ "method": method, "status": status, "duration": 12.4, "url": "https://example.com/api" })
+    }
+
+    #[test]
+    fn test_status_class_matching() {
+        let filter = NetworkFilter::new(None, Some("4xx,5xx")).unwrap();
+        assert!(filter.matches(&event("GET", 404)));
+        assert!(filter.matches(&event("GET", 500)));
+        assert!(filter.matches(&event("GET", 599)));
+        assert!(!filter.matches(&event("GET", 200)));
+        assert!(!filter.matches(&event("GET", 399)));
+    }
+
+    #[test]
+    fn test_status_exact_and_mixed() {
+        let filter = NetworkFilter::new(None, Some("200, 3xx")).unwrap();
+        assert!(filter.matches(&event("GET", 200)));
+        assert!(filter.matches(&event("GET", 302)));
+        assert!(!filter.matches(&event("GET", 201)));
+    }
+
+    #[test]
+    fn test_status_range() {
+        let filter = NetworkFilter::new(None, Some("200-299,404")).unwrap();
+        assert!(filter.matches(&event("GET", 200)));
+        assert!(filter.matches(&event("GET", 299)));
+        assert!(filter.matches(&event("GET", 404)));
+        assert!(!filter.matches(&event("GET", 300)));
+        assert_eq!(NetworkFilter::new(None, Some("299-200")).unwrap_err(), "299-200");
+        assert_eq!(NetworkFilter::new(None, Some("0-999")).unwrap_err(), "0-999");
+    }
+
+    #[test]
+    fn test_invalid_status_filter() {
+        assert_eq!(NetworkFilter::new(None, Some("4xx,abc")).unwrap_err(), "abc");
+        assert_eq!(NetworkFilter::new(None, Some("9xx")).unwrap_err(), "9xx");
+        assert_eq!(NetworkFilter::new(None, Some("42")).unwrap_err(), "42");
+    }
+
+    #[test]
+    fn test_method_filtering() {
+        let filter = NetworkFilter::new(Some("get,Post"), None).unwrap();
+        assert!(filter.matches(&event("GET", 200)));
+        assert!(filter.matches(&event("POST", 201)));
+        assert!(!filter.matches(&event("DELETE", 204)));
+    }
+
+    #[test]
+    fn test_method_and_status_combined() {
+        let filter = NetworkFilter::new(Some("POST"), Some("5xx")).unwrap();
+        assert!(filter.matches(&event("POST", 502)));
+        assert!(!filter.matches(&event("POST", 200)));
+        assert!(!filter.matches(&event("GET", 502)));
+    }
+
+    #[test]
+    fn test_pending_request_without_status() {
+        let pending = json!({ "method": "GET", "url": "https://example.com" });
+        assert!(NetworkFilter::default().matches(&pending));
+        assert!(!NetworkFilter::new(None, Some("2xx")).unwrap().matches(&pending));
+    }
+
+    #[test]
+    fn test_table_row() {
+        let row = table_row(&event("GET", 404));
+        assert_eq!(row, "GET        404      12ms  https://example.com/api");
+        let pending = table_row(&json!({ "method": "POST", "url": "/x" }));
+        assert_eq!(pending, "POST         -         -  /x");
+    }
+}
@@ -1,12 +1,949 @@
 use crate::color;
 use crate::connection::Response;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Alternative output formats selected with `--output <format>` (alias `--format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON value per line, flushed as soon as each element arrives
+    JsonlStream,
+    /// Errors are augmented with a hint on how to resolve them
+    PrettyErrors,
+    /// Numeric metrics in Prometheus text exposition format
+    Prometheus,
+    /// TAP summary of a batch run; the exit code reflects whether all passed
+    Check,
+    /// Failures and warnings as GitHub Actions workflow annotations
+    Github,
+    /// `key: value` lines for the top-level scalar fields of the data
+    Kv,
+    /// An aligned table of a list of objects, one row per object (alias `--table`)
+    Table,
+    /// One whole response object per line, written as each result arrives (alias `--ndjson`)
+    Ndjson,
+    /// Length-framed MessagePack encoding of the response (`msgpack` feature)
+    Msgpack,
+    /// The data reshaped by the jq program given after `jq` (`jq` feature)
+    Jq,
+}
+
+impl OutputFormat {
+    /// The format name to use when `--output` is not given: GitHub annotations
+    /// when running under GitHub Actions (`GITHUB_ACTIONS=true`).
+    pub fn default_name(github_actions: Option<&str>) -> Option<&'static str> {
+        (github_actions == Some("true")).then_some("github")
+    }
+
+    pub const NAMES: &'static [&'static str] = &[
+        "jsonl-stream",
+        "pretty-errors",
+        "prometheus",
+        "check",
+        "github",
+        "kv",
+        "table",
+        "ndjson",
+        #[cfg(feature = "msgpack")]
+        "msgpack",
+        #[cfg(feature = "jq")]
+        "jq",
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "jsonl-stream" => Some(OutputFormat::JsonlStream),
+            "pretty-errors" => Some(OutputFormat::PrettyErrors),
+            "prometheus" => Some(OutputFormat::Prometheus),
+            "check" => Some(OutputFormat::Check),
+            "github" => Some(OutputFormat::Github),
+            "kv" => Some(OutputFormat::Kv),
+            "table" => Some(OutputFormat::Table),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Some(OutputFormat::Msgpack),
+            #[cfg(feature = "jq")]
+            "jq" => Some(OutputFormat::Jq),
+            _ => None,
+        }
+    }
+}
+
+/// Look up a dotted path like `user.emails.0` in a JSON value.
+pub fn lookup_path<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(data, |current, segment| match current {
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => current.get(segment),
+    })
+}
+
+/// Render `{field}` / `{dotted.path}` placeholders from `data`, as used by
+/// `--template` and `--output-template-file`.
+///
+/// Strings are inserted verbatim, other values as compact JSON, and missing
+/// fields as an empty string. `{{` and `}}` produce literal braces.
+pub fn render_template(template: &str, data: &Value) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut path = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    path.push(c);
+                }
+                if !closed {
+                    out.push('{');
+                    out.push_str(&path);
+                    break;
+                }
+                match lookup_path(data, path.trim()) {
+                    Some(Value::String(s)) => out.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(other) => out.push_str(&other.to_string()),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Read the boolean at `path` in a response's data for `--result-field`.
+pub fn result_field(data: Option<&Value>, path: &str) -> Result<bool, String> {
+    match data.and_then(|d| lookup_path(d, path)) {
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(other) => Err(format!("--result-field {} is not a boolean (got {})", path, other)),
+        None => Err(format!("--result-field {} not found in response data", path)),
+    }
+}
+
+/// The value at `path` in the whole response (`data.url`, `data.cookies.0.value`)
+/// for `--field`: strings as they are, null as an empty string and anything
+/// else as compact JSON.
+pub fn extract_field(resp: &Response, path: &str) -> Result<String, String> {
+    let value = serde_json::to_value(resp).unwrap_or_default();
+    match lookup_path(&value, path) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(Value::Null) => Ok(String::new()),
+        Some(other) => Ok(other.to_string()),
+        None => Err(format!("--field {} not found in the response", path)),
+    }
+}
+
+/// Whether `data` holds no results, for `--fail-on-empty`: an empty array, a
+/// zero `count`, or an object whose array fields are all empty.
+pub fn is_empty_result(data: Option<&Value>) -> bool {
+    match data {
+        Some(Value::Array(items)) => items.is_empty(),
+        Some(Value::Object(map)) => {
+            if let Some(count) = map.get("count").and_then(|c| c.as_u64()) {
+                return count == 0;
+            }
+            let mut arrays = map.values().filter_map(|v| v.as_array()).peekable();
+            arrays.peek().is_some() && arrays.all(|a| a.is_empty())
+        }
+        _ => false,
+    }
+}
+
+/// Read a template file for `--output-template-file`, requiring valid UTF-8.
+pub fn load_template_file(path: &str) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Could not read template {}: {}", path, e))?;
+    String::from_utf8(bytes).map_err(|_| format!("Template {} is not valid UTF-8", path))
+}
+
+/// Render the top-level scalar fields of `data` as `key: value` lines.
+///
+/// Returns the lines and the keys of nested objects/arrays that were skipped.
+pub fn format_kv(data: &Value) -> (Vec<String>, Vec<String>) {
+    let scalar = |v: &Value| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => Some(String::new()),
+        Value::Bool(_) | Value::Number(_) => Some(v.to_string()),
+        Value::Array(_) | Value::Object(_) => None,
+    };
+    let Value::Object(map) = data else {
+        return match scalar(data) {
+            Some(value) => (vec![format!("value: {}", value)], Vec::new()),
+            None => (Vec::new(), vec!["value".to_string()]),
+        };
+    };
+    let mut lines = Vec::new();
+    let mut skipped = Vec::new();
+    for (key, value) in map {
+        match scalar(value) {
+            Some(value) => lines.push(format!("{}: {}", key, value)),
+            None => skipped.push(key.clone()),
+        }
+    }
+    (lines, skipped)
+}
+
+/// Flatten nested objects and arrays into one object with dotted keys
+/// (`viewport.width`, `links.0.href`) for `--flatten`. Empty objects and
+/// arrays are kept as leaves; scalars are returned unchanged.
+pub fn flatten(data: &Value) -> Value {
+    fn walk(prefix: &str, value: &Value, out: &mut serde_json::Map<String, Value>) {
+        let key = |k: &str| if prefix.is_empty() { k.to_string() } else { format!("{}.{}", prefix, k) };
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (k, v) in map {
+                    walk(&key(k), v, out);
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for (i, v) in items.iter().enumerate() {
+                    walk(&key(&i.to_string()), v, out);
+                }
+            }
+            leaf => {
+                out.insert(prefix.to_string(), leaf.clone());
+            }
+        }
+    }
+    match data {
+        Value::Object(map) if map.is_empty() => data.clone(),
+        Value::Array(items) if items.is_empty() => data.clone(),
+        Value::Object(_) | Value::Array(_) => {
+            let mut out = serde_json::Map::new();
+            walk("", data, &mut out);
+            Value::Object(out)
+        }
+        _ => data.clone(),
+    }
+}
+
+/// Print a response's data with `--output kv`, noting skipped fields on stderr.
+pub fn print_kv(resp: &Response) {
+    let (lines, skipped) = format_kv(resp.data.as_ref().unwrap_or(&Value::Null));
+    for line in lines {
+        println!("{}", line);
+    }
+    if !skipped.is_empty() {
+        eprintln!(
+            "{} skipped nested fields: {} (use --json to see them)",
+            color::dim("note:"),
+            skipped.join(", ")
+        );
+    }
+}
+
+/// Column width for `--output table` when `--max-col-width` is not given.
+pub const DEFAULT_MAX_COL_WIDTH: usize = 40;
+
+/// The rows for `--output table`: `data` itself or its first array-valued
+/// field (`cookies`, `tabs`), provided it is a non-empty list of objects.
+fn table_rows(data: &Value) -> Option<&Vec<Value>> {
+    split_target(data).filter(|items| !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// Render `items` as an aligned table with one column per key, in key order.
+///
+/// Strings are shown verbatim, other values as compact JSON and missing keys
+/// as blanks. Cells longer than `max_width` characters end in `…`.
+pub fn format_table(items: &[Value], max_width: usize) -> Vec<String> {
+    let mut columns: Vec<&str> = items
+        .iter()
+        .filter_map(Value::as_object)
+        .flat_map(|map| map.keys().map(String::as_str))
+        .collect();
+    columns.sort_unstable();
+    columns.dedup();
+
+    let truncate = |text: &str| {
+        let text = text.replace(['\n', '\r', '\t'], " ");
+        if text.chars().count() > max_width {
+            let kept: String = text.chars().take(max_width.saturating_sub(1)).collect();
+            format!("{}…", kept)
+        } else {
+            text
+        }
+    };
+    let cell = |item: &Value, column: &str| match item.get(column) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => truncate(s),
+        Some(other) => truncate(&other.to_string()),
+    };
+    let mut rows: Vec<Vec<String>> = vec![columns.iter().map(|c| truncate(c)).collect()];
+    rows.extend(items.iter().map(|item| columns.iter().map(|c| cell(item, c)).collect()));
+
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    let render = |row: &[String]| {
+        let padded: Vec<String> = row.iter().zip(&widths).map(|(text, &width)| format!("{:<width$}", text)).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+
+    let mut lines = vec![render(&rows[0]), rule.join("  ")];
+    lines.extend(rows[1..].iter().map(|row| render(row)));
+    lines
+}
+
+/// Print a response's data with `--output table`. Data that isn't a list of
+/// objects is printed as usual, with a note on stderr.
+pub fn print_table(resp: &Response, max_width: usize) {
+    match resp.data.as_ref().and_then(table_rows) {
+        Some(items) => {
+            for line in format_table(items, max_width) {
+                println!("{}", line);
+            }
+        }
+        None => {
+            eprintln!("{} no list of objects to show as a table", color::dim("note:"));
+            print_response(resp, false);
+        }
+    }
+}
+
+/// Format a GitHub Actions workflow command such as `::error::<message>`.
+///
+/// `%`, CR and LF are percent-encoded so multi-line messages stay one annotation.
+pub fn github_annotation(level: &str, message: &str) -> String {
+    let escaped = message
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    format!("::{}::{}", level, escaped)
+}
+
+/// Render batch results as TAP: a plan line, then `ok N - <description>` or
+/// `not ok N - <description>` with the failure message in a YAML block.
+pub fn format_tap(results: &[(String, Result<(), String>)]) -> String {
+    let mut text = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, (description, outcome)) in results.iter().enumerate() {
+        match outcome {
+            Ok(()) => text.push_str(&format!("ok {} - {}\n", i + 1, description)),
+            Err(message) => {
+                text.push_str(&format!("not ok {} - {}\n", i + 1, description));
+                text.push_str("  ---\n");
+                text.push_str(&format!(
+                    "  message: {}\n",
+                    serde_json::to_string(message).unwrap_or_default()
+                ));
+                text.push_str("  ...\n");
+            }
+        }
+    }
+    text
+}
+
+/// Convert a metric key like `domContentLoaded` or `first-paint` to `dom_content_loaded`/`first_paint`.
+fn prometheus_name(key: &str) -> String {
+    let mut name = String::new();
+    for (i, c) in key.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !name.ends_with('_') {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_matches('_').to_string()
+}
+
+/// Escape a label value per the Prometheus text format.
+fn prometheus_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render numeric metrics in Prometheus exposition format, labelled by URL.
+///
+/// Nested objects are flattened with `_`; non-numeric values are skipped.
+pub fn format_prometheus(metrics: &Value, url: Option<&str>) -> String {
+    fn collect(prefix: &str, value: &Value, out: &mut Vec<(String, f64)>) {
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map {
+                    let name = prometheus_name(key);
+                    let name = if prefix.is_empty() { name } else { format!("{}_{}", prefix, name) };
+                    collect(&name, v, out);
+                }
+            }
+            Value::Number(n) => {
+                if let Some(f) = n.as_f64() {
+                    out.push((prefix.to_string(), f));
+                }
+            }
+            Value::Bool(b) => out.push((prefix.to_string(), if *b { 1.0 } else { 0.0 })),
+            _ => {}
+        }
+    }
+
+    let mut samples = Vec::new();
+    collect("", metrics, &mut samples);
+    let labels = url
+        .map(|u| format!("{{url=\"{}\"}}", prometheus_escape(u)))
+        .unwrap_or_default();
+
+    let mut text = String::new();
+    for (name, value) in samples {
+        let name = format!("agent_browser_{}", name);
+        text.push_str(&format!("# TYPE {} gauge\n", name));
+        text.push_str(&format!("{}{} {}\n", name, labels, value));
+    }
+    text
+}
+
+/// Print a response's metrics (`data.metrics`, or `data` itself) for Prometheus.
+pub fn print_prometheus(resp: &Response) {
+    let Some(data) = &resp.data else {
+        return;
+    };
+    let metrics = data.get("metrics").unwrap_or(data);
+    let url = data.get("url").and_then(|v| v.as_str());
+    print!("{}", format_prometheus(metrics, url));
+}
+
+/// Turn a `cdp_url` reply without a websocket endpoint into a clear failure.
+///
+/// The daemon only has an endpoint when it drives a CDP-backed browser.
+pub fn require_cdp_endpoint(resp: &mut Response) {
+    if !resp.success {
+        return;
+    }
+    let has_endpoint = resp
+        .data
+        .as_ref()
+        .and_then(|d| d.get("wsEndpoint"))
+        .and_then(|v| v.as_str())
+        .is_some_and(|s| !s.is_empty());
+    if !has_endpoint {
+        resp.success = false;
+        resp.error = Some("No CDP endpoint: this session is not CDP-backed".to_string());
+        resp.error_code = Some("no_cdp_endpoint".to_string());
+    }
+}
+
+/// Render a navigation summary (`status`, `url`, `redirects`, `title`,
+/// `loadTime`) as a title line followed by indented detail lines.
+fn format_navigation_summary(summary: &Value) -> Vec<String> {
+    let url = summary.get("url").and_then(|v| v.as_str()).unwrap_or("");
+    let title = summary
+        .get("title")
+        .and_then(|v| v.as_str())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(url);
+    let mut detail = summary
+        .get("status")
+        .and_then(|v| v.as_u64())
+        .map(|s| format!("{} {}", s, url))
+        .unwrap_or_else(|| url.to_string());
+    if let Some(ms) = summary.get("loadTime").and_then(|v| v.as_f64()) {
+        detail.push_str(&format!(" in {:.0}ms", ms));
+    }
+
+    let mut lines = vec![title.to_string(), format!("  {}", detail)];
+    let redirects: Vec<&str> = summary
+        .get("redirects")
+        .and_then(|v| v.as_array())
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    if !redirects.is_empty() {
+        lines.push(format!("  redirected: {} -> {}", redirects.join(" -> "), url));
+    }
+    lines
+}
+
+/// Suggested next step for a known error code.
+pub fn error_hint(code: &str) -> Option<&'static str> {
+    match code {
+        "element_not_found" => Some("check the selector with `agent-browser get count <selector>`, or take a fresh `snapshot` for refs"),
+        "timeout" => Some("the page may still be loading; try `agent-browser wait --load networkidle` first"),
+        "navigation_failed" => Some("verify the URL is reachable and any --proxy settings are correct"),
+        "execution_context_destroyed" => Some("the page navigated during the command; wait for the new page and retry"),
+        "browser_not_launched" => Some("open a page first with `agent-browser open <url>`"),
+        "connection_failed" => Some("the daemon may have exited; run `agent-browser session list` to check"),
+        "no_cdp_endpoint" => Some("start the browser with --remote-debugging-port and attach it with --cdp <port>"),
+        _ => None,
+    }
+}
+
+/// Infer an error code from a daemon error message when none was provided.
+pub fn infer_error_code(message: &str) -> Option<&'static str> {
+    let lower = message.to_lowercase();
+    if lower.contains("execution context was destroyed") {
+        Some("execution_context_destroyed")
+    } else if lower.contains("timeout") {
+        Some("timeout")
+    } else if lower.contains("no element") || lower.contains("not found") || lower.contains("resolved to 0 elements") {
+        Some("element_not_found")
+    } else if lower.contains("net::err_") {
+        Some("navigation_failed")
+    } else if lower.contains("browser not launched") {
+        Some("browser_not_launched")
+    } else if lower.contains("failed to connect") {
+        Some("connection_failed")
+    } else {
+        None
+    }
+}
+
+/// Print a failed response with a hint for known error codes.
+///
+/// In JSON mode the hint is added as a `hint` field; otherwise it is rendered
+/// below the error line.
+pub fn print_pretty_error(resp: &Response, json_mode: bool) {
+    let message = resp.error.as_deref().unwrap_or("Unknown error");
+    let code = resp
+        .error_code
+        .as_deref()
+        .or_else(|| infer_error_code(message));
+    let hint = code.and_then(error_hint);
+
+    if json_mode {
+        let mut value = serde_json::to_value(resp).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            if let Some(code) = code {
+                obj.insert("errorCode".to_string(), Value::from(code));
+            }
+            if let Some(hint) = hint {
+                obj.insert("hint".to_string(), Value::from(hint));
+            }
+        }
+        println!("{}", value);
+        return;
+    }
+
+    eprintln!("{} {}", color::error_indicator(), message);
+    if let Some(hint) = hint {
+        eprintln!("  {} {}", color::dim("hint:"), hint);
+    }
+}
+
+/// Write a single streamed element as one JSON line and flush immediately.
+pub fn write_stream_item<W: Write, T: Serialize + ?Sized>(out: &mut W, item: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, item)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+/// NDJSON writer for streaming commands that keeps errors in-band.
+///
+/// Successful items are written as their `data`; failures become
+/// `{"success":false,"error":...,"index":N}` lines on the same stream, where
+/// `N` is the position of the failed element.
+pub struct StreamWriter<W: Write> {
+    out: W,
+    index: usize,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(out: W) -> Self {
+        StreamWriter { out, index: 0 }
+    }
+
+    /// Write one streamed element, successful or not.
+    pub fn write_response(&mut self, resp: &Response) -> io::Result<()> {
+        if resp.success {
+            self.write_item(resp.data.as_ref().unwrap_or(&Value::Null))
+        } else {
+            self.write_error(resp.error.as_deref().unwrap_or("Unknown error"))
+        }
+    }
+
+    pub fn write_item(&mut self, item: &Value) -> io::Result<()> {
+        write_stream_item(&mut self.out, item)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    pub fn write_error(&mut self, message: &str) -> io::Result<()> {
+        let line = serde_json::json!({ "success": false, "error": message, "index": self.index });
+        write_stream_item(&mut self.out, &line)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Finish the stream once the daemon has sent its final line.
+    ///
+    /// If nothing was streamed incrementally, an array in `data` is emitted one
+    /// element per line (any other value as a single line) so the output shape
+    /// is the same whether or not the daemon supports partial responses.
+    pub fn finish(&mut self, resp: &Response) -> io::Result<()> {
+        if !resp.success {
+            return self.write_error(resp.error.as_deref().unwrap_or("Unknown error"));
+        }
+        if self.index > 0 {
+            return Ok(());
+        }
+        match &resp.data {
+            Some(Value::Array(items)) => {
+                for item in items {
+                    self.write_item(item)?;
+                }
+                Ok(())
+            }
+            Some(data) => self.write_item(data),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writer for `--ndjson`: unlike [`StreamWriter`], every line is a whole
+/// response object in the `--json` shape, so consumers can parse each one on
+/// its own and tell results from failures by `success`.
+///
+/// Streamed results are written as they arrive. The final response follows;
+/// it keeps its `data` only if nothing was streamed, so a command without
+/// partial results prints exactly what `--json` would.
+pub struct NdjsonWriter<W: Write> {
+    out: W,
+    streamed: bool,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(out: W) -> Self {
+        NdjsonWriter { out, streamed: false }
+    }
+
+    /// Write one streamed result.
+    pub fn write_partial(&mut self, resp: &Response) -> io::Result<()> {
+        self.streamed = true;
+        write_stream_item(&mut self.out, resp)
+    }
+
+    /// Write the daemon's final response.
+    pub fn finish(&mut self, resp: &Response) -> io::Result<()> {
+        if !self.streamed {
+            return write_stream_item(&mut self.out, resp);
+        }
+        let mut line = serde_json::to_value(resp).unwrap_or_default();
+        if let Some(obj) = line.as_object_mut() {
+            obj.remove("data");
+        }
+        write_stream_item(&mut self.out, &line)
+    }
+
+    /// Write a failure to exchange the command at all, e.g. a lost connection.
+    pub fn write_error(&mut self, message: &str, kind: &str) -> io::Result<()> {
+        let line = serde_json::json!({ "success": false, "error": message, "type": kind });
+        write_stream_item(&mut self.out, &line)
+    }
+}
+
+/// The line-per-result output formats (`jsonl-stream`, `ndjson`), so one
+/// streaming loop can drive either writer.
+pub trait ResultStream {
+    /// Write one result the daemon streamed before its final response.
+    fn partial(&mut self, resp: &Response) -> io::Result<()>;
+    /// Write the daemon's final response.
+    fn done(&mut self, resp: &Response) -> io::Result<()>;
+    /// Write a failure to exchange the command at all.
+    fn failed(&mut self, message: &str, kind: &str) -> io::Result<()>;
+}
+
+impl<W: Write> ResultStream for StreamWriter<W> {
+    fn partial(&mut self, resp: &Response) -> io::Result<()> {
+        self.write_response(resp)
+    }
+
+    fn done(&mut self, resp: &Response) -> io::Result<()> {
+        self.finish(resp)
+    }
+
+    fn failed(&mut self, message: &str, _kind: &str) -> io::Result<()> {
+        self.write_error(message)
+    }
+}
+
+impl<W: Write> ResultStream for NdjsonWriter<W> {
+    fn partial(&mut self, resp: &Response) -> io::Result<()> {
+        self.write_partial(resp)
+    }
+
+    fn done(&mut self, resp: &Response) -> io::Result<()> {
+        self.finish(resp)
+    }
+
+    fn failed(&mut self, message: &str, kind: &str) -> io::Result<()> {
+        self.write_error(message, kind)
+    }
+}
+
+/// Make a field value safe to use as a file name: anything but ASCII
+/// alphanumerics, `-`, `_` and `.` becomes `_`, and leading dots are dropped.
+pub fn sanitize_filename(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .take(100)
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Group array elements by the value at `field` (a dotted path). Elements
+/// without the field are grouped under `_missing`.
+pub fn partition_by(items: &[Value], field: &str) -> BTreeMap<String, Vec<Value>> {
+    let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for item in items {
+        let key = match lookup_path(item, field) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) | None => "_missing".to_string(),
+            Some(other) => other.to_string(),
+        };
+        groups.entry(key).or_default().push(item.clone());
+    }
+    groups
+}
+
+/// The array to split for `--output-split-by`: `data` itself, or its first
+/// array-valued field (e.g. `requests`, `tabs`).
+fn split_target(data: &Value) -> Option<&Vec<Value>> {
+    match data {
+        Value::Array(items) => Some(items),
+        Value::Object(map) => map.values().find_map(|v| v.as_array()),
+        _ => None,
+    }
+}
+
+/// Write each `--output-split-by` group to `<dir>/<value>.json`, returning the
+/// files written. Values that sanitize to the same name get a `-N` suffix.
+pub fn write_split_output(data: &Value, field: &str, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let items = split_target(data).ok_or("--output-split-by needs an array in the response data")?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    let mut written: Vec<PathBuf> = Vec::new();
+    for (value, group) in partition_by(items, field) {
+        let base = sanitize_filename(&value);
+        let mut path = dir.join(format!("{}.json", base));
+        let mut n = 2;
+        while written.contains(&path) {
+            path = dir.join(format!("{}-{}.json", base, n));
+            n += 1;
+        }
+        let json = serde_json::to_string_pretty(&group).unwrap_or_default();
+        std::fs::write(&path, json).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Replacement for text matched by a `--redact` pattern.
+pub const REDACTION: &str = "***";
+
+fn redact_value(value: &mut Value, patterns: &[Regex]) {
+    match value {
+        Value::String(s) => *s = redact_text(s, patterns),
+        Value::Array(items) => items.iter_mut().for_each(|v| redact_value(v, patterns)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact_value(v, patterns)),
+        _ => {}
+    }
+}
+
+fn redact_text(text: &str, patterns: &[Regex]) -> String {
+    patterns.iter().fold(text.to_string(), |acc, re| {
+        re.replace_all(&acc, REDACTION).into_owned()
+    })
+}
+
+/// Replace `--redact` matches in every string of a response's data and in its
+/// error message.
+pub fn redact_response(resp: &mut Response, patterns: &[Regex]) {
+    if patterns.is_empty() {
+        return;
+    }
+    if let Some(data) = resp.data.as_mut() {
+        redact_value(data, patterns);
+    }
+    if let Some(error) = resp.error.as_mut() {
+        *error = redact_text(error, patterns);
+    }
+}
+
+/// Open an `--output-file` for writing: appended to with `--output-append`,
+/// truncated otherwise.
+pub fn open_output_file(path: &str, append: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if append {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    options.open(path)
+}
+
+/// Write a response to an `--output-file` as one JSON line.
+pub fn write_output_file(path: &str, append: bool, resp: &Response) -> io::Result<()> {
+    let mut file = open_output_file(path, append)?;
+    let line = serde_json::to_string(resp).map_err(io::Error::other)?;
+    writeln!(file, "{}", line)
+}
+
+/// Save a successful response for `--output-on-success`: the whole `Response`,
+/// or only its `data` with `--output-on-success-data`, as pretty JSON.
+pub fn write_success_output(path: &str, data_only: bool, resp: &Response) -> io::Result<()> {
+    let json = if data_only {
+        serde_json::to_string_pretty(resp.data.as_ref().unwrap_or(&Value::Null))
+    } else {
+        serde_json::to_string_pretty(resp)
+    };
+    let mut file = open_output_file(path, false)?;
+    writeln!(file, "{}", json.map_err(io::Error::other)?)
+}
+
+/// Encode a response as MessagePack, string keys and all, so it decodes to
+/// the same shape as the JSON output.
+#[cfg(feature = "msgpack")]
+fn encode_msgpack(resp: &Response) -> io::Result<Vec<u8>> {
+    rmp_serde::to_vec_named(resp).map_err(io::Error::other)
+}
+
+#[cfg(not(feature = "msgpack"))]
+fn encode_msgpack(_resp: &Response) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "msgpack output requires building with --features msgpack",
+    ))
+}
+
+/// Write one `--output msgpack` frame: the encoded length as a big-endian
+/// u32, then the MessagePack bytes. Frames can be concatenated on one stream.
+pub fn write_msgpack_frame<W: Write>(out: &mut W, resp: &Response) -> io::Result<()> {
+    let body = encode_msgpack(resp)?;
+    let len = u32::try_from(body.len()).map_err(io::Error::other)?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(&body)?;
+    out.flush()
+}
+
+#[cfg(feature = "jq")]
+type JqFilter = jaq_core::Filter<jaq_core::Native<jaq_json::Val>>;
+
+/// Parse and compile a jq program against jq's standard library.
+#[cfg(feature = "jq")]
+fn compile_jq(program: &str) -> Result<JqFilter, String> {
+    use jaq_core::load::{Arena, File, Loader};
+
+    let arena = Arena::default();
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let modules = loader.load(&arena, File { code: program, path: () }).map_err(|errors| {
+        let reasons: Vec<String> = errors.into_iter().flat_map(|(_, e)| describe_jq_load_error(e)).collect();
+        format!("Invalid jq program '{}': {}", program, reasons.join("; "))
+    })?;
+    jaq_core::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .map_err(|errors| {
+            let reasons: Vec<String> = errors
+                .into_iter()
+                .flat_map(|(_, undefined)| undefined)
+                .map(|(name, kind)| format!("undefined {} '{}'", kind.as_str(), name))
+                .collect();
+            format!("Invalid jq program '{}': {}", program, reasons.join("; "))
+        })
+}
+
+#[cfg(feature = "jq")]
+fn describe_jq_load_error(error: jaq_core::load::Error<&str>) -> Vec<String> {
+    use jaq_core::load::Error;
+
+    // Lexer and parser errors carry the unconsumed rest of the program
+    let at = |rest: &str| match rest.chars().take(20).collect::<String>() {
+        s if s.is_empty() => "end of program".to_string(),
+        s => format!("'{}'", s),
+    };
+    match error {
+        Error::Io(errors) => errors.into_iter().map(|(path, e)| format!("cannot load {}: {}", path, e)).collect(),
+        Error::Lex(errors) => errors
+            .into_iter()
+            .map(|(expected, rest)| format!("expected {} at {}", expected.as_str(), at(rest)))
+            .collect(),
+        Error::Parse(errors) => errors
+            .into_iter()
+            .map(|(expected, rest)| format!("expected {} at {}", expected.as_str(), at(rest)))
+            .collect(),
+    }
+}
+
+/// Check an `--output jq` program before any command is sent.
+#[cfg(feature = "jq")]
+pub fn check_jq(program: &str) -> Result<(), String> {
+    compile_jq(program).map(|_| ())
+}
+
+/// Run an `--output jq` program over response data, collecting every value
+/// it produces.
+#[cfg(feature = "jq")]
+pub fn run_jq(program: &str, data: &Value) -> Result<Vec<Value>, String> {
+    use jaq_core::{Ctx, RcIter};
+
+    let filter = compile_jq(program)?;
+    let inputs = RcIter::new(core::iter::empty());
+    filter
+        .run((Ctx::new([], &inputs), jaq_json::Val::from(data.clone())))
+        .map(|result| result.map(Value::from).map_err(|e| format!("jq error: {}", e)))
+        .collect()
+}
+
+#[cfg(not(feature = "jq"))]
+pub fn check_jq(_program: &str) -> Result<(), String> {
+    Err("jq output requires building with --features jq".to_string())
+}
+
+#[cfg(not(feature = "jq"))]
+pub fn run_jq(program: &str, _data: &Value) -> Result<Vec<Value>, String> {
+    check_jq(program).map(|_| Vec::new())
+}
+
+/// `key=value` pairs of a response's `metadata` for human output.
+pub fn format_metadata(metadata: &Value) -> Option<String> {
+    let pairs: Vec<String> = metadata
+        .as_object()?
+        .iter()
+        .map(|(key, value)| match value {
+            Value::String(s) => format!("{}={}", key, s),
+            other => format!("{}={}", key, other),
+        })
+        .collect();
+    (!pairs.is_empty()).then(|| pairs.join(" "))
+}
 
 pub fn print_response(resp: &Response, json_mode: bool) {
     if json_mode {
         println!("{}", serde_json::to_string(resp).unwrap_or_default());
         return;
     }
+    print_human_response(resp);
+    // On stderr so piped output (get text, eval) stays unchanged
+    if let Some(tags) = resp.metadata.as_ref().and_then(format_metadata) {
+        eprintln!("{}", color::dim(&format!("metadata: {}", tags)));
+    }
+}
 
+fn print_human_response(resp: &Response) {
     if !resp.success {
         eprintln!(
             "{} {}",
@@ -17,6 +954,20 @@ pub fn print_response(resp: &Response, json_mode: bool) {
     }
 
     if let Some(data) = &resp.data {
+        // CDP endpoint
+        if let Some(endpoint) = data.get("wsEndpoint").and_then(|v| v.as_str()) {
+            println!("{}", endpoint);
+            return;
+        }
+        // Navigation summary
+        if let Some(summary) = data.get("summary").filter(|v| v.is_object()) {
+            let lines = format_navigation_summary(summary);
+            println!("{} {}", color::success_indicator(), color::bold(&lines[0]));
+            for line in &lines[1..] {
+                println!("{}", color::dim(line));
+            }
+            return;
+        }
         // Navigation response
         if let Some(url) = data.get("url").and_then(|v| v.as_str()) {
             if let Some(title) = data.get("title").and_then(|v| v.as_str()) {
@@ -70,6 +1021,11 @@ pub fn print_response(resp: &Response, json_mode: bool) {
             println!("{}", checked);
             return;
         }
+        // Accessibility tree
+        if let Some(tree) = data.get("tree") {
+            println!("{}", serde_json::to_string_pretty(tree).unwrap_or_default());
+            return;
+        }
         // Eval result
         if let Some(result) = data.get("result") {
             println!(
@@ -135,7 +1091,7 @@ pub fn print_response(resp: &Response, json_mode: bool) {
         // Cleared requests
         if let Some(cleared) = data.get("cleared").and_then(|v| v.as_bool()) {
             if cleared {
-                println!("\x1b[32m✓\x1b[0m Request log cleared");
+                println!("{} Request log cleared", color::success_indicator());
                 return;
             }
         }
@@ -235,6 +1191,59 @@ pub fn print_response(resp: &Response, json_mode: bool) {
     }
 }
 
+/// Build the `launch`/`warmup` result reporting whether the daemon was already up.
+pub fn launch_ready_data(session: &str, already_running: bool) -> Value {
+    serde_json::json!({ "session": session, "ready": true, "alreadyRunning": already_running })
+}
+
+/// Report that a session has been pre-launched and is ready for commands.
+pub fn print_launch_ready(session: &str, already_running: bool, json_mode: bool) {
+    if json_mode {
+        let data = launch_ready_data(session, already_running);
+        println!("{}", serde_json::json!({ "success": true, "data": data }));
+        return;
+    }
+    let status = if already_running { "already running" } else { "started" };
+    println!(
+        "{} Session {} ready ({})",
+        color::success_indicator(),
+        color::bold(session),
+        status
+    );
+}
+
+/// Ask for confirmation before a destructive operation.
+///
+/// `--yes`/`--force` skip the prompt. When stdin is not a terminal there is
+/// nobody to ask, so this refuses rather than assuming yes.
+pub fn confirm(message: &str, assume_yes: bool) -> bool {
+    let stdin = io::stdin();
+    let is_tty = stdin.is_terminal();
+    confirm_with(message, assume_yes, is_tty, &mut stdin.lock(), &mut io::stderr())
+}
+
+fn confirm_with<R: BufRead, W: Write>(
+    message: &str,
+    assume_yes: bool,
+    is_tty: bool,
+    input: &mut R,
+    output: &mut W,
+) -> bool {
+    if assume_yes {
+        return true;
+    }
+    if !is_tty {
+        return false;
+    }
+    let _ = write!(output, "{} {} Are you sure? [y/N] ", color::warning_indicator(), message);
+    let _ = output.flush();
+    let mut answer = String::new();
+    if input.read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
 /// Print command-specific help. Returns true if help was printed, false if command unknown.
 pub fn print_command_help(command: &str) -> bool {
     let help = match command {
@@ -242,11 +1251,17 @@ pub fn print_command_help(command: &str) -> bool {
         "open" | "goto" | "navigate" => r##"
 agent-browser open - Navigate to a URL
 
-Usage: agent-browser open <url>
+Usage: agent-browser open <url> [--measure] [--summary]
 
 Navigates the browser to the specified URL. If no protocol is provided,
 https:// is automatically prepended.
 
+Options:
+  --measure            Collect page load metrics (combine with
+                       --output prometheus for exposition format)
+  --summary            Report status, redirect chain, final URL, title,
+                       and load time
+
 Aliases: goto, navigate
 
 Global Options:
@@ -254,6 +1269,13 @@ Global Options:
   --session <name>     Use specific session
   --headers <json>     Set HTTP headers (scoped to this origin)
   --headed             Show browser window
+  --set-local-storage <key=value>
+                       Seed localStorage on the target origin (repeatable)
+  --set-session-storage <key=value>
+                       Seed sessionStorage on the target origin (repeatable)
+
+Web storage is origin-scoped: seeded entries are applied by an init script
+once the page for the navigated URL's origin loads.
 
 Examples:
   agent-browser open example.com
@@ -261,6 +1283,9 @@ Examples:
   agent-browser open localhost:3000
   agent-browser open api.example.com --headers '{"Authorization": "Bearer token"}'
     # ^ Headers only sent to api.example.com, not other domains
+  agent-browser open app.example.com --set-local-storage token=abc123
+  agent-browser open example.com --measure --output prometheus
+  agent-browser open example.com --summary
 "##,
         "back" => r##"
 agent-browser back - Navigate back in history
@@ -295,17 +1320,21 @@ Examples:
         "reload" => r##"
 agent-browser reload - Reload the current page
 
-Usage: agent-browser reload
+Usage: agent-browser reload [--hard]
 
 Reloads the current page, equivalent to pressing F5 or clicking
 the browser's reload button.
 
+Options:
+  --hard               Bypass the browser cache (like Ctrl+Shift+R)
+
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
 
 Examples:
   agent-browser reload
+  agent-browser reload --hard
 "##,
 
         // === Core Actions ===
@@ -586,138 +1615,311 @@ Examples:
   agent-browser scrollintoview @e15
 "##,
 
-        // === Wait ===
-        "wait" => r##"
-agent-browser wait - Wait for condition
+        // === Wait ===
+        "wait" => r##"
+agent-browser wait - Wait for condition
+
+Usage: agent-browser wait <selector|ms|option>
+
+Waits for an element to appear, a timeout, or other conditions.
+
+Modes:
+  <selector>           Wait for element to appear
+  <ms>                 Wait for specified milliseconds
+  --url <pattern>      Wait for URL to match pattern
+  --load <state>       Wait for load state (load, domcontentloaded, networkidle)
+  --fn <expression>    Wait for JavaScript expression to be truthy
+  --text <text>        Wait for text to appear on page
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser wait "#loading-spinner"
+  agent-browser wait 2000
+  agent-browser wait --url "**/dashboard"
+  agent-browser wait --load networkidle
+  agent-browser wait --fn "window.appReady === true"
+  agent-browser wait --text "Welcome back"
+"##,
+
+        // === Screenshot/PDF ===
+        "screenshot" => r##"
+agent-browser screenshot - Take a screenshot
+
+Usage: agent-browser screenshot [path]
+
+Captures a screenshot of the current page. If no path is provided,
+outputs base64-encoded image data.
+
+Options:
+  --full, -f           Capture full page (not just viewport)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser screenshot
+  agent-browser screenshot ./screenshot.png
+  agent-browser screenshot --full ./full-page.png
+"##,
+        "pdf" => r##"
+agent-browser pdf - Save page as PDF
+
+Usage: agent-browser pdf <path>
+
+Saves the current page as a PDF file.
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser pdf ./page.pdf
+  agent-browser pdf ~/Documents/report.pdf
+"##,
+
+        // === Snapshot ===
+        "snapshot" => r##"
+agent-browser snapshot - Get accessibility tree snapshot
+
+Usage: agent-browser snapshot [options]
+
+Returns an accessibility tree representation of the page with element
+references (like @e1, @e2) that can be used in subsequent commands.
+Designed for AI agents to understand page structure.
+
+Options:
+  -i, --interactive    Only include interactive elements
+  -c, --compact        Remove empty structural elements
+  -d, --depth <n>      Limit tree depth
+  -s, --selector <sel> Scope snapshot to CSS selector
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser snapshot
+  agent-browser snapshot -i
+  agent-browser snapshot --compact --depth 5
+  agent-browser snapshot -s "#main-content"
+"##,
+
+        // === Eval ===
+        "eval" => r##"
+agent-browser eval - Execute JavaScript
+
+Usage: agent-browser eval <script>
+
+Executes JavaScript code in the browser context and returns the result.
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser eval "document.title"
+  agent-browser eval "window.location.href"
+  agent-browser eval "document.querySelectorAll('a').length"
+"##,
+
+        // === Shutdown ===
+        "shutdown" => r##"
+agent-browser shutdown - Stop the daemon gracefully
+
+Usage: agent-browser shutdown [--timeout ms]
+
+Asks the daemon to close its browser contexts, stop the stream server and
+remove its socket and pid files, then waits until it has exited. Returns
+once it is gone, so the next command starts from a clean slate.
+
+Unlike close --timeout, a daemon that doesn't exit in time is reported
+(exit 5) and left running, never killed. Running shutdown when no daemon
+is running is not an error.
+
+Options:
+  --timeout <ms>       How long to wait for the daemon to exit (default: 10000)
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser shutdown
+  agent-browser shutdown --timeout 30000
+"##,
+
+        // === Ping ===
+        "ping" => r##"
+agent-browser ping - Check that the session's daemon is responsive
+
+Usage: agent-browser ping
+
+Sends a no-op to the daemon and reports the round trip. Never starts a
+daemon or a browser: if none is running, says so and exits 3. Waits up to
+5 seconds for the reply unless --timeout is given.
+
+Global Options:
+  --json               Output as JSON ({"latencyMs": N} on success)
+  --session <name>     Use specific session
+  --timeout <s>        Wait at most s seconds for the reply
+
+Examples:
+  agent-browser ping
+  agent-browser --session work --json ping
+"##,
+
+        // === Close ===
+        "close" | "quit" | "exit" => r##"
+agent-browser close - Close the browser
+
+Usage: agent-browser close [--all] [--timeout ms]
+
+Closes the browser instance for the current session. With --all (or a
+session glob), closes every matching session after asking for confirmation.
 
-Usage: agent-browser wait <selector|ms|option>
+With --timeout, waits up to that long for the daemon to exit, then sends
+SIGTERM, then SIGKILL (TerminateProcess on Windows), and removes the
+session's files, so the session is gone even if the daemon is wedged.
+Use shutdown instead to wait for a clean exit without ever forcing one.
 
-Waits for an element to appear, a timeout, or other conditions.
+Aliases: quit, exit
 
-Modes:
-  <selector>           Wait for element to appear
-  <ms>                 Wait for specified milliseconds
-  --url <pattern>      Wait for URL to match pattern
-  --load <state>       Wait for load state (load, domcontentloaded, networkidle)
-  --fn <expression>    Wait for JavaScript expression to be truthy
-  --text <text>        Wait for text to appear on page
+Options:
+  --timeout <ms>       Escalate if the daemon hasn't exited after <ms>
 
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
+  --all                Close all active sessions
+  --yes, --force       Skip the confirmation prompt
 
 Examples:
-  agent-browser wait "#loading-spinner"
-  agent-browser wait 2000
-  agent-browser wait --url "**/dashboard"
-  agent-browser wait --load networkidle
-  agent-browser wait --fn "window.appReady === true"
-  agent-browser wait --text "Welcome back"
+  agent-browser close
+  agent-browser close --session mysession
+  agent-browser close --all --yes
+  agent-browser close --timeout 3000
 "##,
 
-        // === Screenshot/PDF ===
-        "screenshot" => r##"
-agent-browser screenshot - Take a screenshot
+        // === Launch ===
+        "launch" | "warmup" => r##"
+agent-browser launch - Start the browser without navigating
 
-Usage: agent-browser screenshot [path]
+Usage: agent-browser launch
 
-Captures a screenshot of the current page. If no path is provided,
-outputs base64-encoded image data.
+Starts the daemon and browser for the session (honoring --headed, --proxy,
+--cdp, -p, etc.) and exits, so the next command doesn't pay the startup cost.
+Reports whether the session was already running.
 
-Options:
-  --full, -f           Capture full page (not just viewport)
+Aliases: warmup
 
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
+  --headed             Show browser window
 
 Examples:
-  agent-browser screenshot
-  agent-browser screenshot ./screenshot.png
-  agent-browser screenshot --full ./full-page.png
+  agent-browser launch
+  agent-browser --session work --headed launch
 "##,
-        "pdf" => r##"
-agent-browser pdf - Save page as PDF
 
-Usage: agent-browser pdf <path>
+        // === Wait for function ===
+        "wait-function" => r##"
+agent-browser wait-function - Wait until a JavaScript predicate is truthy
 
-Saves the current page as a PDF file.
+Usage: agent-browser wait-function <js> [--timeout ms]
+
+Polls <js> in the page until it returns a truthy value. <js> may be an
+expression or a function; quote it so the shell passes it as one argument.
+
+Options:
+  --timeout <ms>       Give up after this long (default: 25000)
 
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
 
 Examples:
-  agent-browser pdf ./page.pdf
-  agent-browser pdf ~/Documents/report.pdf
+  agent-browser wait-function "() => window.appReady === true"
+  agent-browser wait-function "document.querySelectorAll('.row').length > 10" --timeout 5000
 "##,
 
-        // === Snapshot ===
-        "snapshot" => r##"
-agent-browser snapshot - Get accessibility tree snapshot
+        // === Accessibility ===
+        "a11y" | "accessibility" => r##"
+agent-browser a11y - Print the accessibility tree
 
-Usage: agent-browser snapshot [options]
+Usage: agent-browser a11y [--selector <sel>]
 
-Returns an accessibility tree representation of the page with element
-references (like @e1, @e2) that can be used in subsequent commands.
-Designed for AI agents to understand page structure.
+Prints the page's accessibility tree (roles, names, states) as Playwright's
+ARIA snapshot, optionally scoped to the subtree of the first element matching
+<sel>. Unlike snapshot, it adds no refs and filters nothing.
 
 Options:
-  -i, --interactive    Only include interactive elements
-  -c, --compact        Remove empty structural elements
-  -d, --depth <n>      Limit tree depth
-  -s, --selector <sel> Scope snapshot to CSS selector
+  -s, --selector <sel>  Only include the subtree rooted at this element
+
+Aliases: accessibility
 
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
 
 Examples:
-  agent-browser snapshot
-  agent-browser snapshot -i
-  agent-browser snapshot --compact --depth 5
-  agent-browser snapshot -s "#main-content"
+  agent-browser a11y
+  agent-browser a11y --selector "#main"
 "##,
 
-        // === Eval ===
-        "eval" => r##"
-agent-browser eval - Execute JavaScript
+        // === Batch ===
+        "batch" | "assert-visible" | "assert-text" | "assert-url" | "assert-title" | "assert-count" => r##"
+agent-browser batch - Run commands from a file
 
-Usage: agent-browser eval <script>
+Usage: agent-browser batch <file>
 
-Executes JavaScript code in the browser context and returns the result.
+Runs each line of <file> as an agent-browser command over one connection.
+Blank lines and lines starting with # are skipped; quotes group arguments.
+Exits non-zero if any command fails. With --json, the report's "errors"
+lists each failure as {"line", "error", "errorCode"}.
+
+Assertions (useful in batch files):
+  assert-visible <selector>        Element is visible
+  assert-text <selector> <text>    Element text contains <text>
+  assert-url <substring>           Current URL contains <substring>
+  assert-title <title>             Page title equals <title>
+  assert-count <selector> <n>      Exactly <n> elements match
 
 Global Options:
   --json               Output as JSON
+  --output check       Print a TAP summary (ok N / not ok N)
   --session <name>     Use specific session
 
 Examples:
-  agent-browser eval "document.title"
-  agent-browser eval "window.location.href"
-  agent-browser eval "document.querySelectorAll('a').length"
+  agent-browser batch smoke.txt
+  agent-browser batch checks.txt --output check
 "##,
 
-        // === Close ===
-        "close" | "quit" | "exit" => r##"
-agent-browser close - Close the browser
-
-Usage: agent-browser close
+        // === CDP URL ===
+        "cdp-url" => r##"
+agent-browser cdp-url - Print the browser's CDP websocket endpoint
 
-Closes the browser instance for the current session.
+Usage: agent-browser cdp-url
 
-Aliases: quit, exit
+Asks the daemon for the websocket endpoint of the browser it drives, so
+other CDP tools (Lighthouse, Puppeteer, ...) can attach to the same browser.
+Fails with "No CDP endpoint" unless the session is attached with --cdp.
 
 Global Options:
   --json               Output as JSON
   --session <name>     Use specific session
 
 Examples:
-  agent-browser close
-  agent-browser close --session mysession
+  agent-browser cdp-url
+  agent-browser --session work cdp-url
 "##,
 
         // === Get ===
-        "get" => r##"
+        "get" | "count" => r##"
 agent-browser get - Retrieve information from elements or page
 
 Usage: agent-browser get <subcommand> [args]
@@ -731,7 +1933,8 @@ Subcommands:
   attr <selector> <name>     Get attribute value
   title                      Get page title
   url                        Get current URL
-  count <selector>           Count matching elements
+  count <selector>           Count matching elements (--highlight outlines
+                             them briefly in a headed browser)
   box <selector>             Get bounding box (x, y, width, height)
   styles <selector>          Get computed styles of elements
 
@@ -747,6 +1950,7 @@ Examples:
   agent-browser get title
   agent-browser get url
   agent-browser get count "li.item"
+  agent-browser --headed count "li.item" --highlight
   agent-browser get box "#header"
   agent-browser get styles "button"
   agent-browser get styles @e1
@@ -854,7 +2058,7 @@ Configures various browser settings and emulation options.
 Settings:
   viewport <w> <h>           Set viewport size
   device <name>              Emulate device (e.g., "iPhone 12")
-  geo <lat> <lng>            Set geolocation
+  geo <lat> <lng>            Set geolocation (or a single <lat>,<lng>)
   offline [on|off]           Toggle offline mode
   headers <json>             Set extra HTTP headers
   credentials <user> <pass>  Set HTTP authentication
@@ -876,13 +2080,34 @@ Examples:
   agent-browser set media light reduced-motion
 "##,
 
+        "set-viewport" => r##"
+agent-browser set-viewport - Resize the viewport of the live page
+
+Usage: agent-browser set-viewport <WIDTHxHEIGHT>
+
+Resizes the viewport of the current page without relaunching the browser.
+
+Global Options:
+  --json               Output as JSON
+  --session <name>     Use specific session
+
+Examples:
+  agent-browser set-viewport 1280x720
+  agent-browser set-viewport 390x844
+"##,
+
         // === Network ===
         "network" => r##"
 agent-browser network - Network interception and monitoring
 
-Usage: agent-browser network <subcommand> [args]
+Usage: agent-browser network [<subcommand>] [args]
+
+Intercept, mock, or monitor network requests. Without a subcommand, streams
+network requests as a live table (method, status, duration, url).
 
-Intercept, mock, or monitor network requests.
+Tail Options:
+  --method <list>            Only show these methods (e.g., GET,POST)
+  --status <list>            Only show these statuses (e.g., 4xx,5xx,200,300-399)
 
 Subcommands:
   route <url> [options]      Intercept requests matching URL pattern
@@ -898,6 +2123,8 @@ Global Options:
   --session <name>     Use specific session
 
 Examples:
+  agent-browser network --status 4xx,5xx
+  agent-browser network --method POST
   agent-browser network route "**/api/*" --abort
   agent-browser network route "**/data.json" --body '{"mock": true}'
   agent-browser network unroute
@@ -1026,6 +2253,10 @@ Examples:
   agent-browser frame "#embed-iframe"
   agent-browser frame "iframe[name='content']"
   agent-browser frame main
+
+To scope one command instead, pass --frame <selector|url> to it; an iframe
+matching the selector wins over a frame whose URL contains the text:
+  agent-browser --frame js.stripe.com click "#pay"
 "##,
 
         // === Dialog ===
@@ -1196,7 +2427,26 @@ instance with separate cookies, storage, and state.
 
 Operations:
   (none)               Show current session name
-  list                 List all active sessions
+  list                 List all active sessions, and any stale ones (a pid
+                       file for an exited daemon, or a socket/port file with
+                       no pid file) that 'session clean' would remove
+  info [name]          Show a session's pid, socket (or port file), whether
+                       the daemon responds, and how long it has been up
+  env                  Show the launch configuration of the running daemon
+                       (headless, proxy, executable path, viewport, ...)
+                       with secrets redacted
+  kill [name...]       Terminate a session's daemon (the current one if no
+                       name is given) and remove its files, without asking
+                       it to close first. With --all, every running session,
+                       after confirmation (--yes skips it)
+  clean                Remove the files of sessions whose daemon is gone
+                       (running sessions are left alone)
+  rename <old> <new>   Move a stopped session's files to a new name
+  config [set k=v...]  Show or change the session's saved launch defaults:
+                       headed, proxy, executable-path (empty value removes).
+                       Saved in ~/.config/agent-browser/sessions/<name>.toml;
+                       command-line flags override them. Changes apply the
+                       next time the session's daemon starts
 
 Environment:
   AGENT_BROWSER_SESSION    Default session name
@@ -1208,6 +2458,13 @@ Global Options:
 Examples:
   agent-browser session
   agent-browser session list
+  agent-browser session info work
+  agent-browser --session work session env
+  agent-browser session kill work
+  agent-browser session kill --all --yes
+  agent-browser session clean
+  agent-browser session rename ci-run-17 checkout
+  agent-browser --session work session config set headed=true proxy=http://proxy:8080
   agent-browser --session test open example.com
 "##,
 
@@ -1227,6 +2484,53 @@ Examples:
   agent-browser install --with-deps
 "##,
 
+        // === Doctor ===
+        "doctor" => r##"
+agent-browser doctor - Find and repair leftover daemon state
+
+Usage: agent-browser doctor [--fix] [--dry-run]
+
+Checks the session dir for files left by crashed daemons, running daemons
+that can no longer be reached, and sockets other users can connect to.
+Exits 1 while any problem remains.
+
+Options:
+  --fix                Repair what can be repaired safely: remove stale
+                       files, stop unreachable daemons, chmod sockets to
+                       600, create a missing temp dir
+  --dry-run            With --fix, show what would be done
+  --yes                Stop unreachable daemons without asking
+
+Only processes confirmed to be a daemon are stopped, and sockets owned by
+someone else are never touched; those are reported for you to handle.
+
+Examples:
+  agent-browser doctor
+  agent-browser doctor --fix --dry-run
+  agent-browser doctor --fix
+"##,
+
+        "completions" => r##"
+agent-browser completions - Print a shell completion script
+
+Usage: agent-browser completions <bash|zsh|fish|powershell>
+
+Completes commands, their subcommands, and global flags.
+
+Examples:
+  # bash (~/.bashrc)
+  source <(agent-browser completions bash)
+
+  # zsh (~/.zshrc, after compinit)
+  source <(agent-browser completions zsh)
+
+  # fish
+  agent-browser completions fish > ~/.config/fish/completions/agent-browser.fish
+
+  # PowerShell ($PROFILE)
+  agent-browser completions powershell | Out-String | Invoke-Expression
+"##,
+
         _ => return false,
     };
     println!("{}", help.trim());
@@ -1261,13 +2565,21 @@ Core Commands:
   pdf <path>                 Save as PDF
   snapshot                   Accessibility tree with refs (for AI)
   eval <js>                  Run JavaScript
+  set-viewport <WxH>         Resize live viewport (e.g., 1280x720)
   connect <port>             Connect to browser via CDP (e.g., connect 9222)
-  close                      Close browser
+  cdp-url                    Print the browser's CDP websocket endpoint
+  a11y [--selector <sel>]    Print the accessibility tree (ARIA snapshot)
+  wait-function <js>         Wait until a JS predicate is truthy
+  batch <file>               Run commands (e.g. assert-*) from a file
+  launch                     Start the browser without navigating (pre-warm)
+  close [--timeout ms]       Close browser (with --timeout, kill it if it hangs)
+  shutdown [--timeout ms]    Close the browser and wait for the daemon to exit
+  ping                       Check the daemon is alive (never starts one)
 
 Navigation:
   back                       Go back
   forward                    Go forward
-  reload                     Reload page
+  reload [--hard]            Reload page (--hard bypasses cache)
 
 Get Info:  agent-browser get <what> [selector]
   text, html, value, attr <name>, title, url, count, box, styles
@@ -1286,7 +2598,8 @@ Browser Settings:  agent-browser set <setting> [value]
   offline [on|off], headers <json>, credentials <user> <pass>
   media [dark|light] [reduced-motion]
 
-Network:  agent-browser network <action>
+Network:  agent-browser network [action]
+  [--method <m>] [--status 4xx,5xx]   Live table of requests
   route <url> [--abort|--body <json>]
   unroute [url]
   requests [--clear] [--filter <pattern>]
@@ -1309,10 +2622,18 @@ Debug:
 Sessions:
   session                    Show current session name
   session list               List active sessions
+  session info [name]        Show a session's pid, socket and uptime
+  session env                Show the running daemon's launch configuration
+  session kill [name|--all]  Terminate a session's daemon and remove its files
+  session clean              Remove files left behind by crashed daemons
+  session rename <old> <new> Rename a stopped session
+  session config [set k=v]   Show or save the session's launch defaults
 
 Setup:
   install                    Install browser binaries
   install --with-deps        Also install system dependencies (Linux)
+  doctor [--fix]             Find (and repair) leftover daemon state
+  completions <shell>        Print a completion script (bash, zsh, fish, powershell)
 
 Snapshot Options:
   -i, --interactive          Only interactive elements
@@ -1321,22 +2642,145 @@ Snapshot Options:
   -s, --selector <sel>       Scope to CSS selector
 
 Options:
-  --session <name>           Isolated session (or AGENT_BROWSER_SESSION env)
+  --session <name>           Isolated session (or AGENT_BROWSER_SESSION env; globs fan out)
   --headers <json>           HTTP headers scoped to URL's origin (for auth)
   --executable-path <path>   Custom browser executable (or AGENT_BROWSER_EXECUTABLE_PATH)
   --extension <path>         Load browser extensions (repeatable).
-  --proxy <url>              Proxy server (http://[user:pass@]host:port)
+  --chromium-flag <flag>     Pass a switch like --disable-gpu to the browser at launch
+                             (repeatable)
+  --frame <selector|url>     Run a selector command inside the matching iframe
+                             (alias: --select-frame)
+  --proxy <url>              Proxy server (http://[user:pass@]host:port); percent-encode
+                             @ and : in credentials (p%40ss for p@ss)
+  --no-proxy <hosts>         Hosts to reach without --proxy, comma-separated
+                             (e.g. localhost,*.internal)
   --json                     JSON output
+  --output <format>          Output format: jsonl-stream, pretty-errors, prometheus,
+                             check, github, kv, table, ndjson, msgpack, jq <program> (alias:
+                             --format; github is the default under GitHub Actions;
+                             msgpack and jq need a build with --features msgpack/jq)
+  --ndjson                   Print one JSON response object per line; array results
+                             are not split up (see jsonl-stream); implies --json
+  --table                    Show lists (tabs, cookies, sessions) as an aligned
+                             table; same as --output table
+  --max-col-width <n>        Truncate table cells longer than <n> characters (default: 40)
+  --abort-on-console-error   Fail if the page logged console errors
+  --all                      Run the command in every active session
+  --max-concurrency <n>      Sessions driven in parallel with --all/globs (default: 4)
+  --yes, --force             Skip confirmation for destructive commands
+  --record-commands <path>   Append successful commands to a shell script
+  --viewport <WxH>           Launch-time viewport size (e.g., 1280x720)
+  --viewport-from-terminal   Launch-time viewport sized from the terminal
+  --no-spawn                 Fail instead of starting a daemon if none is running
+  --retry-on-navigation      Retry once if a navigation interrupts the command
+  --session-prefix <ns>      Namespace session names on disk (or AGENT_BROWSER_SESSION_PREFIX)
+  --redact <regex>           Replace matches in output with *** (repeatable)
+  --output-split-by <field>  Write array results to one file per <field> value
+  --output-dir <dir>         Directory for --output-split-by files
+  --output-file <path>       Also write each response as a JSON line to <path>
+  --output-append            Append to --output-file instead of truncating it
+  --output-on-success <path> Save the full response to <path> when it succeeds
+  --output-on-success-data   Save only the response data to --output-on-success
+  --result-field <path>      Exit 0/1 from a boolean in the response data
+  --field <path>             Print only the value at <path> in the response, e.g.
+                             data.url or data.cookies.0.value
+  --fail-on-empty            Exit 1 when a command returns no results
+  --flatten                  Print nested data as dotted keys (viewport.width,
+                             links.0.href); pairs well with --output kv
+  --stdin                    Read one JSON command per line from stdin and print
+                             one JSON response per line, over a single connection
+  --parse-only               Check the command and print the JSON it would send,
+                             without starting or contacting a daemon (exit 2 if invalid)
+  --dry-run                  Print every command a new daemon would be sent (the
+                             launch for --headed/--proxy/--cdp/-p, throttling, then
+                             the command), pretty-printed or one per line with --json
+  --metadata <key=value>     Tag the request; echoed back in the response (repeatable)
+  --emit-id                  Print the request id to stderr before sending
+  --selector-file <file>     Run the command once per selector in <file>
+                             (one per line; '# ' starts a comment)
+  --template <text>          Render the response data, e.g. '{{title}}: {{url}}'
+  --output-template-file <f> Same as --template, read from a file
+  --throttle-network <p>     Emulate slow-3g, fast-3g, offline, or down,up,latency
+                             (kbit/s, kbit/s, ms); applied at runtime
+  --throttle-cpu <factor>    Slow the CPU down by <factor> (>= 1, e.g. 4)
+  --no-sandbox               Launch Chromium without its sandbox (requires
+                             AGENT_BROWSER_ALLOW_NO_SANDBOX=1 or confirmation)
+  --keep-browser-on-crash    Keep logs and a final screenshot when the browser crashes
+  --keep-alive               Never stop this session's daemon for being idle
+                             (overrides AGENT_BROWSER_IDLE_TIMEOUT)
+  --diagnostics-dir <path>   Where crash diagnostics go (default: temp dir)
+  --timeout <s>              Wait at most s seconds for each response (default: 30,
+                             0 = no limit, or AGENT_BROWSER_TIMEOUT). Put it before
+                             close, shutdown or wait-function, which have their own
+                             --timeout. Daemon startup is a separate wait
+                             (--startup-timeout).
+  --startup-timeout <s>      Wait at most s seconds for a new daemon to start
+                             (default: 5, or AGENT_BROWSER_STARTUP_TIMEOUT)
+  --wait-before <ms>         Pause before sending the command (client-side)
+  --wait-after <ms>          Pause after the command returns (client-side)
+  --node <path>              Node.js binary that runs the daemon (default: node on
+                             PATH, or AGENT_BROWSER_NODE)
+  --daemon-log <path>        Append a newly started daemon's stderr to this file
+                             (or AGENT_BROWSER_LOG env; default: discarded)
+  --tcp                      Talk to the daemon over a localhost TCP port instead of
+                             a Unix socket / named pipe (for debugging with telnet;
+                             use it for every command of the session)
+  --abort-signal-file <path> Cancel the command once <path> exists (checked every
+                             100ms while waiting; exits with code 130)
+  --max-connect-attempts <n> Try a daemon that refuses connections n times, backing
+                             off from 100ms (default: 5, about 1.5s in total)
+  --connect-retries <n>      Same, as retries after the first try (0 = don't retry)
+  --compact-sessions         Run this session as a context in one shared browser
+  --max-sessions <n>         Refuse to start a daemon once n are running
+                             (default: 20, or AGENT_BROWSER_MAX_SESSIONS)
+  --max-idle-reconnect <ms>  Ping a connection idle this long before reusing it (default: 5000)
+  --keepalive-interval <s>   Ping the daemon connection every s seconds to keep it open
+  --set-local-storage <k=v>  Seed localStorage on the opened origin (repeatable)
+  --set-session-storage <k=v>  Seed sessionStorage on the opened origin (repeatable)
+  --ignore-console <regex>   Ignore matching console errors (repeatable)
   --full, -f                 Full page screenshot
-  --headed                   Show browser window (not headless)
+  --headed                   Show browser window (default on desktops outside CI/SSH)
+  --headless                 Hide browser window (default in CI, over SSH, or without a display)
   --cdp <port>               Connect via CDP (Chrome DevTools Protocol)
+  --connect-existing         With --cdp/-p, don't re-send the launch if the
+                             daemon is already running (avoids reconnecting)
+  --color <mode>             Colored output: auto (default; only on a terminal),
+                             always or never. --no-color is --color never
+  --config <path>            Config file to read instead of
+                             ~/.config/agent-browser/config.toml
+  --quiet                    Don't print warnings (results and errors still print)
+  --verbose                  Log the connection target, daemon start command and
+                             connect retries to stderr
   --debug                    Debug output
   --version, -V              Show version
 
 Environment:
   AGENT_BROWSER_SESSION          Session name (default: "default")
   AGENT_BROWSER_EXECUTABLE_PATH  Custom browser executable path
+  AGENT_BROWSER_RECORD_COMMANDS  Script to record successful commands into
+  AGENT_BROWSER_MAX_SESSIONS     Cap on running daemons (default: 20)
+  AGENT_BROWSER_TIMEOUT          Seconds to wait for each response (default: 30)
+  AGENT_BROWSER_STARTUP_TIMEOUT  Seconds to wait for a new daemon to start (default: 5)
+  AGENT_BROWSER_NODE             Path to the Node.js binary that runs the daemon
+  AGENT_BROWSER_RUNTIME          Runtime for the daemon: node, bun or deno (default: node)
+  AGENT_BROWSER_NODE_ARGS        Options for the daemon's runtime (e.g. --inspect)
+  AGENT_BROWSER_LOG              File to append a newly started daemon's stderr to
+  AGENT_BROWSER_IDLE_TIMEOUT     Seconds without commands before a daemon exits (0: never)
   AGENT_BROWSER_STREAM_PORT      Enable WebSocket streaming on port (e.g., 9223)
+  AGENT_BROWSER_CONFIG           Config file to read (like --config)
+  HTTPS_PROXY, HTTP_PROXY        Proxy to launch with when --proxy isn't set
+  NO_PROXY                       Hosts that bypass a proxy taken from the environment
+  NO_COLOR                       Disable colored output (unless --color always)
+
+Exit codes:
+  0    Success
+  1    Other failures (--result-field false, --field not found, --fail-on-empty,
+       local commands)
+  2    Invalid command or flag
+  3    Daemon could not be started or reached, or the connection broke
+  4    The command failed (the daemon answered success: false)
+  5    Timed out waiting for the daemon
+  130  Cancelled by --abort-signal-file
 
 Examples:
   agent-browser open example.com
@@ -1354,3 +2798,759 @@ Examples:
 pub fn print_version() {
     println!("agent-browser {}", env!("CARGO_PKG_VERSION"));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Records the buffer length at every flush so tests can check incremental writes.
+    #[derive(Default)]
+    struct FlushRecorder {
+        buf: Vec<u8>,
+        flushed_at: Vec<usize>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed_at.push(self.buf.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_confirm_yes_flag_bypasses_prompt() {
+        let mut out = Vec::new();
+        assert!(confirm_with("Close all?", true, false, &mut io::empty(), &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_refuses_without_tty() {
+        let mut input = "y\n".as_bytes();
+        let mut out = Vec::new();
+        assert!(!confirm_with("Close all?", false, false, &mut input, &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_tty_answers() {
+        let mut out = Vec::new();
+        assert!(confirm_with("x", false, true, &mut "y\n".as_bytes(), &mut out));
+        assert!(confirm_with("x", false, true, &mut "YES\n".as_bytes(), &mut out));
+        assert!(!confirm_with("x", false, true, &mut "\n".as_bytes(), &mut out));
+        assert!(!confirm_with("x", false, true, &mut "nope\n".as_bytes(), &mut out));
+        assert!(String::from_utf8(out).unwrap().contains("[y/N]"));
+    }
+
+    #[test]
+    fn test_launch_ready_data() {
+        let data = launch_ready_data("work", true);
+        assert_eq!(data["session"], "work");
+        assert_eq!(data["ready"], true);
+        assert_eq!(data["alreadyRunning"], true);
+        assert_eq!(launch_ready_data("work", false)["alreadyRunning"], false);
+    }
+
+    #[test]
+    fn test_error_hint_known_codes() {
+        assert!(error_hint("element_not_found").unwrap().contains("get count"));
+        assert!(error_hint("timeout").unwrap().contains("wait"));
+        assert!(error_hint("execution_context_destroyed").is_some());
+    }
+
+    #[test]
+    fn test_error_hint_unknown_code() {
+        assert_eq!(error_hint("something_else"), None);
+        assert_eq!(error_hint(""), None);
+    }
+
+    #[test]
+    fn test_infer_error_code() {
+        assert_eq!(infer_error_code("Timeout 30000ms exceeded"), Some("timeout"));
+        assert_eq!(
+            infer_error_code("Execution context was destroyed, most likely because of a navigation"),
+            Some("execution_context_destroyed")
+        );
+        assert_eq!(infer_error_code("locator resolved to 0 elements"), Some("element_not_found"));
+        assert_eq!(infer_error_code("something odd"), None);
+    }
+
+    #[test]
+    fn test_stream_writer_error_lines_are_valid_ndjson() {
+        let mut writer = StreamWriter::new(Vec::new());
+        writer.write_response(&Response { success: true, data: Some(json!({ "n": 0 })), ..Default::default() }).unwrap();
+        writer
+            .write_response(&Response { success: false, error: Some("page crashed".to_string()), ..Default::default() })
+            .unwrap();
+        writer.write_item(&json!({ "n": 2 })).unwrap();
+        assert_eq!(writer.index, 3);
+
+        let text = String::from_utf8(writer.out).unwrap();
+        let lines: Vec<Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0], json!({ "n": 0 }));
+        assert_eq!(lines[1], json!({ "success": false, "error": "page crashed", "index": 1 }));
+        assert_eq!(lines[2], json!({ "n": 2 }));
+    }
+
+    #[test]
+    fn test_stream_writer_finish() {
+        // Unstreamed arrays are split into lines
+        let mut writer = StreamWriter::new(Vec::new());
+        writer.finish(&Response { success: true, data: Some(json!([1, 2])), ..Default::default() }).unwrap();
+        assert_eq!(String::from_utf8(writer.out).unwrap(), "1\n2\n");
+
+        // A final failure after streamed items is reported in-band with its index
+        let mut writer = StreamWriter::new(Vec::new());
+        writer.write_item(&json!("a")).unwrap();
+        writer.finish(&Response { success: false, error: Some("boom".to_string()), ..Default::default() }).unwrap();
+        let text = String::from_utf8(writer.out).unwrap();
+        assert_eq!(text, "\"a\"\n{\"error\":\"boom\",\"index\":1,\"success\":false}\n");
+    }
+
+    #[test]
+    fn test_ndjson_writer_lines_are_whole_responses() {
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write_partial(&Response { success: true, data: Some(json!({ "text": "hi" })), ..Default::default() }).unwrap();
+        writer.write_partial(&Response { success: true, data: Some(json!("plain")), ..Default::default() }).unwrap();
+        writer.finish(&Response { success: true, data: Some(json!([{ "text": "hi" }])), ..Default::default() }).unwrap();
+
+        let text = String::from_utf8(writer.out).unwrap();
+        let lines: Vec<Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(Value::is_object));
+        assert_eq!(lines[0], json!({ "success": true, "data": { "text": "hi" }, "error": null }));
+        assert_eq!(lines[1]["data"], "plain");
+        // Streamed results aren't repeated in the final line
+        assert_eq!(lines[2], json!({ "success": true, "error": null }));
+    }
+
+    #[test]
+    fn test_ndjson_writer_without_partials_matches_json() {
+        let resp = Response { success: true, data: Some(json!({ "url": "https://example.com" })), ..Default::default() };
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.finish(&resp).unwrap();
+        assert_eq!(String::from_utf8(writer.out).unwrap(), format!("{}\n", serde_json::to_string(&resp).unwrap()));
+
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write_error("Connection reset", "read_failed").unwrap();
+        let line: Value = serde_json::from_slice(&writer.out).unwrap();
+        assert_eq!(line, json!({ "success": false, "error": "Connection reset", "type": "read_failed" }));
+    }
+
+    #[test]
+    fn test_result_stream_drives_both_writers() {
+        fn drive<S: ResultStream>(writer: &mut S) {
+            writer.partial(&Response { success: true, data: Some(json!(1)), ..Default::default() }).unwrap();
+            writer.failed("Connection reset", "read_failed").unwrap();
+        }
+
+        let mut stream = StreamWriter::new(Vec::new());
+        drive(&mut stream);
+        let text = String::from_utf8(stream.out).unwrap();
+        assert_eq!(text, "1\n{\"error\":\"Connection reset\",\"index\":1,\"success\":false}\n");
+
+        let mut ndjson = NdjsonWriter::new(Vec::new());
+        drive(&mut ndjson);
+        let lines: Vec<Value> =
+            String::from_utf8(ndjson.out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["data"], 1);
+        assert_eq!(lines[1]["type"], "read_failed");
+    }
+
+    #[test]
+    fn test_navigation_summary_with_redirects() {
+        let summary = json!({
+            "status": 200,
+            "url": "https://www.example.com/",
+            "redirects": ["http://example.com/", "https://example.com/"],
+            "title": "Example Domain",
+            "loadTime": 412.6
+        });
+        assert_eq!(
+            format_navigation_summary(&summary),
+            vec![
+                "Example Domain",
+                "  200 https://www.example.com/ in 413ms",
+                "  redirected: http://example.com/ -> https://example.com/ -> https://www.example.com/",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_navigation_summary_minimal() {
+        let summary = json!({ "url": "about:blank", "title": "", "redirects": [] });
+        assert_eq!(format_navigation_summary(&summary), vec!["about:blank", "  about:blank"]);
+    }
+
+    #[test]
+    fn test_require_cdp_endpoint() {
+        let mut ok = Response {
+            success: true,
+            data: Some(json!({ "wsEndpoint": "ws://127.0.0.1:9222/devtools/browser/abc" })),
+            ..Default::default()
+        };
+        require_cdp_endpoint(&mut ok);
+        assert!(ok.success);
+
+        let mut missing = Response {
+            success: true,
+            data: Some(json!({ "wsEndpoint": null })),
+            ..Default::default()
+        };
+        require_cdp_endpoint(&mut missing);
+        assert!(!missing.success);
+        assert_eq!(missing.error_code.as_deref(), Some("no_cdp_endpoint"));
+        assert!(missing.error.unwrap().contains("No CDP endpoint"));
+    }
+
+    fn template_data() -> Value {
+        json!({
+            "url": "https://example.com/",
+            "title": "Example",
+            "metrics": { "load": 412 },
+            "links": [{ "href": "/a" }, { "href": "/b" }]
+        })
+    }
+
+    #[test]
+    fn test_render_template() {
+        let data = template_data();
+        assert_eq!(
+            render_template("{title} <{url}> load={metrics.load}ms first={links.0.href}", &data),
+            "Example <https://example.com/> load=412ms first=/a"
+        );
+        assert_eq!(render_template("{metrics}", &data), r#"{"load":412}"#);
+        assert_eq!(render_template("{{literal}} {title}", &data), "{literal} Example");
+    }
+
+    #[test]
+    fn test_render_template_missing_field() {
+        let data = template_data();
+        assert_eq!(render_template("[{nope}] [{metrics.ttfb}] [{links.9.href}]", &data), "[] [] []");
+        assert_eq!(render_template("unclosed {title", &data), "unclosed {title");
+    }
+
+    fn sample_requests() -> Value {
+        json!({ "requests": [
+            { "url": "/a", "status": 200, "type": "xhr" },
+            { "url": "/b", "status": 404, "type": "document" },
+            { "url": "/c", "status": 200, "type": "xhr" },
+            { "url": "/d", "type": "image/png" }
+        ] })
+    }
+
+    #[test]
+    fn test_partition_by() {
+        let data = sample_requests();
+        let groups = partition_by(data["requests"].as_array().unwrap(), "status");
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["200", "404", "_missing"]);
+        assert_eq!(groups["200"].len(), 2);
+        assert_eq!(groups["200"][1]["url"], "/c");
+        assert_eq!(groups["_missing"][0]["url"], "/d");
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("xhr"), "xhr");
+        assert_eq!(sanitize_filename("image/png"), "image_png");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(sanitize_filename("..."), "_");
+        assert_eq!(sanitize_filename(""), "_");
+        assert_eq!(sanitize_filename("a b:c?"), "a_b_c_");
+        assert_eq!(sanitize_filename(&"x".repeat(300)).len(), 100);
+    }
+
+    #[test]
+    fn test_write_split_output() {
+        let dir = std::env::temp_dir().join(format!("agent-browser-split-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut written = write_split_output(&sample_requests(), "type", &dir).unwrap();
+        written.sort();
+        let names: Vec<String> = written
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["document.json", "image_png.json", "xhr.json"]);
+        let xhr: Value = serde_json::from_str(&std::fs::read_to_string(dir.join("xhr.json")).unwrap()).unwrap();
+        assert_eq!(xhr.as_array().unwrap().len(), 2);
+
+        // Distinct values that sanitize to the same name don't overwrite each other
+        let clash = json!([{ "k": "a/b" }, { "k": "a:b" }]);
+        let written = write_split_output(&clash, "k", &dir).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_ne!(written[0], written[1]);
+
+        assert!(write_split_output(&json!({ "title": "x" }), "k", &dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_redact_response_data_and_error() {
+        let patterns = vec![Regex::new(r"sk-[a-z0-9]+").unwrap(), Regex::new(r"\d{4}-\d{4}").unwrap()];
+        let mut resp = Response {
+            success: false,
+            data: Some(json!({
+                "text": "key sk-abc123 and card 1234-5678",
+                "items": [{ "value": "sk-zzz" }, { "value": "plain" }],
+                "count": 2
+            })),
+            error: Some("Invalid token sk-abc123".to_string()),
+            ..Default::default()
+        };
+        redact_response(&mut resp, &patterns);
+        let data = resp.data.unwrap();
+        assert_eq!(data["text"], "key *** and card ***");
+        assert_eq!(data["items"][0]["value"], "***");
+        assert_eq!(data["items"][1]["value"], "plain");
+        assert_eq!(data["count"], 2);
+        assert_eq!(resp.error.as_deref(), Some("Invalid token ***"));
+    }
+
+    #[test]
+    fn test_redact_response_without_matches() {
+        let patterns = vec![Regex::new("secret").unwrap()];
+        let mut resp = Response {
+            success: true,
+            data: Some(json!({ "title": "Example Domain", "url": "https://example.com/" })),
+            ..Default::default()
+        };
+        redact_response(&mut resp, &patterns);
+        assert_eq!(resp.data.unwrap(), json!({ "title": "Example Domain", "url": "https://example.com/" }));
+    }
+
+    fn output_file_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("agent-browser-{}-{}.ndjson", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn ok_response(n: u64) -> Response {
+        Response {
+            success: true,
+            data: Some(json!({ "n": n })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_output_file_append_accumulates() {
+        let path = output_file_path("output-append");
+        let _ = std::fs::remove_file(&path);
+        write_output_file(&path, true, &ok_response(1)).unwrap();
+        write_output_file(&path, true, &ok_response(2)).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.lines().next().unwrap().contains(r#""n":1"#));
+        assert!(content.lines().nth(1).unwrap().contains(r#""n":2"#));
+    }
+
+    #[test]
+    fn test_output_file_truncates_by_default() {
+        let path = output_file_path("output-truncate");
+        write_output_file(&path, false, &ok_response(1)).unwrap();
+        write_output_file(&path, false, &ok_response(2)).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains(r#""n":2"#));
+    }
+
+    #[test]
+    fn test_write_success_output() {
+        let path = output_file_path("output-on-success");
+        write_success_output(&path, false, &ok_response(7)).unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, json!({ "success": true, "data": { "n": 7 }, "error": null }));
+
+        write_success_output(&path, true, &ok_response(8)).unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved, json!({ "n": 8 }));
+    }
+
+    #[test]
+    fn test_flatten_nested_objects() {
+        let data = json!({ "url": "https://x.test", "viewport": { "width": 1280, "height": 720, "dpr": { "x": 2 } } });
+        assert_eq!(
+            flatten(&data),
+            json!({ "url": "https://x.test", "viewport.width": 1280, "viewport.height": 720, "viewport.dpr.x": 2 })
+        );
+    }
+
+    #[test]
+    fn test_flatten_arrays_and_mixed() {
+        let data = json!({
+            "links": [{ "href": "/a", "tags": ["nav", "top"] }, { "href": "/b", "tags": [] }],
+            "meta": {},
+            "count": 2
+        });
+        assert_eq!(
+            flatten(&data),
+            json!({
+                "links.0.href": "/a",
+                "links.0.tags.0": "nav",
+                "links.0.tags.1": "top",
+                "links.1.href": "/b",
+                "links.1.tags": [],
+                "meta": {},
+                "count": 2
+            })
+        );
+        assert_eq!(flatten(&json!([{ "a": 1 }, 2])), json!({ "0.a": 1, "1": 2 }));
+        assert_eq!(flatten(&json!("plain")), json!("plain"));
+        assert_eq!(flatten(&json!([])), json!([]));
+    }
+
+    #[test]
+    fn test_flattened_kv_has_no_skipped_fields() {
+        let (lines, skipped) = format_kv(&flatten(&json!({ "viewport": { "width": 1280 } })));
+        assert_eq!(lines, vec!["viewport.width: 1280"]);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn test_is_empty_result() {
+        assert!(is_empty_result(Some(&json!([]))));
+        assert!(is_empty_result(Some(&json!({ "count": 0 }))));
+        assert!(is_empty_result(Some(&json!({ "requests": [], "total": 0 }))));
+        assert!(is_empty_result(Some(&json!({ "cookies": [], "origins": [] }))));
+
+        assert!(!is_empty_result(Some(&json!([1]))));
+        assert!(!is_empty_result(Some(&json!({ "count": 2 }))));
+        assert!(!is_empty_result(Some(&json!({ "cookies": [], "origins": [{}] }))));
+        // Nothing array-like to judge: not treated as empty
+        assert!(!is_empty_result(Some(&json!({ "title": "" }))));
+        assert!(!is_empty_result(Some(&Value::Null)));
+        assert!(!is_empty_result(None));
+    }
+
+    #[test]
+    fn test_result_field() {
+        let data = json!({ "visible": true, "checks": { "passed": false, "count": 3 }, "items": [{ "ok": true }] });
+        assert_eq!(result_field(Some(&data), "visible"), Ok(true));
+        assert_eq!(result_field(Some(&data), "checks.passed"), Ok(false));
+        assert_eq!(result_field(Some(&data), "items.0.ok"), Ok(true));
+    }
+
+    #[test]
+    fn test_extract_field() {
+        let resp = Response {
+            success: true,
+            data: Some(json!({ "url": "https://example.com/", "cookies": [{ "value": "abc", "secure": true }], "title": null })),
+            ..Default::default()
+        };
+        assert_eq!(extract_field(&resp, "data.url"), Ok("https://example.com/".to_string()));
+        assert_eq!(extract_field(&resp, "data.cookies.0.value"), Ok("abc".to_string()));
+        assert_eq!(extract_field(&resp, "data.cookies.0.secure"), Ok("true".to_string()));
+        assert_eq!(extract_field(&resp, "data.title"), Ok(String::new()));
+        assert_eq!(extract_field(&resp, "success"), Ok("true".to_string()));
+        assert_eq!(
+            extract_field(&resp, "data.cookies.1.value").unwrap_err(),
+            "--field data.cookies.1.value not found in the response"
+        );
+    }
+
+    #[test]
+    fn test_result_field_missing_or_not_boolean() {
+        let data = json!({ "checks": { "count": 3 } });
+        assert_eq!(
+            result_field(Some(&data), "checks.passed").unwrap_err(),
+            "--result-field checks.passed not found in response data"
+        );
+        assert_eq!(
+            result_field(Some(&data), "checks.count").unwrap_err(),
+            "--result-field checks.count is not a boolean (got 3)"
+        );
+        assert!(result_field(None, "visible").is_err());
+    }
+
+    #[test]
+    fn test_template_file() {
+        let path = std::env::temp_dir().join(format!("agent-browser-template-{}.tmpl", std::process::id()));
+        std::fs::write(&path, "# {title}\n\nURL: {url}\nLoad: {metrics.load}ms\nTTFB: {metrics.ttfb}\n").unwrap();
+        let template = load_template_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            render_template(&template, &template_data()),
+            "# Example\n\nURL: https://example.com/\nLoad: 412ms\nTTFB: \n"
+        );
+
+        std::fs::write(&path, [0xff, 0xfe, b'{']).unwrap();
+        let err = load_template_file(path.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("not valid UTF-8"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(load_template_file("/nonexistent/report.tmpl").unwrap_err().starts_with("Could not read template"));
+    }
+
+    #[test]
+    fn test_format_table_aligns_columns() {
+        let items = vec![
+            json!({ "name": "sid", "value": "abc", "secure": true }),
+            json!({ "name": "theme", "value": null, "path": "/" }),
+        ];
+        assert_eq!(
+            format_table(&items, DEFAULT_MAX_COL_WIDTH),
+            vec![
+                "name   path  secure  value",
+                "-----  ----  ------  -----",
+                "sid          true    abc",
+                "theme  /",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_table_truncates_long_cells() {
+        let items = vec![json!({ "url": "https://example.com/a/very/long/path", "title": "line one\nline two" })];
+        let lines = format_table(&items, 10);
+        assert_eq!(lines[0], "title       url");
+        assert_eq!(lines[1], "----------  ----------");
+        assert_eq!(lines[2], "line one …  https://e…");
+    }
+
+    #[test]
+    fn test_table_rows() {
+        let tabs = json!({ "tabs": [{ "index": 0 }, { "index": 1 }] });
+        assert_eq!(table_rows(&tabs).map(Vec::len), Some(2));
+        assert_eq!(table_rows(&json!([{ "a": 1 }])).map(Vec::len), Some(1));
+        assert!(table_rows(&json!([])).is_none());
+        assert!(table_rows(&json!(["a", "b"])).is_none());
+        assert!(table_rows(&json!({ "title": "Example" })).is_none());
+    }
+
+    #[test]
+    fn test_format_kv_scalars() {
+        let data = json!({ "url": "https://example.com/", "count": 3, "visible": true, "title": null });
+        let (lines, skipped) = format_kv(&data);
+        assert_eq!(lines, vec!["count: 3", "title: ", "url: https://example.com/", "visible: true"]);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn test_format_kv_skips_nested() {
+        let data = json!({ "title": "Example", "tabs": [{ "url": "a" }], "box": { "x": 1 } });
+        let (lines, skipped) = format_kv(&data);
+        assert_eq!(lines, vec!["title: Example"]);
+        assert_eq!(skipped, vec!["box", "tabs"]);
+
+        assert_eq!(format_kv(&json!("plain")), (vec!["value: plain".to_string()], vec![]));
+        assert_eq!(format_kv(&json!([1, 2])), (vec![], vec!["value".to_string()]));
+    }
+
+    #[test]
+    fn test_github_annotation() {
+        assert_eq!(github_annotation("error", "Element not found"), "::error::Element not found");
+        assert_eq!(
+            github_annotation("warning", "console: 100% broken\nat line 2\r"),
+            "::warning::console: 100%25 broken%0Aat line 2%0D"
+        );
+    }
+
+    #[test]
+    fn test_github_default_format() {
+        assert_eq!(OutputFormat::default_name(Some("true")), Some("github"));
+        assert_eq!(OutputFormat::default_name(Some("false")), None);
+        assert_eq!(OutputFormat::default_name(None), None);
+        assert_eq!(OutputFormat::parse("github"), Some(OutputFormat::Github));
+    }
+
+    #[test]
+    fn test_format_tap_mixed_results() {
+        let results = vec![
+            ("assert-url example.com".to_string(), Ok(())),
+            (
+                "assert-text h1 Welcome".to_string(),
+                Err(r#"expected text to contain "Welcome", got "Sign in""#.to_string()),
+            ),
+            ("assert-count li 3".to_string(), Ok(())),
+        ];
+        assert_eq!(
+            format_tap(&results),
+            "TAP version 13\n\
+             1..3\n\
+             ok 1 - assert-url example.com\n\
+             not ok 2 - assert-text h1 Welcome\n  \
+             ---\n  \
+             message: \"expected text to contain \\\"Welcome\\\", got \\\"Sign in\\\"\"\n  \
+             ...\n\
+             ok 3 - assert-count li 3\n"
+        );
+    }
+
+    #[test]
+    fn test_format_tap_empty() {
+        assert_eq!(format_tap(&[]), "TAP version 13\n1..0\n");
+    }
+
+    #[test]
+    fn test_format_prometheus() {
+        let metrics = json!({ "domContentLoaded": 120.5, "load": 480, "first-paint": 90, "title": "skip me" });
+        let text = format_prometheus(&metrics, Some("https://example.com/"));
+        assert_eq!(
+            text,
+            "# TYPE agent_browser_dom_content_loaded gauge\n\
+             agent_browser_dom_content_loaded{url=\"https://example.com/\"} 120.5\n\
+             # TYPE agent_browser_first_paint gauge\n\
+             agent_browser_first_paint{url=\"https://example.com/\"} 90\n\
+             # TYPE agent_browser_load gauge\n\
+             agent_browser_load{url=\"https://example.com/\"} 480\n"
+        );
+    }
+
+    #[test]
+    fn test_format_prometheus_label_escaping() {
+        let text = format_prometheus(&json!({ "ttfb": 5 }), Some("https://x.test/?q=\"a\\b\"\n"));
+        assert!(text.contains(r#"agent_browser_ttfb{url="https://x.test/?q=\"a\\b\"\n"} 5"#));
+    }
+
+    #[test]
+    fn test_format_prometheus_nested_without_url() {
+        let text = format_prometheus(&json!({ "timing": { "responseEnd": 42 } }), None);
+        assert_eq!(text, "# TYPE agent_browser_timing_response_end gauge\nagent_browser_timing_response_end 42\n");
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_frame_round_trip() {
+        let resp = Response {
+            success: true,
+            data: Some(json!({ "title": "Example", "links": [{ "href": "/a" }], "count": 2, "ratio": 0.5 })),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_msgpack_frame(&mut out, &resp).unwrap();
+        write_msgpack_frame(&mut out, &Response::default()).unwrap();
+
+        let (len, rest) = out.split_at(4);
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let decoded: Response = rmp_serde::from_slice(&rest[..len]).unwrap();
+        assert!(decoded.success);
+        assert_eq!(decoded.data, resp.data);
+
+        // a second frame follows the first
+        let second = &rest[len..];
+        let len = u32::from_be_bytes(second[..4].try_into().unwrap()) as usize;
+        let decoded: Response = rmp_serde::from_slice(&second[4..4 + len]).unwrap();
+        assert!(!decoded.success);
+        assert_eq!(second.len(), 4 + len);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_keeps_error_fields() {
+        let resp = Response {
+            success: false,
+            data: None,
+            error: Some("Element not found".to_string()),
+            error_code: Some("element_not_found".to_string()),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_msgpack_frame(&mut out, &resp).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&out[4..]).unwrap();
+        assert_eq!(decoded["error"], "Element not found");
+        assert_eq!(decoded["errorCode"], "element_not_found");
+    }
+
+    #[cfg(not(feature = "msgpack"))]
+    #[test]
+    fn test_msgpack_needs_feature() {
+        assert_eq!(OutputFormat::parse("msgpack"), None);
+        let err = write_msgpack_frame(&mut Vec::new(), &Response::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[cfg(feature = "jq")]
+    #[test]
+    fn test_run_jq_expressions() {
+        let data = json!({
+            "title": "Example",
+            "links": [{ "href": "/a", "text": "A" }, { "href": "/b", "text": "B" }]
+        });
+        assert_eq!(run_jq(".title", &data).unwrap(), vec![json!("Example")]);
+        assert_eq!(run_jq(".links | length", &data).unwrap(), vec![json!(2)]);
+        assert_eq!(run_jq(".links[].href", &data).unwrap(), vec![json!("/a"), json!("/b")]);
+        assert_eq!(
+            run_jq("[.links[] | select(.text == \"B\") | .href]", &data).unwrap(),
+            vec![json!(["/b"])]
+        );
+        assert_eq!(
+            run_jq("{title, count: (.links | length)}", &data).unwrap(),
+            vec![json!({ "title": "Example", "count": 2 })]
+        );
+        assert_eq!(run_jq("empty", &data).unwrap(), Vec::<Value>::new());
+    }
+
+    #[cfg(feature = "jq")]
+    #[test]
+    fn test_jq_reports_invalid_programs() {
+        let err = check_jq(".links |").unwrap_err();
+        assert!(err.starts_with("Invalid jq program '.links |'"), "{}", err);
+        assert!(err.contains("end of program"), "{}", err);
+        let err = check_jq("nosuchfn(1)").unwrap_err();
+        assert!(err.contains("undefined filter 'nosuchfn'"), "{}", err);
+        assert!(check_jq(".a | map(. + 1)").is_ok());
+        // Runtime errors come from evaluating the data
+        let err = run_jq(".[0]", &json!({ "a": 1 })).unwrap_err();
+        assert!(err.starts_with("jq error:"), "{}", err);
+    }
+
+    #[cfg(not(feature = "jq"))]
+    #[test]
+    fn test_jq_needs_feature() {
+        assert_eq!(OutputFormat::parse("jq"), None);
+        assert!(check_jq(".").unwrap_err().contains("--features jq"));
+    }
+
+    #[test]
+    fn test_format_metadata() {
+        assert_eq!(
+            format_metadata(&json!({ "task": "signup", "step": "2" })),
+            Some("step=2 task=signup".to_string())
+        );
+        assert_eq!(format_metadata(&json!({ "n": 1 })), Some("n=1".to_string()));
+        assert_eq!(format_metadata(&json!({})), None);
+        assert_eq!(format_metadata(&json!("tag")), None);
+    }
+
+    #[test]
+    fn test_metadata_round_trips_through_response() {
+        let wire = r#"{"success":true,"data":{"url":"/"},"metadata":{"task":"signup"}}"#;
+        let resp: Response = serde_json::from_str(wire).unwrap();
+        assert_eq!(resp.metadata, Some(json!({ "task": "signup" })));
+        let back: Value = serde_json::to_value(&resp).unwrap();
+        assert_eq!(back["metadata"], json!({ "task": "signup" }));
+
+        let plain: Value = serde_json::to_value(Response { success: true, ..Default::default() }).unwrap();
+        assert!(plain.get("metadata").is_none());
+    }
+
+    #[test]
+    fn test_output_format_parse() {
+        assert_eq!(OutputFormat::parse("jsonl-stream"), Some(OutputFormat::JsonlStream));
+        assert_eq!(OutputFormat::parse("pretty-errors"), Some(OutputFormat::PrettyErrors));
+        assert_eq!(OutputFormat::parse("nope"), None);
+    }
+
+    #[test]
+    fn test_write_stream_item_flushes_each_line() {
+        let mut out = FlushRecorder::default();
+        for i in 0..3 {
+            write_stream_item(&mut out, &json!({ "n": i })).unwrap();
+            // Every element is flushed right after its newline is written
+            assert_eq!(out.flushed_at.len(), i + 1);
+            assert_eq!(*out.flushed_at.last().unwrap(), out.buf.len());
+            assert_eq!(out.buf.last(), Some(&b'\n'));
+        }
+        let text = String::from_utf8(out.buf).unwrap();
+        assert_eq!(text, "{\"n\":0}\n{\"n\":1}\n{\"n\":2}\n");
+    }
+}
@@ -0,0 +1,127 @@
+//! Shared parsers for `WIDTHxHEIGHT`, `X,Y` and `START-END` arguments.
+
+use std::fmt;
+
+/// A value that didn't have the expected shape, e.g.
+/// "expected WIDTHxHEIGHT, got '1280'".
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueError {
+    pub expected: &'static str,
+    pub got: String,
+}
+
+impl ValueError {
+    fn new(expected: &'static str, got: &str) -> Self {
+        ValueError {
+            expected,
+            got: got.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got '{}'", self.expected, self.got)
+    }
+}
+
+/// Parse `WIDTHxHEIGHT` (e.g. `1280x720`, `X` also accepted) into positive integers.
+pub fn dimensions(s: &str) -> Result<(u32, u32), ValueError> {
+    const EXPECTED: &str = "WIDTHxHEIGHT";
+    let (w, h) = s.split_once(['x', 'X']).ok_or_else(|| ValueError::new(EXPECTED, s))?;
+    let positive = |part: &str| part.trim().parse::<u32>().ok().filter(|&n| n > 0);
+    match (positive(w), positive(h)) {
+        (Some(w), Some(h)) => Ok((w, h)),
+        _ => Err(ValueError::new("WIDTHxHEIGHT with positive integers", s)),
+    }
+}
+
+/// Parse an `X,Y` pair of finite numbers (e.g. `37.77,-122.41`).
+pub fn coordinates(s: &str) -> Result<(f64, f64), ValueError> {
+    let err = || ValueError::new("X,Y", s);
+    let (x, y) = s.split_once(',').ok_or_else(err)?;
+    let number = |part: &str| part.trim().parse::<f64>().ok().filter(|n| n.is_finite());
+    Ok((number(x).ok_or_else(err)?, number(y).ok_or_else(err)?))
+}
+
+/// Parse an inclusive `START-END` range of non-negative integers (e.g. `200-299`).
+pub fn range(s: &str) -> Result<(u64, u64), ValueError> {
+    let (start, end) = s.split_once('-').ok_or_else(|| ValueError::new("START-END", s))?;
+    match (start.trim().parse::<u64>(), end.trim().parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => Ok((start, end)),
+        (Ok(_), Ok(_)) => Err(ValueError::new("START-END with START <= END", s)),
+        _ => Err(ValueError::new("START-END", s)),
+    }
+}
+
+/// Parse a finite number that is zero or greater.
+pub fn non_negative(s: &str) -> Result<f64, ValueError> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .ok_or_else(|| ValueError::new("a non-negative number", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimensions_valid() {
+        assert_eq!(dimensions("1280x720"), Ok((1280, 720)));
+        assert_eq!(dimensions("800X600"), Ok((800, 600)));
+        assert_eq!(dimensions(" 390 x 844 "), Ok((390, 844)));
+    }
+
+    #[test]
+    fn test_dimensions_invalid() {
+        assert_eq!(dimensions("1280").unwrap_err().to_string(), "expected WIDTHxHEIGHT, got '1280'");
+        assert_eq!(dimensions("").unwrap_err().expected, "WIDTHxHEIGHT");
+        for bad in ["0x720", "1280x0", "-1x720", "axb", "1280x", "x720", "1.5x2"] {
+            let err = dimensions(bad).unwrap_err();
+            assert_eq!(err.expected, "WIDTHxHEIGHT with positive integers", "{}", bad);
+            assert_eq!(err.got, bad);
+        }
+    }
+
+    #[test]
+    fn test_coordinates_valid() {
+        assert_eq!(coordinates("10,20"), Ok((10.0, 20.0)));
+        assert_eq!(coordinates("37.7749, -122.4194"), Ok((37.7749, -122.4194)));
+        assert_eq!(coordinates("-5,0"), Ok((-5.0, 0.0)));
+    }
+
+    #[test]
+    fn test_coordinates_invalid() {
+        assert_eq!(coordinates("10").unwrap_err().to_string(), "expected X,Y, got '10'");
+        for bad in ["10,", ",20", "a,b", "1,2,3", "NaN,1", "inf,0", "10x20"] {
+            assert_eq!(coordinates(bad).unwrap_err(), ValueError::new("X,Y", bad), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_range_valid() {
+        assert_eq!(range("200-299"), Ok((200, 299)));
+        assert_eq!(range("5-5"), Ok((5, 5)));
+        assert_eq!(range(" 0 - 10 "), Ok((0, 10)));
+    }
+
+    #[test]
+    fn test_range_invalid() {
+        assert_eq!(range("200").unwrap_err().to_string(), "expected START-END, got '200'");
+        assert_eq!(range("299-200").unwrap_err().expected, "START-END with START <= END");
+        for bad in ["-5", "5-", "a-b", "-1-5", "1-2-3", "1.5-2"] {
+            assert_eq!(range(bad).unwrap_err().expected, "START-END", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_non_negative() {
+        assert_eq!(non_negative("0"), Ok(0.0));
+        assert_eq!(non_negative(" 562.5 "), Ok(562.5));
+        assert_eq!(non_negative("-1").unwrap_err().to_string(), "expected a non-negative number, got '-1'");
+        assert!(non_negative("fast").is_err());
+        assert!(non_negative("inf").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Reusing one daemon connection for several commands within an invocation.
+
+use serde_json::{json, Value};
+use std::io::{BufReader, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::commands::gen_id;
+use crate::connection::{exchange, CommandError, Connection, Response};
+
+/// Idle time after which a pooled connection is pinged before being reused.
+pub const DEFAULT_MAX_IDLE: Duration = Duration::from_secs(5);
+
+type Connector<S> = Box<dyn FnMut() -> Result<S, CommandError>>;
+type Shared<S> = Arc<Mutex<Option<BufReader<S>>>>;
+
+/// A lazily opened, reusable connection to one session's daemon.
+///
+/// A connection that sat idle longer than `max_idle` may belong to a daemon
+/// that has since restarted, so it is checked with a cheap `ping` first and
+/// transparently replaced if the check fails. With a keepalive, a background
+/// thread also pings at a fixed interval and drops the connection on failure
+/// so the next `send` reconnects.
+pub struct Session<S = Connection> {
+    connect: Connector<S>,
+    conn: Shared<S>,
+    last_used: Instant,
+    max_idle: Duration,
+    /// Run on the connection before each command, e.g. to re-arm timeouts.
+    arm: fn(&S, &Value),
+    /// Dropping this stops the keepalive thread.
+    keepalive: Option<Sender<()>>,
+}
+
+impl Session<Connection> {
+    pub fn open(session: &str, max_idle: Duration) -> Self {
+        let name = session.to_string();
+        let mut pooled = Session::with_connector(max_idle, move || Connection::open(&name));
+        pooled.arm = Connection::arm_timeouts_for;
+        pooled
+    }
+}
+
+impl<S: Read + Write> Session<S> {
+    fn with_connector<C>(max_idle: Duration, connect: C) -> Self
+    where
+        C: FnMut() -> Result<S, CommandError> + 'static,
+    {
+        Session {
+            connect: Box::new(connect),
+            conn: Arc::new(Mutex::new(None)),
+            last_used: Instant::now(),
+            max_idle,
+            arm: |_, _| {},
+            keepalive: None,
+        }
+    }
+
+    pub fn send(&mut self, cmd: Value) -> Result<Response, CommandError> {
+        self.send_streaming(cmd, |_| {})
+    }
+
+    /// Like `send`, handing streamed items to `on_item` as they arrive.
+    pub fn send_streaming<F>(&mut self, cmd: Value, on_item: F) -> Result<Response, CommandError>
+    where
+        F: FnMut(&Response),
+    {
+        let mut conn = self.conn.lock().unwrap();
+        if conn.is_some() && self.last_used.elapsed() > self.max_idle && !ping(&mut conn) {
+            *conn = None;
+        }
+        let reader = match conn.as_mut() {
+            Some(reader) => reader,
+            None => conn.insert(BufReader::new((self.connect)()?)),
+        };
+        (self.arm)(reader.get_ref(), &cmd);
+        let result = exchange(reader, &cmd, on_item);
+        if result.is_err() {
+            *conn = None;
+        }
+        self.last_used = Instant::now();
+        result
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Session<S> {
+    /// Ping an open connection every `interval` from a background thread
+    /// (`--keepalive-interval`) until the session is dropped.
+    pub fn start_keepalive(&mut self, interval: Duration) {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let conn = Arc::clone(&self.conn);
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let mut conn = conn.lock().unwrap();
+                if conn.is_some() && !ping(&mut conn) {
+                    *conn = None;
+                }
+            }
+        });
+        self.keepalive = Some(stop);
+    }
+}
+
+/// Any reply counts as alive, even an error from a daemon without `ping`.
+fn ping<S: Read + Write>(conn: &mut Option<BufReader<S>>) -> bool {
+    let Some(reader) = conn.as_mut() else {
+        return false;
+    };
+    let ping = json!({ "id": gen_id(), "action": "ping" });
+    exchange(reader, &ping, |_| {}).is_ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+
+    /// Answer `replies` requests on `stream`, then hang up. Returns the actions seen.
+    fn serve(stream: UnixStream, replies: usize) -> JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            let mut actions = Vec::new();
+            for _ in 0..replies {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let cmd: Value = serde_json::from_str(&line).unwrap();
+                actions.push(cmd["action"].as_str().unwrap_or("").to_string());
+                let reply = json!({ "id": cmd["id"], "success": true, "data": null });
+                writeln!(reader.get_mut(), "{}", reply).unwrap();
+            }
+            actions
+        })
+    }
+
+    fn pooled(streams: Vec<UnixStream>, max_idle: Duration) -> (Session<UnixStream>, Arc<Mutex<usize>>) {
+        let connects = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&connects);
+        let mut streams = streams.into_iter();
+        let session = Session::with_connector(max_idle, move || {
+            *counter.lock().unwrap() += 1;
+            streams.next().ok_or_else(|| CommandError::DaemonNotFound {
+                attempts: 1,
+                source: std::io::Error::new(std::io::ErrorKind::NotFound, "no daemon"),
+            })
+        });
+        (session, connects)
+    }
+
+    #[test]
+    fn test_stale_connection_reconnects() {
+        let (first, first_peer) = UnixStream::pair().unwrap();
+        let (second, second_peer) = UnixStream::pair().unwrap();
+        let (mut session, connects) = pooled(vec![first, second], Duration::ZERO);
+
+        // The first daemon answers once and then goes away, as on a restart
+        let old_daemon = serve(first_peer, 1);
+        assert!(session.send(json!({ "id": "1", "action": "title" })).unwrap().success);
+        old_daemon.join().unwrap();
+
+        let new_daemon = serve(second_peer, 1);
+        let resp = session.send(json!({ "id": "2", "action": "url" })).unwrap();
+        assert!(resp.success);
+        assert_eq!(*connects.lock().unwrap(), 2);
+        assert_eq!(new_daemon.join().unwrap(), vec!["url"]);
+    }
+
+    #[test]
+    fn test_fresh_connection_is_reused_without_ping() {
+        let (client, peer) = UnixStream::pair().unwrap();
+        let (mut session, connects) = pooled(vec![client], Duration::from_secs(60));
+        let daemon = serve(peer, 2);
+
+        session.send(json!({ "id": "1", "action": "title" })).unwrap();
+        session.send(json!({ "id": "2", "action": "url" })).unwrap();
+        drop(session);
+
+        assert_eq!(*connects.lock().unwrap(), 1);
+        assert_eq!(daemon.join().unwrap(), vec!["title", "url"]);
+    }
+
+    #[test]
+    fn test_idle_connection_is_pinged() {
+        let (client, peer) = UnixStream::pair().unwrap();
+        let (mut session, connects) = pooled(vec![client], Duration::ZERO);
+        let daemon = serve(peer, 3);
+
+        session.send(json!({ "id": "1", "action": "title" })).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        session.send(json!({ "id": "2", "action": "url" })).unwrap();
+        drop(session);
+
+        assert_eq!(*connects.lock().unwrap(), 1);
+        assert_eq!(daemon.join().unwrap(), vec!["title", "ping", "url"]);
+    }
+
+    #[test]
+    fn test_keepalive_pings_at_interval() {
+        let (client, peer) = UnixStream::pair().unwrap();
+        let (mut session, _) = pooled(vec![client], Duration::from_secs(60));
+        let daemon = serve(peer, usize::MAX);
+
+        session.send(json!({ "id": "1", "action": "title" })).unwrap();
+        session.start_keepalive(Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(110));
+        drop(session);
+
+        let actions = daemon.join().unwrap();
+        let pings = actions.iter().filter(|a| *a == "ping").count();
+        assert_eq!(actions[0], "title");
+        assert!((3..=6).contains(&pings), "expected ~5 pings, got {}", pings);
+    }
+
+    #[test]
+    fn test_failed_keepalive_marks_reconnect() {
+        let (first, first_peer) = UnixStream::pair().unwrap();
+        let (second, second_peer) = UnixStream::pair().unwrap();
+        let (mut session, connects) = pooled(vec![first, second], Duration::from_secs(60));
+
+        // The daemon goes away after the first command; the keepalive notices
+        let old_daemon = serve(first_peer, 1);
+        session.send(json!({ "id": "1", "action": "title" })).unwrap();
+        old_daemon.join().unwrap();
+        session.start_keepalive(Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(50));
+        assert!(session.conn.lock().unwrap().is_none());
+
+        let new_daemon = serve(second_peer, 1);
+        assert!(session.send(json!({ "id": "2", "action": "url" })).unwrap().success);
+        drop(session);
+        assert_eq!(*connects.lock().unwrap(), 2);
+        assert_eq!(new_daemon.join().unwrap(), vec!["url"]);
+    }
+}
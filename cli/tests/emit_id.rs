@@ -0,0 +1,70 @@
+//! `--emit-id` against a stand-in daemon that answers every command.
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+/// Listen as session `session`'s daemon in `dir`, recording each command it
+/// receives and answering it successfully (as the CLI's own version, so no
+/// mismatch warning joins the id on stderr).
+fn fake_daemon(dir: &Path, session: &str) -> Arc<Mutex<Vec<Value>>> {
+    let socket = dir.join(format!("agent-browser-{}.sock", session));
+    let listener = UnixListener::bind(&socket).unwrap();
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o600)).unwrap();
+    // Our own pid, so the CLI sees a live daemon
+    fs::write(dir.join(format!("agent-browser-{}.pid", session)), std::process::id().to_string()).unwrap();
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let log = Arc::clone(&log);
+            thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let cmd: Value = serde_json::from_str(&line).unwrap();
+                    let data = json!({ "url": "https://example.com", "version": env!("CARGO_PKG_VERSION") });
+                    let resp = json!({ "id": cmd["id"], "success": true, "data": data });
+                    log.lock().unwrap().push(cmd);
+                    if writeln!(writer, "{}", resp).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    received
+}
+
+#[test]
+fn test_emit_id_with_streamed_output() {
+    for format in ["jsonl-stream", "ndjson"] {
+        let dir = std::env::temp_dir().join(format!("agent-browser-emit-id-{}-{}", std::process::id(), format));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let session = "emit-id";
+        let received = fake_daemon(&dir, session);
+
+        let out = Command::new(env!("CARGO_BIN_EXE_agent-browser"))
+            .args(["--no-spawn", "--session", session, "--emit-id", "--output", format, "get", "url"])
+            .env("XDG_RUNTIME_DIR", &dir)
+            .env("XDG_CONFIG_HOME", &dir)
+            .output()
+            .unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(out.status.success(), "{}: {}", format, String::from_utf8_lossy(&out.stderr));
+        let sent = received.lock().unwrap();
+        let url_cmd = sent.iter().find(|c| c["action"] == "url").expect("url command sent");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert_eq!(stderr.lines().next(), url_cmd["id"].as_str(), "{}", format);
+    }
+}
@@ -0,0 +1,33 @@
+//! Exit codes of the built binary for failures caught before any daemon is
+//! contacted.
+
+use std::process::{Command, Output};
+
+use serde_json::Value;
+
+/// `EXIT_USAGE` in main.rs.
+const EXIT_USAGE: i32 = 2;
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_agent-browser"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", std::env::temp_dir().join("agent-browser-exit-codes-test"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_session_rename_usage_exit() {
+    let out = run(&["session", "rename", "only-one"]);
+    assert_eq!(out.status.code(), Some(EXIT_USAGE));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("session rename <old> <new>"));
+}
+
+#[test]
+fn test_session_config_usage_exit() {
+    let out = run(&["--json", "session", "config", "bogus"]);
+    assert_eq!(out.status.code(), Some(EXIT_USAGE));
+    let line: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(line["success"], false);
+    assert_eq!(line["type"], "usage");
+}
@@ -1,5 +1,5 @@
 import type { Page, Frame } from 'playwright-core';
-import type { BrowserManager, ScreencastFrame } from './browser.js';
+import type { BrowserManager, NetworkEvent, ScreencastFrame } from './browser.js';
 import type {
   Command,
   Response,
@@ -38,6 +38,7 @@ import type {
   PdfCommand,
   RouteCommand,
   RequestsCommand,
+  NetworkTailCommand,
   DownloadCommand,
   GeolocationCommand,
   PermissionsCommand,
@@ -73,6 +74,8 @@ import type {
   AddStyleCommand,
   EmulateMediaCommand,
   OfflineCommand,
+  ThrottleNetworkCommand,
+  ThrottleCpuCommand,
   HeadersCommand,
   GetByAltTextCommand,
   GetByTitleCommand,
@@ -105,6 +108,7 @@ import type {
   RecordingStopCommand,
   RecordingRestartCommand,
   NavigateData,
+  NavigationSummary,
   ScreenshotData,
   EvaluateData,
   ContentData,
@@ -121,6 +125,17 @@ import type {
   StylesData,
 } from './types.js';
 import { successResponse, errorResponse } from './protocol.js';
+import * as fs from 'fs';
+
+// The package version, reported to the CLI for its version handshake
+const DAEMON_VERSION: string = (() => {
+  try {
+    const pkg = JSON.parse(fs.readFileSync(new URL('../package.json', import.meta.url), 'utf8'));
+    return typeof pkg.version === 'string' ? pkg.version : 'unknown';
+  } catch {
+    return 'unknown';
+  }
+})();
 
 // Callback for screencast frames - will be set by the daemon when streaming is active
 let screencastFrameCallback: ((frame: ScreencastFrame) => void) | null = null;
@@ -194,8 +209,21 @@ export function toAIFriendlyError(error: unknown, selector: string): Error {
 
 /**
  * Execute a command and return a response
+ * With `frame` set, the command runs inside the matching frame (see BrowserManager.withFrame)
  */
 export async function executeCommand(command: Command, browser: BrowserManager): Promise<Response> {
+  if (!command.frame) {
+    return dispatchCommand(command, browser);
+  }
+  try {
+    return await browser.withFrame(command.frame, () => dispatchCommand(command, browser));
+  } catch (error) {
+    const message = error instanceof Error ? error.message : String(error);
+    return errorResponse(command.id, message);
+  }
+}
+
+async function dispatchCommand(command: Command, browser: BrowserManager): Promise<Response> {
   try {
     switch (command.action) {
       case 'launch':
@@ -238,6 +266,8 @@ export async function executeCommand(command: Command, browser: BrowserManager):
         return await handleScreenshot(command, browser);
       case 'snapshot':
         return await handleSnapshot(command, browser);
+      case 'a11y_snapshot':
+        return await handleA11ySnapshot(command, browser);
       case 'evaluate':
         return await handleEvaluate(command, browser);
       case 'wait':
@@ -252,6 +282,14 @@ export async function executeCommand(command: Command, browser: BrowserManager):
         return await handleContent(command, browser);
       case 'close':
         return await handleClose(command, browser);
+      case 'version':
+        return successResponse(command.id, { version: DAEMON_VERSION });
+      case 'ping':
+        return successResponse(command.id, { pong: true });
+      case 'cdp_url':
+        return successResponse(command.id, { wsEndpoint: await browser.getCdpEndpoint() });
+      case 'session_env':
+        return handleSessionEnv(command, browser);
       case 'tab_new':
         return await handleTabNew(command, browser);
       case 'tab_list':
@@ -291,14 +329,17 @@ export async function executeCommand(command: Command, browser: BrowserManager):
       case 'permissions':
         return await handlePermissions(command, browser);
       case 'viewport':
+      case 'setViewport':
         return await handleViewport(command, browser);
       case 'useragent':
         return await handleUserAgent(command, browser);
       case 'device':
         return await handleDevice(command, browser);
       case 'back':
+      case 'goBack':
         return await handleBack(command, browser);
       case 'forward':
+      case 'goForward':
         return await handleForward(command, browser);
       case 'reload':
         return await handleReload(command, browser);
@@ -378,6 +419,10 @@ export async function executeCommand(command: Command, browser: BrowserManager):
         return await handleEmulateMedia(command, browser);
       case 'offline':
         return await handleOffline(command, browser);
+      case 'throttle_network':
+        return await handleThrottleNetwork(command, browser);
+      case 'throttle_cpu':
+        return await handleThrottleCpu(command, browser);
       case 'headers':
         return await handleHeaders(command, browser);
       case 'pause':
@@ -475,16 +520,94 @@ async function handleNavigate(
     await browser.setScopedHeaders(command.url, command.headers);
   }
 
-  await page.goto(command.url, {
-    waitUntil: command.waitUntil ?? 'load',
-  });
+  // Seed web storage from an init script that only lives for this navigation,
+  // so later loads don't overwrite what the app stored
+  let storageScript: string | null = null;
+  const cdp =
+    command.localStorage || command.sessionStorage ? await browser.getCDPSession() : null;
+  if (cdp) {
+    const { identifier } = await cdp.send('Page.addScriptToEvaluateOnNewDocument', {
+      source: storageSeedScript(
+        new URL(command.url).origin,
+        command.localStorage ?? {},
+        command.sessionStorage ?? {}
+      ),
+    });
+    storageScript = identifier;
+  }
+
+  let response: Awaited<ReturnType<Page['goto']>>;
+  try {
+    response = await page.goto(command.url, {
+      waitUntil: command.waitUntil ?? 'load',
+    });
+  } finally {
+    if (cdp && storageScript) {
+      await cdp.send('Page.removeScriptToEvaluateOnNewDocument', { identifier: storageScript });
+    }
+  }
+
+  const url = page.url();
+  const title = await page.title();
+  const metrics = command.measure || command.summary ? await collectLoadMetrics(page) : null;
+
+  let summary: NavigationSummary | undefined;
+  if (command.summary) {
+    const redirects: string[] = [];
+    for (let req = response?.request().redirectedFrom(); req; req = req.redirectedFrom()) {
+      redirects.unshift(req.url());
+    }
+    summary = {
+      status: response?.status() ?? null,
+      url,
+      redirects,
+      title,
+      loadTime: metrics?.load ?? null,
+    };
+  }
 
   return successResponse(command.id, {
-    url: page.url(),
-    title: await page.title(),
+    url,
+    title,
+    ...(command.measure && metrics && { metrics }),
+    ...(summary && { summary }),
   });
 }
 
+/**
+ * Load timings (ms since navigation start) and transfer stats of the current document
+ */
+async function collectLoadMetrics(page: Page): Promise<Record<string, number | null>> {
+  return (await page.evaluate(`(() => {
+    const nav = performance.getEntriesByType('navigation')[0];
+    const paint = (name) => performance.getEntriesByName(name)[0]?.startTime ?? null;
+    return {
+      ttfb: nav ? nav.responseStart : null,
+      domContentLoaded: nav ? nav.domContentLoadedEventEnd : null,
+      load: nav ? nav.loadEventEnd : null,
+      firstPaint: paint('first-paint'),
+      firstContentfulPaint: paint('first-contentful-paint'),
+      transferSize: nav ? nav.transferSize : null,
+      resources: performance.getEntriesByType('resource').length,
+    };
+  })()`)) as Record<string, number | null>;
+}
+
+/**
+ * Init script that writes the given entries to web storage on pages of `origin`
+ */
+function storageSeedScript(
+  origin: string,
+  local: Record<string, string>,
+  session: Record<string, string>
+): string {
+  return `(() => {
+  if (location.origin !== ${JSON.stringify(origin)}) return;
+  for (const [k, v] of Object.entries(${JSON.stringify(local)})) localStorage.setItem(k, v);
+  for (const [k, v] of Object.entries(${JSON.stringify(session)})) sessionStorage.setItem(k, v);
+})();`;
+}
+
 async function handleClick(command: ClickCommand, browser: BrowserManager): Promise<Response> {
   // Support both refs (@e1) and regular selectors
   const locator = browser.getLocator(command.selector);
@@ -561,6 +684,16 @@ async function handleScreenshot(
   }
 }
 
+async function handleA11ySnapshot(
+  command: Command & { action: 'a11y_snapshot' },
+  browser: BrowserManager
+): Promise<Response<SnapshotData>> {
+  const locator = command.selector
+    ? browser.getLocator(command.selector).first()
+    : browser.getPage().locator(':root');
+  return successResponse(command.id, { snapshot: await locator.ariaSnapshot() });
+}
+
 async function handleSnapshot(
   command: Command & {
     action: 'snapshot';
@@ -1069,6 +1202,30 @@ async function handleRequests(
   return successResponse(command.id, { requests });
 }
 
+/**
+ * Stream each finished request to `emit` until `stopped` settles (the client
+ * disconnects or cancels the tail). The daemon calls this directly, since the
+ * events go out as partial lines ahead of the final response.
+ */
+export async function handleNetworkTail(
+  command: NetworkTailCommand,
+  browser: BrowserManager,
+  emit: (event: NetworkEvent) => void,
+  stopped: Promise<void>
+): Promise<Response> {
+  let count = 0;
+  const unsubscribe = browser.onNetworkEvent((event) => {
+    count++;
+    emit(event);
+  });
+  try {
+    await stopped;
+  } finally {
+    unsubscribe();
+  }
+  return successResponse(command.id, { streamed: count });
+}
+
 async function handleDownload(
   command: DownloadCommand,
   browser: BrowserManager
@@ -1109,6 +1266,23 @@ async function handlePermissions(
   });
 }
 
+function handleSessionEnv(
+  command: Command & { action: 'session_env' },
+  browser: BrowserManager
+): Response {
+  const options = browser.getLaunchOptions();
+  if (!options) {
+    return successResponse(command.id, { launched: false });
+  }
+  // The CLI redacts passwords and tokens before printing
+  const { id: _id, action: _action, ...config } = options;
+  return successResponse(command.id, {
+    launched: true,
+    session: process.env.AGENT_BROWSER_SESSION || 'default',
+    ...config,
+  });
+}
+
 async function handleViewport(
   command: ViewportCommand,
   browser: BrowserManager
@@ -1150,7 +1324,7 @@ async function handleDevice(command: DeviceCommand, browser: BrowserManager): Pr
 }
 
 async function handleBack(
-  command: Command & { action: 'back' },
+  command: Command & { action: 'back' | 'goBack' },
   browser: BrowserManager
 ): Promise<Response> {
   const page = browser.getPage();
@@ -1159,7 +1333,7 @@ async function handleBack(
 }
 
 async function handleForward(
-  command: Command & { action: 'forward' },
+  command: Command & { action: 'forward' | 'goForward' },
   browser: BrowserManager
 ): Promise<Response> {
   const page = browser.getPage();
@@ -1172,7 +1346,16 @@ async function handleReload(
   browser: BrowserManager
 ): Promise<Response> {
   const page = browser.getPage();
-  await page.reload();
+  if (command.hard) {
+    // Playwright's reload can't skip the cache; CDP's can
+    const cdp = await browser.getCDPSession();
+    await Promise.all([
+      page.waitForEvent('load'),
+      cdp.send('Page.reload', { ignoreCache: true }),
+    ]);
+  } else {
+    await page.reload();
+  }
   return successResponse(command.id, { url: page.url() });
 }
 
@@ -1237,7 +1420,11 @@ async function handleIsChecked(
 
 async function handleCount(command: CountCommand, browser: BrowserManager): Promise<Response> {
   const page = browser.getPage();
-  const count = await page.locator(command.selector).count();
+  const locator = page.locator(command.selector);
+  const count = await locator.count();
+  if (command.highlight && count > 0) {
+    await locator.highlight();
+  }
   return successResponse(command.id, { count });
 }
 
@@ -1598,6 +1785,35 @@ async function handleOffline(command: OfflineCommand, browser: BrowserManager):
   return successResponse(command.id, { offline: command.offline });
 }
 
+async function handleThrottleNetwork(
+  command: ThrottleNetworkCommand,
+  browser: BrowserManager
+): Promise<Response> {
+  const cdp = await browser.getCDPSession();
+  await cdp.send('Network.enable');
+  await cdp.send('Network.emulateNetworkConditions', {
+    offline: command.offline,
+    downloadThroughput: command.downloadThroughput,
+    uploadThroughput: command.uploadThroughput,
+    latency: command.latency,
+  });
+  return successResponse(command.id, {
+    offline: command.offline,
+    downloadThroughput: command.downloadThroughput,
+    uploadThroughput: command.uploadThroughput,
+    latency: command.latency,
+  });
+}
+
+async function handleThrottleCpu(
+  command: ThrottleCpuCommand,
+  browser: BrowserManager
+): Promise<Response> {
+  const cdp = await browser.getCDPSession();
+  await cdp.send('Emulation.setCPUThrottlingRate', { rate: command.rate });
+  return successResponse(command.id, { rate: command.rate });
+}
+
 async function handleHeaders(command: HeadersCommand, browser: BrowserManager): Promise<Response> {
   await browser.setExtraHeaders(command.headers);
   return successResponse(command.id, { set: true });
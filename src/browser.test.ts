@@ -183,6 +183,36 @@ describe('BrowserManager', () => {
     });
   });
 
+  describe('contexts on a shared browser', () => {
+    it('should keep cookies apart between managers sharing a browser', async () => {
+      const first = new BrowserManager();
+      const second = new BrowserManager();
+      await first.launchInBrowser(browser.getBrowser()!, { id: 'a', action: 'launch' });
+      await second.launchInBrowser(browser.getBrowser()!, { id: 'b', action: 'launch' });
+
+      await first
+        .getPage()
+        .context()
+        .addCookies([{ name: 'isolated', value: 'first', url: 'https://example.com' }]);
+
+      const secondCookies = await second.getPage().context().cookies();
+      expect(secondCookies.find((c) => c.name === 'isolated')).toBeUndefined();
+      const ownerCookies = await browser.getPage().context().cookies();
+      expect(ownerCookies.find((c) => c.name === 'isolated')).toBeUndefined();
+
+      await first.close();
+      await second.close();
+    });
+
+    it('should leave the shared browser running when a context manager closes', async () => {
+      const manager = new BrowserManager();
+      await manager.launchInBrowser(browser.getBrowser()!, { id: 'c', action: 'launch' });
+      await manager.close();
+      expect(browser.getBrowser()?.isConnected()).toBe(true);
+      expect(manager.isLaunched()).toBe(false);
+    });
+  });
+
   describe('localStorage operations', () => {
     it('should set and get localStorage item', async () => {
       const page = browser.getPage();
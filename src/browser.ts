@@ -16,7 +16,7 @@ import {
 } from 'playwright-core';
 import path from 'node:path';
 import os from 'node:os';
-import { existsSync, mkdirSync, rmSync } from 'node:fs';
+import { existsSync, mkdirSync, rmSync, writeFileSync } from 'node:fs';
 import type { LaunchCommand } from './types.js';
 import { type RefMap, type EnhancedSnapshot, getEnhancedSnapshot, parseRef } from './snapshot.js';
 
@@ -52,6 +52,15 @@ interface TrackedRequest {
   resourceType: string;
 }
 
+export interface NetworkEvent {
+  method: string;
+  url: string;
+  status?: number; // Absent when the request failed
+  duration?: number; // ms from request start to response end
+  resourceType: string;
+  failure?: string;
+}
+
 interface ConsoleMessage {
   type: string;
   text: string;
@@ -69,7 +78,11 @@ interface PageError {
 export class BrowserManager {
   private browser: Browser | null = null;
   private cdpPort: number | null = null;
+  private launchOptions: LaunchCommand | null = null;
+  private diagnosticsDir: string | null = null;
   private isPersistentContext: boolean = false;
+  // False when this manager only opened a context on another manager's browser
+  private ownsBrowser: boolean = true;
   private browserbaseSessionId: string | null = null;
   private browserbaseApiKey: string | null = null;
   private browserUseSessionId: string | null = null;
@@ -108,6 +121,25 @@ export class BrowserManager {
     return this.browser !== null || this.isPersistentContext;
   }
 
+  /**
+   * Get the options the current browser was launched with, or null if none is
+   */
+  getLaunchOptions(): LaunchCommand | null {
+    return this.launchOptions;
+  }
+
+  /**
+   * Get the browser's websocket endpoint when connected over CDP (--cdp),
+   * or null for browsers Playwright launched itself
+   */
+  async getCdpEndpoint(): Promise<string | null> {
+    if (this.cdpPort === null) return null;
+    const response = await fetch(`http://localhost:${this.cdpPort}/json/version`);
+    if (!response.ok) return null;
+    const info = (await response.json()) as { webSocketDebuggerUrl?: string };
+    return info.webSocketDebuggerUrl ?? null;
+  }
+
   /**
    * Get enhanced snapshot with refs and cache the ref map
    */
@@ -175,9 +207,8 @@ export class BrowserManager {
     const locator = this.getLocatorFromRef(selectorOrRef);
     if (locator) return locator;
 
-    // Otherwise treat as regular selector
-    const page = this.getPage();
-    return page.locator(selectorOrRef);
+    // Otherwise treat as regular selector, in the active frame
+    return this.getFrame().locator(selectorOrRef);
   }
 
   /**
@@ -231,6 +262,39 @@ export class BrowserManager {
     }
   }
 
+  /**
+   * Find a frame for a command's `frame` field: the content frame of the iframe
+   * element matching it as a selector, else the first frame whose URL contains it
+   */
+  async findFrame(target: string): Promise<Frame> {
+    const page = this.getPage();
+    // Frame URLs are rarely valid selectors; treat a selector error as no match
+    const element = await page.$(target).catch(() => null);
+    const contentFrame = element ? await element.contentFrame() : null;
+    if (contentFrame) {
+      return contentFrame;
+    }
+    const frame = page.frames().find((f) => f !== page.mainFrame() && f.url().includes(target));
+    if (!frame) {
+      throw new Error(`Frame not found: no iframe matches selector or URL "${target}"`);
+    }
+    return frame;
+  }
+
+  /**
+   * Run `fn` with the frame matching `target` active, then restore the previous frame
+   */
+  async withFrame<T>(target: string, fn: () => Promise<T>): Promise<T> {
+    const frame = await this.findFrame(target);
+    const previous = this.activeFrame;
+    this.activeFrame = frame;
+    try {
+      return await fn();
+    } finally {
+      this.activeFrame = previous;
+    }
+  }
+
   /**
    * Switch back to main frame
    */
@@ -287,6 +351,32 @@ export class BrowserManager {
     });
   }
 
+  /**
+   * Call `listener` as each request on the current page finishes or fails.
+   * Returns a function that stops listening.
+   */
+  onNetworkEvent(listener: (event: NetworkEvent) => void): () => void {
+    const page = this.getPage();
+    const report = async (request: Request) => {
+      const response = await request.response().catch(() => null);
+      const { responseEnd } = request.timing();
+      listener({
+        method: request.method(),
+        url: request.url(),
+        status: response?.status(),
+        duration: responseEnd >= 0 ? responseEnd : undefined,
+        resourceType: request.resourceType(),
+        failure: request.failure()?.errorText,
+      });
+    };
+    page.on('requestfinished', report);
+    page.on('requestfailed', report);
+    return () => {
+      page.removeListener('requestfinished', report);
+      page.removeListener('requestfailed', report);
+    };
+  }
+
   /**
    * Get tracked requests
    */
@@ -831,6 +921,10 @@ export class BrowserManager {
         return;
       }
     }
+    this.launchOptions = options;
+    this.diagnosticsDir = options.keepBrowserOnCrash
+      ? (options.diagnosticsDir ?? path.join(os.tmpdir(), 'agent-browser-diagnostics'))
+      : null;
 
     if (cdpPort) {
       await this.connectViaCDP(cdpPort);
@@ -868,7 +962,12 @@ export class BrowserManager {
         {
           headless: false,
           executablePath: options.executablePath,
-          args: [`--disable-extensions-except=${extPaths}`, `--load-extension=${extPaths}`],
+          chromiumSandbox: !options.noSandbox,
+          args: [
+            `--disable-extensions-except=${extPaths}`,
+            `--load-extension=${extPaths}`,
+            ...(options.args ?? []),
+          ],
           viewport,
           extraHTTPHeaders: options.headers,
           ...(options.proxy && { proxy: options.proxy }),
@@ -876,9 +975,12 @@ export class BrowserManager {
       );
       this.isPersistentContext = true;
     } else {
+      // Playwright disables Chromium's sandbox unless asked; keep it on by default
       this.browser = await launcher.launch({
         headless: options.headless ?? true,
         executablePath: options.executablePath,
+        chromiumSandbox: !options.noSandbox,
+        ...(options.args && { args: options.args }),
       });
       this.cdpPort = null;
       context = await this.browser.newContext({
@@ -897,6 +999,30 @@ export class BrowserManager {
     this.setupPageTracking(page);
   }
 
+  /**
+   * Open an isolated context and page on a browser another manager launched,
+   * so its cookies and storage stay separate. Closing this manager closes only
+   * what it opened.
+   */
+  async launchInBrowser(browser: Browser, options: LaunchCommand): Promise<void> {
+    this.browser = browser;
+    this.ownsBrowser = false;
+    this.launchOptions = options;
+
+    const context = await browser.newContext({
+      viewport: options.viewport ?? { width: 1280, height: 720 },
+      extraHTTPHeaders: options.headers,
+      ...(options.proxy && { proxy: options.proxy }),
+    });
+    context.setDefaultTimeout(60000);
+    this.contexts.push(context);
+
+    const page = await context.newPage();
+    this.pages.push(page);
+    this.activePageIndex = 0;
+    this.setupPageTracking(page);
+  }
+
   /**
    * Connect to a running browser via CDP (Chrome DevTools Protocol)
    */
@@ -967,6 +1093,10 @@ export class BrowserManager {
       });
     });
 
+    page.on('crash', () => {
+      void this.writeCrashDiagnostics(page);
+    });
+
     page.on('close', () => {
       const index = this.pages.indexOf(page);
       if (index !== -1) {
@@ -978,6 +1108,27 @@ export class BrowserManager {
     });
   }
 
+  /**
+   * Save console logs, page errors and a screenshot of a crashed page,
+   * if launched with keepBrowserOnCrash
+   */
+  private async writeCrashDiagnostics(page: Page): Promise<void> {
+    const dir = this.diagnosticsDir;
+    if (!dir) return;
+    mkdirSync(dir, { recursive: true });
+    const base = path.join(dir, `crash-${new Date().toISOString().replace(/[:.]/g, '-')}`);
+    writeFileSync(
+      `${base}.json`,
+      JSON.stringify(
+        { url: page.url(), console: this.consoleMessages, errors: this.pageErrors },
+        null,
+        2
+      )
+    );
+    // Best effort: the renderer may be gone
+    await page.screenshot({ path: `${base}.png` }).catch(() => {});
+  }
+
   /**
    * Set up tracking for new pages in a context (for CDP connections)
    */
@@ -1561,17 +1712,17 @@ export class BrowserManager {
         this.browser = null;
       }
     } else {
-      // Regular browser: close everything
+      // Regular browser: close everything, leaving a shared browser to its owner
       for (const page of this.pages) {
         await page.close().catch(() => {});
       }
       for (const context of this.contexts) {
         await context.close().catch(() => {});
       }
-      if (this.browser) {
+      if (this.browser && this.ownsBrowser) {
         await this.browser.close().catch(() => {});
-        this.browser = null;
       }
+      this.browser = null;
     }
 
     this.pages = [];
@@ -1582,6 +1733,9 @@ export class BrowserManager {
     this.browserUseSessionId = null;
     this.browserUseApiKey = null;
     this.isPersistentContext = false;
+    this.ownsBrowser = true;
+    this.launchOptions = null;
+    this.diagnosticsDir = null;
     this.activePageIndex = 0;
     this.refMap = {};
     this.lastSnapshot = '';
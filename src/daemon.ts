@@ -3,13 +3,17 @@ import * as fs from 'fs';
 import * as path from 'path';
 import * as os from 'os';
 import { BrowserManager } from './browser.js';
-import { parseCommand, serializeResponse, errorResponse } from './protocol.js';
-import { executeCommand } from './actions.js';
+import { parseCommand, serializeResponse, errorResponse, successResponse } from './protocol.js';
+import { executeCommand, handleNetworkTail } from './actions.js';
+import type { Response } from './types.js';
 import { StreamServer } from './stream-server.js';
 
 // Platform detection
 const isWindows = process.platform === 'win32';
 
+// Listen on localhost TCP instead of a Unix socket / named pipe (CLI `--tcp`)
+const useTcp = process.env.AGENT_BROWSER_TRANSPORT === 'tcp';
+
 // Session support - each session gets its own socket/pid
 let currentSession = process.env.AGENT_BROWSER_SESSION || 'default';
 
@@ -34,8 +38,26 @@ export function getSession(): string {
 }
 
 /**
- * Get port number for TCP mode (Windows)
- * Uses a hash of the session name to get a consistent port
+ * Directory for the socket, pid and port files: the one the CLI resolved
+ * (AGENT_BROWSER_SOCKET_DIR), else $XDG_RUNTIME_DIR on Unix if it is an
+ * existing absolute directory, else the temp dir. Must match the CLI.
+ */
+export function getSessionDir(): string {
+  if (process.env.AGENT_BROWSER_SOCKET_DIR) return process.env.AGENT_BROWSER_SOCKET_DIR;
+  const xdg = process.env.XDG_RUNTIME_DIR;
+  if (!isWindows && xdg && path.isAbsolute(xdg)) {
+    try {
+      if (fs.statSync(xdg).isDirectory()) return xdg;
+    } catch {
+      // Missing; fall back to the temp dir
+    }
+  }
+  return os.tmpdir();
+}
+
+/**
+ * Preferred port for TCP mode (--tcp)
+ * Uses a hash of the session name; the daemon falls back to a free port if it is taken
  */
 function getPortForSession(session: string): number {
   let hash = 0;
@@ -48,22 +70,52 @@ function getPortForSession(session: string): number {
 }
 
 /**
- * Get the socket path for the current session (Unix) or port (Windows)
+ * Get the socket path (Unix), named pipe (Windows) or port (TCP mode) for the current session
  */
 export function getSocketPath(session?: string): string {
   const sess = session ?? currentSession;
+  if (useTcp) {
+    return String(getDaemonPort(sess));
+  }
   if (isWindows) {
-    return String(getPortForSession(sess));
+    return `\\\\.\\pipe\\agent-browser-${sess}`;
   }
-  return path.join(os.tmpdir(), `agent-browser-${sess}.sock`);
+  return path.join(getSessionDir(), `agent-browser-${sess}.sock`);
 }
 
 /**
- * Get the port file path for Windows (stores the port number)
+ * Get the port file path for TCP mode (stores the port number)
  */
 export function getPortFile(session?: string): string {
   const sess = session ?? currentSession;
-  return path.join(os.tmpdir(), `agent-browser-${sess}.port`);
+  return path.join(getSessionDir(), `agent-browser-${sess}.port`);
+}
+
+/**
+ * Get the port a TCP-mode daemon listens on: the one recorded in its port file,
+ * or the hashed preferred port if there is no file yet
+ */
+function getDaemonPort(session: string): number {
+  try {
+    const port = parseInt(fs.readFileSync(getPortFile(session), 'utf8').trim(), 10);
+    if (port > 0 && port < 65536) return port;
+  } catch {
+    // No port file; fall back to the hash
+  }
+  return getPortForSession(session);
+}
+
+/**
+ * Listen on 127.0.0.1:port and resolve with the port actually bound
+ */
+function listenTcp(server: net.Server, port: number): Promise<number> {
+  return new Promise((resolve, reject) => {
+    server.once('error', reject);
+    server.listen(port, '127.0.0.1', () => {
+      server.off('error', reject);
+      resolve((server.address() as net.AddressInfo).port);
+    });
+  });
 }
 
 /**
@@ -71,7 +123,7 @@ export function getPortFile(session?: string): string {
  */
 export function getPidFile(session?: string): string {
   const sess = session ?? currentSession;
-  return path.join(os.tmpdir(), `agent-browser-${sess}.pid`);
+  return path.join(getSessionDir(), `agent-browser-${sess}.pid`);
 }
 
 /**
@@ -95,16 +147,16 @@ export function isDaemonRunning(session?: string): boolean {
 
 /**
  * Get connection info for the current session
- * Returns { type: 'unix', path: string } or { type: 'tcp', port: number }
+ * Returns { type: 'unix', path: string }, { type: 'pipe', path: string } or { type: 'tcp', port: number }
  */
 export function getConnectionInfo(
   session?: string
-): { type: 'unix'; path: string } | { type: 'tcp'; port: number } {
+): { type: 'unix' | 'pipe'; path: string } | { type: 'tcp'; port: number } {
   const sess = session ?? currentSession;
-  if (isWindows) {
-    return { type: 'tcp', port: getPortForSession(sess) };
+  if (useTcp) {
+    return { type: 'tcp', port: getDaemonPort(sess) };
   }
-  return { type: 'unix', path: path.join(os.tmpdir(), `agent-browser-${sess}.sock`) };
+  return { type: isWindows ? 'pipe' : 'unix', path: getSocketPath(sess) };
 }
 
 /**
@@ -116,10 +168,11 @@ export function cleanupSocket(session?: string): void {
   try {
     if (fs.existsSync(pidFile)) fs.unlinkSync(pidFile);
     if (fs.existsSync(streamPortFile)) fs.unlinkSync(streamPortFile);
-    if (isWindows) {
+    if (useTcp) {
       const portFile = getPortFile(session);
       if (fs.existsSync(portFile)) fs.unlinkSync(portFile);
-    } else {
+    } else if (!isWindows) {
+      // Named pipes disappear with the server; only Unix sockets leave a file
       const socketPath = getSocketPath(session);
       if (fs.existsSync(socketPath)) fs.unlinkSync(socketPath);
     }
@@ -133,7 +186,7 @@ export function cleanupSocket(session?: string): void {
  */
 export function getStreamPortFile(session?: string): string {
   const sess = session ?? currentSession;
-  return path.join(os.tmpdir(), `agent-browser-${sess}.stream`);
+  return path.join(getSessionDir(), `agent-browser-${sess}.stream`);
 }
 
 /**
@@ -147,6 +200,51 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
   const browser = new BrowserManager();
   let shuttingDown = false;
 
+  // Exit after this many seconds without commands (AGENT_BROWSER_IDLE_TIMEOUT,
+  // fixed when the CLI spawns the daemon). A running command is never idle.
+  const idleTimeoutMs = parseFloat(process.env.AGENT_BROWSER_IDLE_TIMEOUT ?? '') * 1000;
+  let idleTimer: NodeJS.Timeout | null = null;
+  let inFlight = 0;
+  const armIdleTimer = () => {
+    if (idleTimer) clearTimeout(idleTimer);
+    idleTimer = null;
+    if (idleTimeoutMs > 0 && inFlight === 0) {
+      idleTimer = setTimeout(() => void shutdown(), idleTimeoutMs);
+    }
+  };
+
+  // Commands still running, by id, so a `cancel` from another connection can
+  // answer them early. Playwright can't abort an action midway: the work
+  // finishes in the background and its result is dropped.
+  const cancellers = new Map<string, () => void>();
+  const runCancellable = (id: string, run: () => Promise<Response>): Promise<Response> =>
+    new Promise((resolve, reject) => {
+      cancellers.set(id, () => resolve(errorResponse(id, 'Cancelled')));
+      run()
+        .then(resolve, reject)
+        .finally(() => cancellers.delete(id));
+    });
+
+  // Compact sessions send a contextId with every command; each gets its own
+  // manager holding a separate BrowserContext on this daemon's browser, so
+  // cookies, storage and tabs never mix between them
+  const contextManagers = new Map<string, Promise<BrowserManager>>();
+  const managerFor = (contextId: string): Promise<BrowserManager> => {
+    let manager = contextManagers.get(contextId);
+    if (!manager) {
+      const shared = browser.getBrowser();
+      if (!shared) {
+        throw new Error('Compact sessions need a regular browser launch (not extensions)');
+      }
+      const created = new BrowserManager();
+      const options = browser.getLaunchOptions() ?? { id: contextId, action: 'launch' };
+      manager = created.launchInBrowser(shared, options).then(() => created);
+      manager.catch(() => contextManagers.delete(contextId));
+      contextManagers.set(contextId, manager);
+    }
+    return manager;
+  };
+
   // Start stream server if port is specified (or use default if env var is set)
   const streamPort =
     options?.streamPort ??
@@ -177,6 +275,10 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
 
         if (!line.trim()) continue;
 
+        // Errors are answered with the request's id, so the CLI can match them up
+        let commandId = 'error';
+        inFlight++;
+        armIdleTimer();
         try {
           const parseResult = parseCommand(line);
 
@@ -185,12 +287,42 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
             socket.write(serializeResponse(resp) + '\n');
             continue;
           }
+          commandId = parseResult.command.id;
+
+          if (parseResult.command.action === 'cancel') {
+            const cancel = cancellers.get(parseResult.command.commandId);
+            cancel?.();
+            socket.write(
+              serializeResponse(successResponse(commandId, { cancelled: cancel !== undefined })) +
+                '\n'
+            );
+            continue;
+          }
+
+          // Closing a compact session closes only its context; the daemon stays up
+          // for the others. Its launch starts the shared browser if none is running.
+          const contextId = parseResult.command.contextId;
+          if (contextId && parseResult.command.action === 'close') {
+            const manager = contextManagers.get(contextId);
+            contextManagers.delete(contextId);
+            await (await manager)?.close();
+            socket.write(serializeResponse(successResponse(commandId, { closed: true })) + '\n');
+            continue;
+          }
+          if (contextId && parseResult.command.action === 'launch' && !browser.isLaunched()) {
+            await browser.launch(parseResult.command);
+          }
 
           // Auto-launch browser if not already launched and this isn't a launch command
           if (
             !browser.isLaunched() &&
             parseResult.command.action !== 'launch' &&
-            parseResult.command.action !== 'close'
+            parseResult.command.action !== 'close' &&
+            parseResult.command.action !== 'version' &&
+            parseResult.command.action !== 'ping' &&
+            parseResult.command.action !== 'shutdown' &&
+            parseResult.command.action !== 'cdp_url' &&
+            parseResult.command.action !== 'session_env'
           ) {
             const extensions = process.env.AGENT_BROWSER_EXTENSIONS
               ? process.env.AGENT_BROWSER_EXTENSIONS.split(',')
@@ -206,6 +338,16 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
             });
           }
 
+          // Acknowledge, then close the browser and remove our files before
+          // exiting; the CLI waits for the pid file to go
+          if (parseResult.command.action === 'shutdown') {
+            socket.write(
+              serializeResponse(successResponse(parseResult.command.id, { shuttingDown: true })) + '\n'
+            );
+            void shutdown();
+            return;
+          }
+
           // Handle close command specially
           if (parseResult.command.action === 'close') {
             const response = await executeCommand(parseResult.command, browser);
@@ -222,11 +364,40 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
             return;
           }
 
-          const response = await executeCommand(parseResult.command, browser);
+          const target =
+            contextId && browser.isLaunched() ? await managerFor(contextId) : browser;
+
+          // Stream network events as partial lines until the CLI hangs up or cancels
+          if (parseResult.command.action === 'network_tail') {
+            const command = parseResult.command;
+            const stopped = new Promise<void>((resolve) => {
+              socket.once('close', () => resolve());
+              cancellers.set(command.id, resolve);
+            });
+            const response = await handleNetworkTail(
+              command,
+              target,
+              (event) =>
+                socket.write(
+                  JSON.stringify({ ...successResponse(command.id, event), partial: true }) + '\n'
+                ),
+              stopped
+            ).finally(() => cancellers.delete(command.id));
+            if (!socket.destroyed) {
+              socket.write(serializeResponse(response) + '\n');
+            }
+            continue;
+          }
+
+          const command = parseResult.command;
+          const response = await runCancellable(command.id, () => executeCommand(command, target));
           socket.write(serializeResponse(response) + '\n');
         } catch (err) {
           const message = err instanceof Error ? err.message : String(err);
-          socket.write(serializeResponse(errorResponse('error', message)) + '\n');
+          socket.write(serializeResponse(errorResponse(commandId, message)) + '\n');
+        } finally {
+          inFlight--;
+          armIdleTimer();
         }
       }
     });
@@ -241,19 +412,30 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
   // Write PID file before listening
   fs.writeFileSync(pidFile, process.pid.toString());
 
-  if (isWindows) {
-    // Windows: use TCP socket on localhost
-    const port = getPortForSession(currentSession);
-    const portFile = getPortFile();
-    fs.writeFileSync(portFile, port.toString());
-    server.listen(port, '127.0.0.1', () => {
-      // Daemon is ready on TCP port
-    });
+  if (useTcp) {
+    // TCP mode: localhost port hashed from the session name, or any free port
+    // if that one is taken. The port file is written once we are listening, so
+    // clients that find it can connect straight away.
+    let port: number;
+    try {
+      try {
+        port = await listenTcp(server, getPortForSession(currentSession));
+      } catch (err) {
+        if ((err as NodeJS.ErrnoException).code !== 'EADDRINUSE') throw err;
+        port = await listenTcp(server, 0);
+      }
+    } catch (err) {
+      console.error('Server error:', err);
+      cleanupSocket();
+      process.exit(1);
+    }
+    fs.writeFileSync(getPortFile(), port.toString());
   } else {
-    // Unix: use Unix domain socket
+    // Unix domain socket, or a named pipe on Windows
     const socketPath = getSocketPath();
     server.listen(socketPath, () => {
-      // Daemon is ready
+      // The socket lives in the shared temp dir; only its owner may connect
+      if (!isWindows) fs.chmodSync(socketPath, 0o600);
     });
   }
 
@@ -291,6 +473,8 @@ export async function startDaemon(options?: { streamPort?: number }): Promise<vo
   process.on('SIGTERM', shutdown);
   process.on('SIGHUP', shutdown);
 
+  armIdleTimer();
+
   // Handle unexpected errors - always cleanup
   process.on('uncaughtException', (err) => {
     console.error('Uncaught exception:', err);
@@ -31,6 +31,44 @@ describe('parseCommand', () => {
       }
     });
 
+    it('should parse navigate with web storage', () => {
+      const result = parseCommand(
+        cmd({
+          id: '1',
+          action: 'navigate',
+          url: 'https://app.example.com',
+          localStorage: { token: 'abc' },
+          sessionStorage: { step: '2' },
+        })
+      );
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'navigate') {
+        expect(result.command.localStorage).toEqual({ token: 'abc' });
+        expect(result.command.sessionStorage).toEqual({ step: '2' });
+      }
+    });
+
+    it('should parse navigate with measure', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'navigate', url: 'https://example.com', measure: true })
+      );
+      expect(result.success).toBe(true);
+    });
+
+    it('should parse navigate with summary', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'navigate', url: 'https://example.com', summary: true })
+      );
+      expect(result.success).toBe(true);
+    });
+
+    it('should reject navigate with non-string storage values', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'navigate', url: 'https://x.test', localStorage: { n: 1 } })
+      );
+      expect(result.success).toBe(false);
+    });
+
     it('should reject navigate without url', () => {
       const result = parseCommand(cmd({ id: '1', action: 'navigate' }));
       expect(result.success).toBe(false);
@@ -50,6 +88,19 @@ describe('parseCommand', () => {
       const result = parseCommand(cmd({ id: '1', action: 'reload' }));
       expect(result.success).toBe(true);
     });
+
+    it('should parse goBack and goForward commands', () => {
+      expect(parseCommand(cmd({ id: '1', action: 'goBack' })).success).toBe(true);
+      expect(parseCommand(cmd({ id: '1', action: 'goForward' })).success).toBe(true);
+    });
+
+    it('should keep reload hard flag', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'reload', hard: true }));
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'reload') {
+        expect(result.command.hard).toBe(true);
+      }
+    });
   });
 
   describe('click', () => {
@@ -419,6 +470,15 @@ describe('parseCommand', () => {
       expect(result.success).toBe(true);
     });
 
+    it('should parse a11y_snapshot with and without selector', () => {
+      expect(parseCommand(cmd({ id: '1', action: 'a11y_snapshot' })).success).toBe(true);
+      const result = parseCommand(cmd({ id: '1', action: 'a11y_snapshot', selector: '#main' }));
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'a11y_snapshot') {
+        expect(result.command.selector).toBe('#main');
+      }
+    });
+
     it('should parse snapshot with interactive filter', () => {
       const result = parseCommand(cmd({ id: '1', action: 'snapshot', interactive: true }));
       expect(result.success).toBe(true);
@@ -503,6 +563,21 @@ describe('parseCommand', () => {
       const result = parseCommand(cmd({ id: '1', action: 'launch', cdpPort: 'invalid' }));
       expect(result.success).toBe(false);
     });
+
+    it('should parse launch with crash diagnostics', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'launch', keepBrowserOnCrash: true, diagnosticsDir: '/tmp/diag' })
+      );
+      expect(result.success).toBe(true);
+      if (result.success && result.command.action === 'launch') {
+        expect(result.command.diagnosticsDir).toBe('/tmp/diag');
+      }
+    });
+
+    it('should parse launch with noSandbox', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'launch', noSandbox: true }));
+      expect(result.success).toBe(true);
+    });
   });
 
   describe('mouse actions', () => {
@@ -560,6 +635,13 @@ describe('parseCommand', () => {
       const result = parseCommand(cmd({ id: '1', action: 'ischecked', selector: '#checkbox' }));
       expect(result.success).toBe(true);
     });
+
+    it('should parse count with highlight', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'count', selector: '.item', highlight: true })
+      );
+      expect(result.success).toBe(true);
+    });
   });
 
   describe('viewport and settings', () => {
@@ -568,6 +650,11 @@ describe('parseCommand', () => {
       expect(result.success).toBe(true);
     });
 
+    it('should parse setViewport', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'setViewport', width: 1280, height: 720 }));
+      expect(result.success).toBe(true);
+    });
+
     it('should parse geolocation', () => {
       const result = parseCommand(
         cmd({ id: '1', action: 'geolocation', latitude: 37.7749, longitude: -122.4194 })
@@ -579,6 +666,44 @@ describe('parseCommand', () => {
       const result = parseCommand(cmd({ id: '1', action: 'offline', offline: true }));
       expect(result.success).toBe(true);
     });
+
+    it('should parse throttle_network', () => {
+      const result = parseCommand(
+        cmd({
+          id: '1',
+          action: 'throttle_network',
+          offline: false,
+          downloadThroughput: 50000,
+          uploadThroughput: 50000,
+          latency: 2000,
+        })
+      );
+      expect(result.success).toBe(true);
+    });
+
+    it('should parse throttle_cpu', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'throttle_cpu', rate: 4 }));
+      expect(result.success).toBe(true);
+    });
+
+    it('should reject throttle_cpu rate below 1', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'throttle_cpu', rate: 0.5 }));
+      expect(result.success).toBe(false);
+    });
+
+    it('should reject throttle_network with negative latency', () => {
+      const result = parseCommand(
+        cmd({
+          id: '1',
+          action: 'throttle_network',
+          offline: false,
+          downloadThroughput: 0,
+          uploadThroughput: 0,
+          latency: -1,
+        })
+      );
+      expect(result.success).toBe(false);
+    });
   });
 
   describe('trace', () => {
@@ -610,6 +735,24 @@ describe('parseCommand', () => {
     });
   });
 
+  describe('network', () => {
+    it('should parse network_tail', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'network_tail' }));
+      expect(result.success).toBe(true);
+    });
+
+    it('should parse network_tail with filters', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'network_tail', method: 'GET,POST', status: '4xx' })
+      );
+      expect(result.success).toBe(true);
+      if (result.success) {
+        expect(result.command.method).toBe('GET,POST');
+        expect(result.command.status).toBe('4xx');
+      }
+    });
+  });
+
   describe('dialog', () => {
     it('should parse dialog accept', () => {
       const result = parseCommand(cmd({ id: '1', action: 'dialog', response: 'accept' }));
@@ -1029,6 +1172,35 @@ describe('parseCommand', () => {
     });
   });
 
+  describe('cancel', () => {
+    it('should parse cancel with a command id', () => {
+      const result = parseCommand(cmd({ id: 'cancel-7', action: 'cancel', commandId: '7' }));
+      expect(result.success).toBe(true);
+    });
+
+    it('should reject cancel without a command id', () => {
+      const result = parseCommand(cmd({ id: 'cancel-7', action: 'cancel' }));
+      expect(result.success).toBe(false);
+    });
+  });
+
+  describe('compact sessions', () => {
+    it('should keep the contextId of any command', () => {
+      const result = parseCommand(
+        cmd({ id: '1', action: 'navigate', url: 'https://example.com', contextId: 'agent1' })
+      );
+      expect(result.success).toBe(true);
+      if (result.success) {
+        expect(result.command.contextId).toBe('agent1');
+      }
+    });
+
+    it('should reject an empty contextId', () => {
+      const result = parseCommand(cmd({ id: '1', action: 'snapshot', contextId: '' }));
+      expect(result.success).toBe(false);
+    });
+  });
+
   describe('invalid commands', () => {
     it('should reject unknown action', () => {
       const result = parseCommand(cmd({ id: '1', action: 'unknown' }));
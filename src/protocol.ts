@@ -5,6 +5,10 @@ import type { Command, Response } from './types.js';
 const baseCommandSchema = z.object({
   id: z.string(),
   action: z.string(),
+  // Run in the iframe matching this selector, or the frame whose URL contains it
+  frame: z.string().min(1).optional(),
+  // Run in this compact session's own browser context (see --compact-sessions)
+  contextId: z.string().min(1).optional(),
 });
 
 // Individual action schemas
@@ -21,6 +25,7 @@ const launchSchema = baseCommandSchema.extend({
   cdpPort: z.number().positive().optional(),
   executablePath: z.string().optional(),
   extensions: z.array(z.string()).optional(),
+  args: z.array(z.string().startsWith('--')).optional(),
   headers: z.record(z.string()).optional(),
   proxy: z
     .object({
@@ -31,6 +36,9 @@ const launchSchema = baseCommandSchema.extend({
     })
     .optional(),
   provider: z.string().optional(),
+  keepBrowserOnCrash: z.boolean().optional(),
+  diagnosticsDir: z.string().min(1).optional(),
+  noSandbox: z.boolean().optional(),
 });
 
 const navigateSchema = baseCommandSchema.extend({
@@ -38,6 +46,10 @@ const navigateSchema = baseCommandSchema.extend({
   url: z.string().min(1),
   waitUntil: z.enum(['load', 'domcontentloaded', 'networkidle']).optional(),
   headers: z.record(z.string()).optional(),
+  localStorage: z.record(z.string()).optional(),
+  sessionStorage: z.record(z.string()).optional(),
+  measure: z.boolean().optional(),
+  summary: z.boolean().optional(),
 });
 
 const clickSchema = baseCommandSchema.extend({
@@ -217,6 +229,12 @@ const requestsSchema = baseCommandSchema.extend({
   clear: z.boolean().optional(),
 });
 
+const networkTailSchema = baseCommandSchema.extend({
+  action: z.literal('network_tail'),
+  method: z.string().optional(),
+  status: z.string().optional(),
+});
+
 const downloadSchema = baseCommandSchema.extend({
   action: z.literal('download'),
   selector: z.string().min(1),
@@ -242,6 +260,11 @@ const viewportSchema = baseCommandSchema.extend({
   height: z.number().positive(),
 });
 
+// Sent by `set-viewport`; same payload as viewport
+const setViewportSchema = viewportSchema.extend({
+  action: z.literal('setViewport'),
+});
+
 const userAgentSchema = baseCommandSchema.extend({
   action: z.literal('useragent'),
   userAgent: z.string().min(1),
@@ -260,8 +283,18 @@ const forwardSchema = baseCommandSchema.extend({
   action: z.literal('forward'),
 });
 
+// goBack/goForward are the names the CLI sends; back/forward stay for older clients
+const goBackSchema = baseCommandSchema.extend({
+  action: z.literal('goBack'),
+});
+
+const goForwardSchema = baseCommandSchema.extend({
+  action: z.literal('goForward'),
+});
+
 const reloadSchema = baseCommandSchema.extend({
   action: z.literal('reload'),
+  hard: z.boolean().optional(),
 });
 
 const urlSchema = baseCommandSchema.extend({
@@ -301,6 +334,7 @@ const isCheckedSchema = baseCommandSchema.extend({
 const countSchema = baseCommandSchema.extend({
   action: z.literal('count'),
   selector: z.string().min(1),
+  highlight: z.boolean().optional(),
 });
 
 const boundingBoxSchema = baseCommandSchema.extend({
@@ -480,6 +514,19 @@ const offlineSchema = baseCommandSchema.extend({
   offline: z.boolean(),
 });
 
+const throttleNetworkSchema = baseCommandSchema.extend({
+  action: z.literal('throttle_network'),
+  offline: z.boolean(),
+  downloadThroughput: z.number().nonnegative(),
+  uploadThroughput: z.number().nonnegative(),
+  latency: z.number().nonnegative(),
+});
+
+const throttleCpuSchema = baseCommandSchema.extend({
+  action: z.literal('throttle_cpu'),
+  rate: z.number().min(1),
+});
+
 const headersSchema = baseCommandSchema.extend({
   action: z.literal('headers'),
   headers: z.record(z.string()),
@@ -692,6 +739,11 @@ const snapshotSchema = baseCommandSchema.extend({
   selector: z.string().optional(),
 });
 
+const a11ySnapshotSchema = baseCommandSchema.extend({
+  action: z.literal('a11y_snapshot'),
+  selector: z.string().min(1).optional(),
+});
+
 const evaluateSchema = baseCommandSchema.extend({
   action: z.literal('evaluate'),
   script: z.string().min(1),
@@ -734,6 +786,31 @@ const closeSchema = baseCommandSchema.extend({
   action: z.literal('close'),
 });
 
+const versionSchema = baseCommandSchema.extend({
+  action: z.literal('version'),
+});
+
+const pingSchema = baseCommandSchema.extend({
+  action: z.literal('ping'),
+});
+
+const shutdownSchema = baseCommandSchema.extend({
+  action: z.literal('shutdown'),
+});
+
+const cdpUrlSchema = baseCommandSchema.extend({
+  action: z.literal('cdp_url'),
+});
+
+const sessionEnvSchema = baseCommandSchema.extend({
+  action: z.literal('session_env'),
+});
+
+const cancelSchema = baseCommandSchema.extend({
+  action: z.literal('cancel'),
+  commandId: z.string().min(1),
+});
+
 // Tab/Window schemas
 const tabNewSchema = baseCommandSchema.extend({
   action: z.literal('tab_new'),
@@ -786,6 +863,7 @@ const commandSchema = z.discriminatedUnion('action', [
   pressSchema,
   screenshotSchema,
   snapshotSchema,
+  a11ySnapshotSchema,
   evaluateSchema,
   waitSchema,
   scrollSchema,
@@ -793,6 +871,12 @@ const commandSchema = z.discriminatedUnion('action', [
   hoverSchema,
   contentSchema,
   closeSchema,
+  versionSchema,
+  pingSchema,
+  shutdownSchema,
+  cdpUrlSchema,
+  sessionEnvSchema,
+  cancelSchema,
   tabNewSchema,
   tabListSchema,
   tabSwitchSchema,
@@ -809,14 +893,18 @@ const commandSchema = z.discriminatedUnion('action', [
   routeSchema,
   unrouteSchema,
   requestsSchema,
+  networkTailSchema,
   downloadSchema,
   geolocationSchema,
   permissionsSchema,
   viewportSchema,
+  setViewportSchema,
   userAgentSchema,
   deviceSchema,
   backSchema,
   forwardSchema,
+  goBackSchema,
+  goForwardSchema,
   reloadSchema,
   urlSchema,
   titleSchema,
@@ -859,6 +947,8 @@ const commandSchema = z.discriminatedUnion('action', [
   addStyleSchema,
   emulateMediaSchema,
   offlineSchema,
+  throttleNetworkSchema,
+  throttleCpuSchema,
   headersSchema,
   pauseSchema,
   getByAltTextSchema,
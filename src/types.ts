@@ -4,6 +4,8 @@ import type { Page, Browser, BrowserContext } from 'playwright-core';
 export interface BaseCommand {
   id: string;
   action: string;
+  frame?: string;
+  contextId?: string;
 }
 
 // Action-specific command types
@@ -16,6 +18,7 @@ export interface LaunchCommand extends BaseCommand {
   executablePath?: string;
   cdpPort?: number;
   extensions?: string[];
+  args?: string[];
   proxy?: {
     server: string;
     bypass?: string;
@@ -23,6 +26,11 @@ export interface LaunchCommand extends BaseCommand {
     password?: string;
   };
   provider?: string;
+  // Write console logs, page errors and a screenshot to diagnosticsDir when a page crashes
+  keepBrowserOnCrash?: boolean;
+  diagnosticsDir?: string;
+  // Run Chromium without its sandbox; the CLI requires an explicit opt-in
+  noSandbox?: boolean;
 }
 
 export interface NavigateCommand extends BaseCommand {
@@ -30,6 +38,13 @@ export interface NavigateCommand extends BaseCommand {
   url: string;
   waitUntil?: 'load' | 'domcontentloaded' | 'networkidle';
   headers?: Record<string, string>;
+  // Web storage seeded on the URL's origin before its scripts run
+  localStorage?: Record<string, string>;
+  sessionStorage?: Record<string, string>;
+  // Report page load metrics from the Navigation and Paint Timing APIs
+  measure?: boolean;
+  // Report status, redirect chain, final URL, title and load time
+  summary?: boolean;
 }
 
 export interface ClickCommand extends BaseCommand {
@@ -216,6 +231,13 @@ export interface RequestsCommand extends BaseCommand {
   clear?: boolean;
 }
 
+// Live network tail; the CLI applies the method/status filters
+export interface NetworkTailCommand extends BaseCommand {
+  action: 'network_tail';
+  method?: string;
+  status?: string;
+}
+
 // Download handling
 export interface DownloadCommand extends BaseCommand {
   action: 'download';
@@ -240,7 +262,7 @@ export interface PermissionsCommand extends BaseCommand {
 
 // Viewport
 export interface ViewportCommand extends BaseCommand {
-  action: 'viewport';
+  action: 'viewport' | 'setViewport';
   width: number;
   height: number;
 }
@@ -266,8 +288,18 @@ export interface ForwardCommand extends BaseCommand {
   action: 'forward';
 }
 
+export interface GoBackCommand extends BaseCommand {
+  action: 'goBack';
+}
+
+export interface GoForwardCommand extends BaseCommand {
+  action: 'goForward';
+}
+
 export interface ReloadCommand extends BaseCommand {
   action: 'reload';
+  // Bypass the cache, like Shift+Reload
+  hard?: boolean;
 }
 
 // Get URL/Title
@@ -309,6 +341,8 @@ export interface IsCheckedCommand extends BaseCommand {
 export interface CountCommand extends BaseCommand {
   action: 'count';
   selector: string;
+  // Outline every match in the page (visible in headed mode)
+  highlight?: boolean;
 }
 
 // Bounding box
@@ -704,6 +738,21 @@ export interface OfflineCommand extends BaseCommand {
   offline: boolean;
 }
 
+// Emulate network conditions; throughputs in bytes/s, latency in ms
+export interface ThrottleNetworkCommand extends BaseCommand {
+  action: 'throttle_network';
+  offline: boolean;
+  downloadThroughput: number;
+  uploadThroughput: number;
+  latency: number;
+}
+
+// Slow down the CPU by a factor; 1 means no throttling
+export interface ThrottleCpuCommand extends BaseCommand {
+  action: 'throttle_cpu';
+  rate: number;
+}
+
 // Set extra HTTP headers
 export interface HeadersCommand extends BaseCommand {
   action: 'headers';
@@ -734,6 +783,12 @@ export interface SnapshotCommand extends BaseCommand {
   action: 'snapshot';
 }
 
+// Plain Playwright ARIA snapshot, without refs or filtering
+export interface A11ySnapshotCommand extends BaseCommand {
+  action: 'a11y_snapshot';
+  selector?: string;
+}
+
 export interface EvaluateCommand extends BaseCommand {
   action: 'evaluate';
   script: string;
@@ -776,6 +831,37 @@ export interface CloseCommand extends BaseCommand {
   action: 'close';
 }
 
+// Version handshake: the CLI checks it was built from the same release
+export interface VersionCommand extends BaseCommand {
+  action: 'version';
+}
+
+// Health check: answers without launching a browser
+export interface PingCommand extends BaseCommand {
+  action: 'ping';
+}
+
+// Graceful exit: close the browser and clean up, then stop the daemon
+export interface ShutdownCommand extends BaseCommand {
+  action: 'shutdown';
+}
+
+// Websocket endpoint of a CDP-connected browser; null otherwise
+export interface CdpUrlCommand extends BaseCommand {
+  action: 'cdp_url';
+}
+
+// Launch configuration of the running browser; answers without launching one
+export interface SessionEnvCommand extends BaseCommand {
+  action: 'session_env';
+}
+
+// Stop waiting on a running command; handled by the daemon itself
+export interface CancelCommand extends BaseCommand {
+  action: 'cancel';
+  commandId: string;
+}
+
 // Tab/Window commands
 export interface TabNewCommand extends BaseCommand {
   action: 'tab_new';
@@ -823,6 +909,7 @@ export type Command =
   | PressCommand
   | ScreenshotCommand
   | SnapshotCommand
+  | A11ySnapshotCommand
   | EvaluateCommand
   | WaitCommand
   | ScrollCommand
@@ -830,6 +917,12 @@ export type Command =
   | HoverCommand
   | ContentCommand
   | CloseCommand
+  | VersionCommand
+  | PingCommand
+  | ShutdownCommand
+  | CdpUrlCommand
+  | SessionEnvCommand
+  | CancelCommand
   | TabNewCommand
   | TabListCommand
   | TabSwitchCommand
@@ -846,6 +939,7 @@ export type Command =
   | RouteCommand
   | UnrouteCommand
   | RequestsCommand
+  | NetworkTailCommand
   | DownloadCommand
   | GeolocationCommand
   | PermissionsCommand
@@ -854,6 +948,8 @@ export type Command =
   | DeviceCommand
   | BackCommand
   | ForwardCommand
+  | GoBackCommand
+  | GoForwardCommand
   | ReloadCommand
   | UrlCommand
   | TitleCommand
@@ -896,6 +992,8 @@ export type Command =
   | AddStyleCommand
   | EmulateMediaCommand
   | OfflineCommand
+  | ThrottleNetworkCommand
+  | ThrottleCpuCommand
   | HeadersCommand
   | PauseCommand
   | GetByAltTextCommand
@@ -946,6 +1044,19 @@ export type Response<T = unknown> = SuccessResponse<T> | ErrorResponse;
 export interface NavigateData {
   url: string;
   title: string;
+  // Timings in ms since navigation start, transferSize in bytes, resources as a
+  // count; null when the browser has no timing entry
+  metrics?: Record<string, number | null>;
+  summary?: NavigationSummary;
+}
+
+export interface NavigationSummary {
+  status: number | null;
+  url: string;
+  // Requested URLs that redirected, in order
+  redirects: string[];
+  title: string;
+  loadTime: number | null;
 }
 
 export interface ScreenshotData {